@@ -4,7 +4,8 @@ use std::fs::create_dir_all;
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use simple_ica_controller::msg::{
-    AccountResponse, AdminResponse, ExecuteMsg, InstantiateMsg, ListAccountsResponse, QueryMsg,
+    AccountInfo, AccountResponse, AdminResponse, ChannelResponse, ClosedAccountInfo, ExecuteMsg,
+    InstantiateMsg, ListAccountsResponse, ListClosedAccountsResponse, ListHostsResponse, QueryMsg,
 };
 
 fn main() {
@@ -19,4 +20,9 @@ fn main() {
     export_schema(&schema_for!(AdminResponse), &out_dir);
     export_schema(&schema_for!(AccountResponse), &out_dir);
     export_schema(&schema_for!(ListAccountsResponse), &out_dir);
+    export_schema(&schema_for!(AccountInfo), &out_dir);
+    export_schema(&schema_for!(ListHostsResponse), &out_dir);
+    export_schema(&schema_for!(ChannelResponse), &out_dir);
+    export_schema(&schema_for!(ListClosedAccountsResponse), &out_dir);
+    export_schema(&schema_for!(ClosedAccountInfo), &out_dir);
 }
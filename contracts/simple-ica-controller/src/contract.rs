@@ -1,18 +1,27 @@
 use crate::error::ContractError;
 use cosmwasm_std::{
-    entry_point, to_binary, Addr, Api, CosmosMsg, Deps, DepsMut, Env, IbcMsg,
-    MessageInfo, Order, QueryResponse, Response, StdError, StdResult,
+    entry_point, to_binary, Addr, Api, CosmosMsg, Deps, DepsMut, Env, IbcMsg, MessageInfo, Order,
+    QueryResponse, Response, StdError, StdResult,
 };
 use cw1_whitelist::state::AdminList;
+use cw_storage_plus::Bound;
 
-use simple_ica::PacketMsg;
+use simple_ica::{
+    DispatchMode, OutflowLimit, PacketMsg, ProtocolVersion, RemoteMsg, DEFAULT_ACCOUNT_ID,
+    FEATURE_BEST_EFFORT_DISPATCH, FEATURE_CUSTOM_MSGS, FEATURE_MIGRATE_ACCOUNT,
+    FEATURE_MSG_POLICIES, FEATURE_OUTFLOW_LIMITS, FEATURE_RETURN_ADDRESS, FEATURE_SUB_ACCOUNTS,
+};
 
-use crate::ibc::PACKET_LIFETIME;
+use crate::ibc::{who_am_i_msg, PACKET_LIFETIME};
 use crate::msg::{
-    AccountInfo, AccountResponse, AdminResponse, ExecuteMsg, InstantiateMsg, ListAccountsResponse,
-    QueryMsg,
+    AccountFilter, AccountInfo, AccountResponse, AdminResponse, ChannelAccount, ChannelResponse,
+    ClosedAccountInfo, ExecuteMsg, HostInfo, InstantiateMsg, ListAccountsResponse,
+    ListClosedAccountsResponse, ListHostsResponse, QueryMsg,
+};
+use crate::state::{
+    accounts, channel_version, is_valid_alias, resolve_channel, AccountPrediction, RemoteHost,
+    ADMIN, CHANNELS, CHANNEL_ALIASES, CLOSED_ACCOUNTS, HOSTS, REPLACEMENTS,
 };
-use crate::state::{ACCOUNTS, ADMIN};
 
 #[entry_point]
 pub fn instantiate(
@@ -22,7 +31,7 @@ pub fn instantiate(
     msg: InstantiateMsg,
 ) -> StdResult<Response> {
     // we store the reflect_id for creating accounts later
-    let mut adminaccs = map_validate(deps.api, &&msg.admins)?;
+    let mut adminaccs = map_validate(deps.api, &msg.admins)?;
     // let sender = deps.api.addr_validate(&info.sender)?;
     adminaccs.insert(0, info.sender.clone());
     let admin = AdminList {
@@ -50,19 +59,154 @@ pub fn execute(
         ExecuteMsg::DeleteAdmins { deleting_admins } => {
             execute_delete_admins(deps, env, info, deleting_admins)
         }
-        ExecuteMsg::SendMsgs { channel_id, msgs } => {
-            execute_send_msgs(deps, env, info, channel_id, msgs)
-        }
+        ExecuteMsg::SendMsgs {
+            channel_id,
+            msgs,
+            mode,
+            account_id,
+        } => execute_send_msgs(
+            deps,
+            env,
+            info,
+            channel_id,
+            account_id,
+            msgs,
+            mode.unwrap_or_default(),
+        ),
+        ExecuteMsg::SendCustomMsgs {
+            channel_id,
+            msgs,
+            mode,
+            account_id,
+        } => execute_send_custom_msgs(
+            deps,
+            env,
+            info,
+            channel_id,
+            account_id,
+            msgs,
+            mode.unwrap_or_default(),
+        ),
         ExecuteMsg::CheckRemoteBalance {
             channel_id,
             callback,
-        } => execute_check_remote_balance(deps, env, info, channel_id, callback),
+            account_id,
+        } => execute_check_remote_balance(deps, env, info, channel_id, account_id, callback),
+        ExecuteMsg::CreateAccount { channel_id, label } => {
+            execute_create_account(deps, env, info, channel_id, label)
+        }
+        ExecuteMsg::MigrateAccount {
+            channel_id,
+            account_id,
+            new_code_id,
+        } => execute_migrate_account(deps, env, info, channel_id, account_id, new_code_id),
+        ExecuteMsg::SetOutflowLimits {
+            channel_id,
+            account_id,
+            limits,
+        } => execute_set_outflow_limits(deps, env, info, channel_id, account_id, limits),
+        ExecuteMsg::BlockMsgs {
+            channel_id,
+            account_id,
+            kinds,
+        } => execute_block_msgs(deps, env, info, channel_id, account_id, kinds),
+        ExecuteMsg::SetReturnAddress {
+            channel_id,
+            address,
+        } => execute_set_return_address(deps, env, info, channel_id, address),
+        ExecuteMsg::RefreshRemoteAddress {
+            channel_id,
+            account_id,
+        } => execute_refresh_remote_address(deps, env, info, channel_id, account_id),
         ExecuteMsg::SendFunds {
             reflect_channel_id,
             transfer_channel_id,
-        } => execute_send_funds(deps, env, info, reflect_channel_id, transfer_channel_id),
+            account_id,
+        } => execute_send_funds(
+            deps,
+            env,
+            info,
+            reflect_channel_id,
+            account_id,
+            transfer_channel_id,
+        ),
         ExecuteMsg::ExecuteCosmosMsg { msgs } => execute_cosmos_msgs(deps, env, info, msgs),
+        ExecuteMsg::CloseChannel { channel_id } => {
+            execute_close_channel(deps, env, info, channel_id)
+        }
+        ExecuteMsg::ReplaceChannel {
+            old_channel_id,
+            new_channel_id,
+        } => execute_replace_channel(deps, env, info, old_channel_id, new_channel_id),
+        ExecuteMsg::SetChannelAlias { channel_id, alias } => {
+            execute_set_channel_alias(deps, env, info, channel_id, alias)
+        }
+        ExecuteMsg::RegisterHost {
+            connection_id,
+            port_id,
+            chain_id,
+            prediction,
+        } => execute_register_host(
+            deps,
+            env,
+            info,
+            connection_id,
+            port_id,
+            chain_id,
+            prediction,
+        ),
+        ExecuteMsg::RemoveHost { connection_id } => {
+            execute_remove_host(deps, env, info, connection_id)
+        }
+    }
+}
+
+pub fn execute_register_host(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    connection_id: String,
+    port_id: String,
+    chain_id: String,
+    prediction: Option<AccountPrediction>,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may register hosts").into());
+    }
+
+    let host = RemoteHost {
+        port_id,
+        chain_id,
+        prediction,
+    };
+    host.validate()?;
+    HOSTS.save(deps.storage, &connection_id, &host)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_host")
+        .add_attribute("connection_id", connection_id)
+        .add_attribute("port_id", host.port_id))
+}
+
+pub fn execute_remove_host(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    connection_id: String,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may remove hosts").into());
     }
+
+    HOSTS.remove(deps.storage, &connection_id);
+
+    Ok(Response::new()
+        .add_attribute("action", "remove_host")
+        .add_attribute("connection_id", connection_id))
 }
 
 pub fn execute_cosmos_msgs(
@@ -90,7 +234,7 @@ pub fn execute_add_admins(
 ) -> Result<Response, ContractError> {
     // auth check
     let mut admin = ADMIN.load(deps.storage)?;
-    if !admin.is_admin(&info.sender.to_string()) {
+    if !admin.is_admin(&info.sender) {
         return Err(StdError::generic_err("Only admin may send messages").into());
     }
 
@@ -113,7 +257,7 @@ pub fn execute_delete_admins(
 ) -> Result<Response, ContractError> {
     // auth check
     let mut admin = ADMIN.load(deps.storage)?;
-    if !admin.is_admin(&info.sender.to_string()) {
+    if !admin.is_admin(&info.sender) {
         return Err(StdError::generic_err("Only admin may send messages").into());
     }
 
@@ -121,37 +265,147 @@ pub fn execute_delete_admins(
         .iter()
         .map(|a| deps.api.addr_validate(a))
         .collect::<StdResult<_>>()?;
-    for i in 0..admin.admins.len() {
-        if admin.admins.contains(&deleting_addrs[i]) {
-            admin.admins.remove(i);
-        }
-    }
+    admin.admins.retain(|a| !deleting_addrs.contains(a));
 
     ADMIN.save(deps.storage, &admin)?;
 
     Ok(Response::new().add_attribute("action", "delete_admins"))
 }
 
+pub fn execute_close_channel(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    channel_id: String,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may close channels").into());
+    }
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    // ensure the channel is open (not found if not)
+    CHANNELS.load(deps.storage, &channel_id)?;
+
+    // the accounts are moved to the closed ones in ibc_channel_close
+    let msg = IbcMsg::CloseChannel {
+        channel_id: channel_id.clone(),
+    };
+    Ok(Response::new()
+        .add_message(msg)
+        .add_attribute("action", "handle_close_channel")
+        .add_attribute("channel_id", channel_id))
+}
+
+pub fn execute_replace_channel(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    old_channel_id: String,
+    new_channel_id: String,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may replace channels").into());
+    }
+    let old = resolve_channel(deps.storage, &old_channel_id)?;
+    let new = resolve_channel(deps.storage, &new_channel_id)?;
+    // ensure both channels are open (not found if not)
+    let old_chain = accounts()
+        .load(deps.storage, (&old, DEFAULT_ACCOUNT_ID))?
+        .chain_id;
+    let new_chain = accounts()
+        .load(deps.storage, (&new, DEFAULT_ACCOUNT_ID))?
+        .chain_id;
+    if old == new || old_chain.is_empty() || old_chain != new_chain {
+        return Err(ContractError::InvalidReplacement { old, new });
+    }
+    REPLACEMENTS.save(deps.storage, &new, &old)?;
+
+    // the host confirms the account in its answer
+    let encoding = channel_version(deps.storage, &new)?.encoding;
+    let msg = who_am_i_msg(&env, &new, encoding, DEFAULT_ACCOUNT_ID)?;
+    Ok(Response::new()
+        .add_message(msg)
+        .add_attribute("action", "handle_replace_channel")
+        .add_attribute("channel_id", new))
+}
+
+pub fn execute_set_channel_alias(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    alias: Option<String>,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may set channel aliases").into());
+    }
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    let mut channel = CHANNELS.load(deps.storage, &channel_id)?;
+
+    if let Some(alias) = &alias {
+        if !is_valid_alias(alias) {
+            return Err(ContractError::InvalidAlias(alias.clone()));
+        }
+        if let Some(other) = CHANNEL_ALIASES.may_load(deps.storage, alias)? {
+            if other != channel_id {
+                return Err(ContractError::AliasTaken {
+                    alias: alias.clone(),
+                    channel: other,
+                });
+            }
+        }
+    }
+    if let Some(old) = &channel.alias {
+        CHANNEL_ALIASES.remove(deps.storage, old);
+    }
+    if let Some(alias) = &alias {
+        CHANNEL_ALIASES.save(deps.storage, alias, &channel_id)?;
+    }
+    channel.alias = alias;
+    CHANNELS.save(deps.storage, &channel_id, &channel)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_channel_alias")
+        .add_attribute("channel_id", channel_id))
+}
+
 pub fn execute_send_msgs(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     channel_id: String,
+    account_id: u64,
     msgs: Vec<CosmosMsg>,
+    mode: DispatchMode,
 ) -> Result<Response, ContractError> {
     // auth check
     let admin = ADMIN.load(deps.storage)?;
     if !admin.is_admin(&info.sender) {
         return Err(StdError::generic_err("Only admin may send messages").into());
     }
-    // ensure the channel exists (not found if not registered)
-    ACCOUNTS.load(deps.storage, &channel_id)?;
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    // ensure the account exists (not found if not registered)
+    accounts().load(deps.storage, (&channel_id, account_id))?;
+
+    let version = channel_version(deps.storage, &channel_id)?;
+    if !mode.is_atomic() {
+        check_feature(version, &channel_id, FEATURE_BEST_EFFORT_DISPATCH)?;
+    }
 
     // construct a packet to send
-    let packet = PacketMsg::Dispatch { msgs };
+    let packet = PacketMsg::Dispatch {
+        msgs,
+        mode,
+        account_id,
+    };
     let msg = IbcMsg::SendPacket {
+        data: version.encoding.encode(&packet)?,
         channel_id,
-        data: to_binary(&packet)?,
         timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
     };
 
@@ -161,11 +415,241 @@ pub fn execute_send_msgs(
     Ok(res)
 }
 
+pub fn execute_send_custom_msgs(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    account_id: u64,
+    msgs: Vec<RemoteMsg>,
+    mode: DispatchMode,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may send messages").into());
+    }
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    // ensure the account exists (not found if not registered)
+    accounts().load(deps.storage, (&channel_id, account_id))?;
+
+    // older hosts do not know this packet
+    let version = channel_version(deps.storage, &channel_id)?;
+    check_feature(version, &channel_id, FEATURE_CUSTOM_MSGS)?;
+    if !mode.is_atomic() {
+        check_feature(version, &channel_id, FEATURE_BEST_EFFORT_DISPATCH)?;
+    }
+
+    // construct a packet to send
+    let packet = PacketMsg::DispatchCustom {
+        msgs,
+        mode,
+        account_id,
+    };
+    let msg = IbcMsg::SendPacket {
+        data: version.encoding.encode(&packet)?,
+        channel_id,
+        timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
+    };
+
+    let res = Response::new()
+        .add_message(msg)
+        .add_attribute("action", "handle_send_custom_msgs");
+    Ok(res)
+}
+
+pub fn execute_set_return_address(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    address: Option<String>,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may send messages").into());
+    }
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    // ensure the channel exists (not found if not registered)
+    accounts().load(deps.storage, (&channel_id, DEFAULT_ACCOUNT_ID))?;
+
+    let version = channel_version(deps.storage, &channel_id)?;
+    check_feature(version, &channel_id, FEATURE_RETURN_ADDRESS)?;
+
+    // construct a packet to send
+    let packet = PacketMsg::SetReturnAddress { address };
+    let msg = IbcMsg::SendPacket {
+        data: version.encoding.encode(&packet)?,
+        channel_id,
+        timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
+    };
+
+    let res = Response::new()
+        .add_message(msg)
+        .add_attribute("action", "handle_set_return_address");
+    Ok(res)
+}
+
+pub fn execute_create_account(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    label: String,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may send messages").into());
+    }
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    // ensure the channel exists (not found if not registered)
+    accounts().load(deps.storage, (&channel_id, DEFAULT_ACCOUNT_ID))?;
+
+    let version = channel_version(deps.storage, &channel_id)?;
+    check_feature(version, &channel_id, FEATURE_SUB_ACCOUNTS)?;
+
+    // construct a packet to send
+    let packet = PacketMsg::CreateAccount { label };
+    let msg = IbcMsg::SendPacket {
+        data: version.encoding.encode(&packet)?,
+        channel_id,
+        timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
+    };
+
+    let res = Response::new()
+        .add_message(msg)
+        .add_attribute("action", "handle_create_account");
+    Ok(res)
+}
+
+pub fn execute_migrate_account(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    account_id: u64,
+    new_code_id: u64,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may send messages").into());
+    }
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    // ensure the account exists (not found if not registered)
+    accounts().load(deps.storage, (&channel_id, account_id))?;
+
+    let version = channel_version(deps.storage, &channel_id)?;
+    check_feature(version, &channel_id, FEATURE_MIGRATE_ACCOUNT)?;
+
+    // construct a packet to send
+    let packet = PacketMsg::MigrateAccount {
+        new_code_id,
+        account_id,
+    };
+    let msg = IbcMsg::SendPacket {
+        data: version.encoding.encode(&packet)?,
+        channel_id,
+        timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
+    };
+
+    let res = Response::new()
+        .add_message(msg)
+        .add_attribute("action", "handle_migrate_account");
+    Ok(res)
+}
+
+pub fn execute_set_outflow_limits(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    account_id: u64,
+    limits: Vec<OutflowLimit>,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may send messages").into());
+    }
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    // ensure the account exists (not found if not registered)
+    accounts().load(deps.storage, (&channel_id, account_id))?;
+
+    let version = channel_version(deps.storage, &channel_id)?;
+    check_feature(version, &channel_id, FEATURE_OUTFLOW_LIMITS)?;
+
+    // construct a packet to send
+    let packet = PacketMsg::SetOutflowLimits { limits, account_id };
+    let msg = IbcMsg::SendPacket {
+        data: version.encoding.encode(&packet)?,
+        channel_id,
+        timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
+    };
+
+    let res = Response::new()
+        .add_message(msg)
+        .add_attribute("action", "handle_set_outflow_limits");
+    Ok(res)
+}
+
+pub fn execute_block_msgs(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    account_id: u64,
+    kinds: Vec<String>,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may send messages").into());
+    }
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    // ensure the account exists (not found if not registered)
+    accounts().load(deps.storage, (&channel_id, account_id))?;
+
+    let version = channel_version(deps.storage, &channel_id)?;
+    check_feature(version, &channel_id, FEATURE_MSG_POLICIES)?;
+
+    // construct a packet to send
+    let packet = PacketMsg::BlockMsgs { kinds, account_id };
+    let msg = IbcMsg::SendPacket {
+        data: version.encoding.encode(&packet)?,
+        channel_id,
+        timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
+    };
+
+    let res = Response::new()
+        .add_message(msg)
+        .add_attribute("action", "handle_block_msgs");
+    Ok(res)
+}
+
+/// Fails unless the version negotiated for the channel has the given feature
+fn check_feature(
+    version: &ProtocolVersion,
+    channel_id: &str,
+    feature: &str,
+) -> Result<(), ContractError> {
+    if !version.supports(feature) {
+        return Err(ContractError::FeatureNotSupported {
+            channel: channel_id.to_string(),
+            feature: feature.to_string(),
+        });
+    }
+    Ok(())
+}
+
 pub fn execute_check_remote_balance(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     channel_id: String,
+    account_id: u64,
     callback: bool,
 ) -> Result<Response, ContractError> {
     // auth check
@@ -174,14 +658,19 @@ pub fn execute_check_remote_balance(
         return Err(StdError::generic_err("Only admin may send messages").into());
     }
 
-    // ensure the channel exists (not found if not registered)
-    ACCOUNTS.load(deps.storage, &channel_id)?;
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    // ensure the account exists (not found if not registered)
+    accounts().load(deps.storage, (&channel_id, account_id))?;
 
     // construct a packet to send
-    let packet = PacketMsg::Balances { callback };
+    let packet = PacketMsg::Balances {
+        callback,
+        account_id,
+    };
+    let encoding = channel_version(deps.storage, &channel_id)?.encoding;
     let msg = IbcMsg::SendPacket {
+        data: encoding.encode(&packet)?,
         channel_id,
-        data: to_binary(&packet)?,
         timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
     };
 
@@ -191,11 +680,40 @@ pub fn execute_check_remote_balance(
     Ok(res)
 }
 
+pub fn execute_refresh_remote_address(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    account_id: u64,
+) -> Result<Response, ContractError> {
+    // auth check
+    let admin = ADMIN.load(deps.storage)?;
+    if !admin.is_admin(&info.sender) {
+        return Err(StdError::generic_err("Only admin may send messages").into());
+    }
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    // ensure the account exists (not found if not registered)
+    let mut acct = accounts().load(deps.storage, (&channel_id, account_id))?;
+    acct.who_am_i_retries = 0;
+    acct.confirmed = false;
+    accounts().save(deps.storage, (&channel_id, account_id), &acct)?;
+
+    let encoding = channel_version(deps.storage, &channel_id)?.encoding;
+    let msg = who_am_i_msg(&env, &channel_id, encoding, account_id)?;
+
+    let res = Response::new()
+        .add_message(msg)
+        .add_attribute("action", "handle_refresh_remote_address");
+    Ok(res)
+}
+
 pub fn execute_send_funds(
     deps: DepsMut,
     env: Env,
     mut info: MessageInfo,
     reflect_channel_id: String,
+    account_id: u64,
     transfer_channel_id: String,
 ) -> Result<Response, ContractError> {
     // auth check
@@ -217,7 +735,8 @@ pub fn execute_send_funds(
     }
 
     // load remote account
-    let data = ACCOUNTS.load(deps.storage, &reflect_channel_id)?;
+    let reflect_channel_id = resolve_channel(deps.storage, &reflect_channel_id)?;
+    let data = accounts().load(deps.storage, (&reflect_channel_id, account_id))?;
     let remote_addr = match data.remote_addr {
         Some(addr) => addr,
         None => return Err(ContractError::UnregisteredChannel(reflect_channel_id)),
@@ -238,32 +757,159 @@ pub fn execute_send_funds(
 }
 
 #[entry_point]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<QueryResponse> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<QueryResponse> {
     match msg {
         QueryMsg::Admins {} => to_binary(&query_admins(deps)?),
-        QueryMsg::Account { channel_id } => to_binary(&query_account(deps, channel_id)?),
-        QueryMsg::ListAccounts {} => to_binary(&query_list_accounts(deps)?),
+        QueryMsg::Account {
+            channel_id,
+            account_id,
+        } => to_binary(&query_account(deps, channel_id, account_id)?),
+        QueryMsg::ListAccounts {
+            start_after,
+            limit,
+            filter,
+        } => to_binary(&query_list_accounts(deps, env, start_after, limit, filter)?),
+        QueryMsg::AccountByRemoteAddr { remote_addr } => {
+            to_binary(&query_account_by_remote_addr(deps, remote_addr)?)
+        }
+        QueryMsg::ListClosedAccounts { start_after, limit } => {
+            to_binary(&query_list_closed_accounts(deps, start_after, limit)?)
+        }
+        QueryMsg::ClosedAccount {
+            channel_id,
+            account_id,
+        } => to_binary(&query_closed_account(deps, channel_id, account_id)?),
+        QueryMsg::ListHosts {} => to_binary(&query_list_hosts(deps)?),
+        QueryMsg::Channel { channel_id } => to_binary(&query_channel(deps, channel_id)?),
     }
 }
 
-fn query_account(deps: Deps, channel_id: String) -> StdResult<AccountResponse> {
-    let account = ACCOUNTS.load(deps.storage, &channel_id)?;
-    Ok(account.into())
+fn query_channel(deps: Deps, channel_id: String) -> StdResult<ChannelResponse> {
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    let info = CHANNELS.load(deps.storage, &channel_id)?;
+    Ok(ChannelResponse {
+        version: info.version,
+        encoding: info.encoding,
+        metadata: info.metadata,
+        alias: info.alias,
+        connection_id: info.connection_id,
+        counterparty_port: info.counterparty_port,
+        counterparty_channel_id: info.counterparty_channel_id,
+        replaced_by: info.replaced_by,
+        replacing: REPLACEMENTS.may_load(deps.storage, &channel_id)?,
+    })
 }
 
-fn query_list_accounts(deps: Deps) -> StdResult<ListAccountsResponse> {
-    let accounts = ACCOUNTS
+fn query_list_hosts(deps: Deps) -> StdResult<ListHostsResponse> {
+    let hosts = HOSTS
         .range(deps.storage, None, None, Order::Ascending)
         .map(|r| {
-            let (channel_id, account) = r?;
-            Ok(AccountInfo::convert(channel_id, account))
+            let (connection_id, host) = r?;
+            Ok(HostInfo::convert(connection_id, host))
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(ListHostsResponse { hosts })
+}
+
+fn query_account(deps: Deps, channel_id: String, account_id: u64) -> StdResult<AccountResponse> {
+    let channel_id = resolve_channel(deps.storage, &channel_id)?;
+    let account = accounts().load(deps.storage, (&channel_id, account_id))?;
+    Ok(account.into())
+}
+
+const DEFAULT_LIST_LIMIT: u32 = 10;
+const MAX_LIST_LIMIT: u32 = 30;
+
+fn query_list_accounts(
+    deps: Deps,
+    env: Env,
+    start_after: Option<ChannelAccount>,
+    limit: Option<u32>,
+    filter: AccountFilter,
+) -> StdResult<ListAccountsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT) as usize;
+    let start = start_after
+        .as_ref()
+        .map(|key| Bound::exclusive((key.channel_id.as_str(), key.account_id)));
+    // an index narrows down the range if the filter allows it
+    let map = accounts();
+    let range = if filter.missing_remote_addr {
+        map.idx
+            .remote_addr
+            .prefix(String::new())
+            .range(deps.storage, start, None, Order::Ascending)
+    } else if let Some(chain_id) = &filter.chain_id {
+        map.idx
+            .chain_id
+            .prefix(chain_id.clone())
+            .range(deps.storage, start, None, Order::Ascending)
+    } else {
+        map.range(deps.storage, start, None, Order::Ascending)
+    };
+    let accounts = range
+        .filter(|r| match r {
+            Ok((_, account)) => filter.matches(account, env.block.time),
+            Err(_) => true,
+        })
+        .take(limit)
+        .map(|r| {
+            let ((channel_id, account_id), account) = r?;
+            let channel = CHANNELS.may_load(deps.storage, &channel_id)?;
+            Ok(AccountInfo::convert(
+                channel_id, account_id, account, channel,
+            ))
         })
         .collect::<StdResult<_>>()?;
     Ok(ListAccountsResponse { accounts })
 }
 
+/// Several channels to the same host share an account, this returns the first one
+fn query_account_by_remote_addr(deps: Deps, remote_addr: String) -> StdResult<AccountInfo> {
+    let ((channel_id, account_id), account) = accounts()
+        .idx
+        .remote_addr
+        .prefix(remote_addr.clone())
+        .range(deps.storage, None, None, Order::Ascending)
+        .next()
+        .transpose()?
+        .ok_or_else(|| StdError::not_found(format!("account {}", remote_addr)))?;
+    let channel = CHANNELS.may_load(deps.storage, &channel_id)?;
+    Ok(AccountInfo::convert(
+        channel_id, account_id, account, channel,
+    ))
+}
+
+fn query_list_closed_accounts(
+    deps: Deps,
+    start_after: Option<ChannelAccount>,
+    limit: Option<u32>,
+) -> StdResult<ListClosedAccountsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT) as usize;
+    let start = start_after
+        .as_ref()
+        .map(|key| Bound::exclusive((key.channel_id.as_str(), key.account_id)));
+    let accounts = CLOSED_ACCOUNTS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|r| {
+            let ((channel_id, account_id), closed) = r?;
+            Ok(ClosedAccountInfo::convert(channel_id, account_id, closed))
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(ListClosedAccountsResponse { accounts })
+}
+
+fn query_closed_account(
+    deps: Deps,
+    channel_id: String,
+    account_id: u64,
+) -> StdResult<ClosedAccountInfo> {
+    let closed = CLOSED_ACCOUNTS.load(deps.storage, (&channel_id, account_id))?;
+    Ok(ClosedAccountInfo::convert(channel_id, account_id, closed))
+}
+
 fn query_admins(deps: Deps) -> StdResult<AdminResponse> {
-    let AdminList { admins,mutable: _} = ADMIN.load(deps.storage)?;
+    let AdminList { admins, mutable: _ } = ADMIN.load(deps.storage)?;
     Ok(AdminResponse {
         admins: admins.into_iter().map(|a| a.into()).collect(),
     })
@@ -274,7 +920,7 @@ mod tests {
     use super::*;
     use cosmwasm_std::{
         testing::{mock_dependencies, mock_dependencies_with_balance, mock_env, mock_info},
-        BankMsg, Coin, SubMsg, Uint128, WasmMsg,
+        BankMsg, Coin, SubMsg, Uint128,
     };
 
     const CREATOR: &str = "creator";
@@ -316,7 +962,7 @@ mod tests {
             amount: Uint128::new(123456789),
             denom: "uatom".into(),
         };
-        let mut deps = mock_dependencies_with_balance(&[funds.clone()]);
+        let mut deps = mock_dependencies_with_balance(std::slice::from_ref(&funds));
 
         let msg = InstantiateMsg {
             admins: vec![SUB_ADMIN.to_string()],
@@ -331,19 +977,13 @@ mod tests {
             amount: vec![funds.clone()],
         }
         .into()];
-        info = mock_info(CREATOR, &[funds.clone()]);
+        info = mock_info(CREATOR, std::slice::from_ref(&funds));
 
+        // the messages are dispatched directly from this contract
         let res = execute_cosmos_msgs(deps.as_mut(), mock_env(), info, cosmos_msg.clone());
-        let wl_msg = cw1_whitelist::msg::ExecuteMsg::Execute { msgs: cosmos_msg };
-        let expt_msg: Vec<WasmMsg> = vec![WasmMsg::Execute {
-            contract_addr: "cosmos2contract".into(),
-            msg: to_binary(&wl_msg).unwrap(),
-            funds: vec![],
-        }]
-        .into();
         assert_eq!(
             res.unwrap().messages,
-            expt_msg.into_iter().map(SubMsg::new).collect::<Vec<_>>(),
+            cosmos_msg.into_iter().map(SubMsg::new).collect::<Vec<_>>(),
         );
     }
 }
@@ -28,6 +28,24 @@ pub enum ContractError {
     #[error("Invalid remote address for this channel")]
     InvalidRemoteAddr { addr: String, channel: String },
 
+    #[error("No trusted host registered for connection {0}")]
+    UnknownHost(String),
+
+    #[error("Counterparty port {port} is not the host registered for connection {connection}")]
+    UntrustedHost { connection: String, port: String },
+
+    #[error("Invalid channel alias {0}")]
+    InvalidAlias(String),
+
+    #[error("Alias {alias} is already used by {channel}")]
+    AliasTaken { alias: String, channel: String },
+
+    #[error("Channel {new} cannot replace {old}, it must be another channel to the same chain")]
+    InvalidReplacement { old: String, new: String },
+
+    #[error("Channel {channel} does not support {feature}")]
+    FeatureNotSupported { channel: String, feature: String },
+
     #[error("Unaothorized")]
     Whitelist(#[from] whitelist_error),
 }
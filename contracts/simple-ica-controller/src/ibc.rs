@@ -1,35 +1,71 @@
 use cosmwasm_std::{
-    entry_point, from_slice, to_binary, BankMsg, CosmosMsg, DepsMut, Env, Ibc3ChannelOpenResponse,
+    entry_point, from_slice, BankMsg, CosmosMsg, DepsMut, Env, Ibc3ChannelOpenResponse,
     IbcBasicResponse, IbcChannelCloseMsg, IbcChannelConnectMsg, IbcChannelOpenMsg, IbcMsg,
     IbcPacketAckMsg, IbcPacketReceiveMsg, IbcPacketTimeoutMsg, IbcReceiveResponse, MessageInfo,
-    StdResult,
+    Order, StdResult, Storage,
 };
 
-use simple_ica::{check_order, check_version, BalancesResponse, PacketMsg, StdAck, WhoAmIResponse};
+use simple_ica::{
+    channel_open_version, check_order, connected_metadata, connected_version, BalancesResponse,
+    CreateAccountResponse, DispatchMode, DispatchResponse, Encoding, MigrateAccountResponse,
+    MsgOutcome, PacketMsg, RemoteError, StdAck, WhoAmIResponse, DEFAULT_ACCOUNT_ID,
+};
 
 use crate::contract::execute_cosmos_msgs;
 use crate::error::ContractError;
-use crate::state::{AccountData, ACCOUNTS};
+use crate::state::{
+    accounts, channel_version, is_valid_alias, AccountData, ChannelInfo, ClosedAccount, CHANNELS,
+    CHANNEL_ALIASES, CLOSED_ACCOUNTS, HOSTS, REPLACEMENTS,
+};
 
 // TODO: make configurable?
 /// packets live one hour
 pub const PACKET_LIFETIME: u64 = 60 * 60;
 
+/// how often a timed out `WhoAmI` is resent before we wait for a refresh
+pub const MAX_WHO_AM_I_RETRIES: u32 = 3;
+
+/// Asks the host for the address of the account
+pub fn who_am_i_msg(
+    env: &Env,
+    channel_id: &str,
+    encoding: Encoding,
+    account_id: u64,
+) -> StdResult<IbcMsg> {
+    let packet = PacketMsg::WhoAmI { account_id };
+    Ok(IbcMsg::SendPacket {
+        channel_id: channel_id.to_string(),
+        data: encoding.encode(&packet)?,
+        timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
+    })
+}
+
 #[entry_point]
-/// enforces ordering and versioing constraints
+/// enforces ordering and negotiates the protocol version
 pub fn ibc_channel_open(
-    _deps: DepsMut,
+    deps: DepsMut,
     _env: Env,
     msg: IbcChannelOpenMsg,
 ) -> Result<Option<Ibc3ChannelOpenResponse>, ContractError> {
     let channel = msg.channel();
     check_order(&channel.order)?;
-    check_version(&channel.version)?;
-    if let Some(counter_version) = msg.counterparty_version() {
-        check_version(counter_version)?;
+
+    // only talk to the host we trust on this connection
+    let connection = &channel.connection_id;
+    let host = HOSTS
+        .may_load(deps.storage, connection)?
+        .ok_or_else(|| ContractError::UnknownHost(connection.clone()))?;
+    let port = &channel.counterparty_endpoint.port_id;
+    if &host.port_id != port {
+        return Err(ContractError::UntrustedHost {
+            connection: connection.clone(),
+            port: port.clone(),
+        });
     }
 
-    Ok(None)
+    // we propose all versions we know on init and pick the best one on try
+    let version = channel_open_version(&msg)?;
+    Ok(Some(Ibc3ChannelOpenResponse { version }))
 }
 
 #[entry_point]
@@ -38,40 +74,93 @@ pub fn ibc_channel_connect(
     deps: DepsMut,
     env: Env,
     msg: IbcChannelConnectMsg,
-) -> StdResult<IbcBasicResponse> {
+) -> Result<IbcBasicResponse, ContractError> {
+    let version = connected_version(&msg)?;
     let channel = msg.channel();
     let channel_id = &channel.endpoint.channel_id;
 
-    // create an account holder the channel exists (not found if not registered)
-    let data = AccountData::default();
-    ACCOUNTS.save(deps.storage, channel_id, &data)?;
+    // create an account holder the channel exists (not found if not registered),
+    // the address is already known if the host uses a predictable one
+    let (remote_addr, chain_id) = match HOSTS.may_load(deps.storage, &channel.connection_id)? {
+        Some(host) => (
+            host.predict_account(&channel.endpoint.port_id, DEFAULT_ACCOUNT_ID)?,
+            host.chain_id,
+        ),
+        None => (None, String::new()),
+    };
 
-    // construct a packet to send
-    let packet = PacketMsg::WhoAmI {};
-    let msg = IbcMsg::SendPacket {
-        channel_id: channel_id.clone(),
-        data: to_binary(&packet)?,
-        timeout: env.block.time.plus_seconds(PACKET_LIFETIME).into(),
+    // the first channel to a chain goes by its chain id, the admin can rename it
+    let alias = Some(chain_id.clone()).filter(|alias| {
+        is_valid_alias(alias) && !CHANNEL_ALIASES.has(deps.storage, alias.as_str())
+    });
+    if let Some(alias) = &alias {
+        CHANNEL_ALIASES.save(deps.storage, alias, channel_id)?;
+    }
+
+    // remember which protocol version this channel speaks, the account
+    // options the host agreed to and where it leads
+    let info = ChannelInfo {
+        version: version.version.to_string(),
+        encoding: version.encoding,
+        metadata: connected_metadata(&msg)?,
+        connection_id: channel.connection_id.clone(),
+        counterparty_port: channel.counterparty_endpoint.port_id.clone(),
+        counterparty_channel_id: channel.counterparty_endpoint.channel_id.clone(),
+        alias,
+        replaced_by: None,
     };
+    CHANNELS.save(deps.storage, channel_id, &info)?;
+    let data = AccountData {
+        remote_addr: remote_addr.clone(),
+        chain_id,
+        ..AccountData::default()
+    };
+    accounts().save(deps.storage, (channel_id, DEFAULT_ACCOUNT_ID), &data)?;
 
-    Ok(IbcBasicResponse::new()
+    let msg = who_am_i_msg(&env, channel_id, version.encoding, DEFAULT_ACCOUNT_ID)?;
+
+    let res = IbcBasicResponse::new()
         .add_message(msg)
         .add_attribute("action", "ibc_connect")
-        .add_attribute("channel_id", channel_id))
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("version", version.version);
+    match remote_addr {
+        Some(addr) => Ok(res.add_attribute("predicted_account", addr)),
+        None => Ok(res),
+    }
 }
 
 #[entry_point]
-/// On closed channel, simply delete the account from our local store
+/// On closed channel, move the accounts to `CLOSED_ACCOUNTS`, so the funds on the
+/// host chain can still be found
 pub fn ibc_channel_close(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     msg: IbcChannelCloseMsg,
 ) -> StdResult<IbcBasicResponse> {
     let channel = msg.channel();
 
     // remove the channel
     let channel_id = &channel.endpoint.channel_id;
-    ACCOUNTS.remove(deps.storage, channel_id);
+    let info = CHANNELS.may_load(deps.storage, channel_id)?;
+    let closed: Vec<(u64, AccountData)> = accounts()
+        .prefix(channel_id)
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for (account_id, account) in closed {
+        accounts().remove(deps.storage, (channel_id, account_id))?;
+        let tombstone = ClosedAccount {
+            closed_at: env.block.time,
+            account,
+            channel: info.clone(),
+        };
+        CLOSED_ACCOUNTS.save(deps.storage, (channel_id, account_id), &tombstone)?;
+    }
+    if let Some(alias) = info.and_then(|info| info.alias) {
+        CHANNEL_ALIASES.remove(deps.storage, &alias);
+    }
+    CHANNELS.remove(deps.storage, channel_id);
+    REPLACEMENTS.remove(deps.storage, channel_id);
 
     Ok(IbcBasicResponse::new()
         .add_attribute("action", "ibc_close")
@@ -99,25 +188,151 @@ pub fn ibc_packet_ack(
     // which local channel was this packet send from
     let caller = msg.original_packet.src.channel_id;
     // we need to parse the ack based on our request
-    let packet: PacketMsg = from_slice(&msg.original_packet.data)?;
-    let res: StdAck = from_slice(&msg.acknowledgement.data)?;
+    let encoding = channel_version(deps.storage, &caller)?.encoding;
+    let packet: PacketMsg = encoding.decode(&msg.original_packet.data)?;
+    let res = decode_ack(encoding, &msg.acknowledgement.data);
+    // the accounts of a replaced channel moved to its successor
+    let caller = match CHANNELS.may_load(deps.storage, &caller)? {
+        Some(ChannelInfo {
+            replaced_by: Some(successor),
+            ..
+        }) => successor,
+        _ => caller,
+    };
 
     match packet {
-        PacketMsg::Dispatch { .. } => acknowledge_dispatch(deps, caller, res),
-        PacketMsg::WhoAmI {} => acknowledge_who_am_i(deps, caller, res),
-        PacketMsg::Balances { .. } => acknowledge_balances(deps, env, caller, res),
+        PacketMsg::Dispatch { mode, .. } | PacketMsg::DispatchCustom { mode, .. } => {
+            acknowledge_dispatch(deps, caller, encoding, mode, res)
+        }
+        PacketMsg::WhoAmI { account_id } => {
+            acknowledge_who_am_i(deps, caller, account_id, encoding, res)
+        }
+        PacketMsg::Balances { account_id, .. } => {
+            acknowledge_balances(deps, env, caller, account_id, encoding, res)
+        }
+        PacketMsg::SetReturnAddress { .. } => Ok(acknowledge_set_return_address(res)),
+        PacketMsg::CreateAccount { label } => {
+            acknowledge_create_account(deps, caller, label, encoding, res)
+        }
+        PacketMsg::MigrateAccount { .. } => acknowledge_migrate_account(encoding, res),
+        PacketMsg::SetOutflowLimits { account_id, .. } => {
+            Ok(acknowledge_set_outflow_limits(account_id, res))
+        }
+        PacketMsg::BlockMsgs { account_id, .. } => Ok(acknowledge_block_msgs(account_id, res)),
+    }
+}
+
+// receive PacketMsg::MigrateAccount response, the host keeps the status
+fn acknowledge_migrate_account(
+    encoding: Encoding,
+    ack: StdAck,
+) -> Result<IbcBasicResponse, ContractError> {
+    let res = IbcBasicResponse::new().add_attribute("action", "acknowledge_migrate_account");
+    let MigrateAccountResponse {
+        account_id,
+        code_id,
+    } = match ack.into_result() {
+        Ok(data) => encoding.decode(&data)?,
+        Err(err) => return Ok(with_remote_error(res, err)),
+    };
+    Ok(res
+        .add_attribute("account_id", account_id.to_string())
+        .add_attribute("code_id", code_id.to_string()))
+}
+
+// receive PacketMsg::CreateAccount response
+// store the new account along with its address
+fn acknowledge_create_account(
+    deps: DepsMut,
+    caller: String,
+    label: String,
+    encoding: Encoding,
+    ack: StdAck,
+) -> Result<IbcBasicResponse, ContractError> {
+    let res = IbcBasicResponse::new().add_attribute("action", "acknowledge_create_account");
+    let CreateAccountResponse {
+        account_id,
+        account,
+    } = match ack.into_result() {
+        Ok(data) => encoding.decode(&data)?,
+        Err(err) => return Ok(with_remote_error(res, err)),
+    };
+
+    // sub-accounts live on the same chain as the default one
+    let chain_id = accounts()
+        .load(deps.storage, (&caller, DEFAULT_ACCOUNT_ID))?
+        .chain_id;
+    let data = AccountData {
+        // the host leaves this empty if it did not get the address in time
+        remote_addr: Some(account).filter(|a| !a.is_empty()),
+        label: Some(label),
+        chain_id,
+        ..AccountData::default()
+    };
+    accounts().save(deps.storage, (&caller, account_id), &data)?;
+
+    Ok(res.add_attribute("account_id", account_id.to_string()))
+}
+
+// receive PacketMsg::SetReturnAddress response, there is nothing to store
+fn acknowledge_set_return_address(ack: StdAck) -> IbcBasicResponse {
+    let res = IbcBasicResponse::new().add_attribute("action", "acknowledge_set_return_address");
+    match ack.into_result() {
+        Ok(_) => res,
+        Err(err) => with_remote_error(res, err),
+    }
+}
+
+// receive PacketMsg::SetOutflowLimits response, the host keeps the limits
+fn acknowledge_set_outflow_limits(account_id: u64, ack: StdAck) -> IbcBasicResponse {
+    let res = IbcBasicResponse::new()
+        .add_attribute("action", "acknowledge_set_outflow_limits")
+        .add_attribute("account_id", account_id.to_string());
+    match ack.into_result() {
+        Ok(_) => res,
+        Err(err) => with_remote_error(res, err),
+    }
+}
+
+// receive PacketMsg::BlockMsgs response, the host refuses those kinds from now on
+fn acknowledge_block_msgs(account_id: u64, ack: StdAck) -> IbcBasicResponse {
+    let res = IbcBasicResponse::new()
+        .add_attribute("action", "acknowledge_block_msgs")
+        .add_attribute("account_id", account_id.to_string());
+    match ack.into_result() {
+        Ok(_) => res,
+        Err(err) => with_remote_error(res, err),
     }
 }
 
 // receive PacketMsg::Dispatch response
-#[allow(clippy::unnecessary_wraps)]
 fn acknowledge_dispatch(
     _deps: DepsMut,
     _caller: String,
-    _ack: StdAck,
+    encoding: Encoding,
+    mode: DispatchMode,
+    ack: StdAck,
 ) -> Result<IbcBasicResponse, ContractError> {
-    // TODO: actually handle success/error?
-    Ok(IbcBasicResponse::new().add_attribute("action", "acknowledge_dispatch"))
+    let res = IbcBasicResponse::new().add_attribute("action", "acknowledge_dispatch");
+    let data = match ack.into_result() {
+        Ok(data) if !mode.is_atomic() => data,
+        Ok(_) => return Ok(res),
+        Err(err) => return Ok(with_remote_error(res, err)),
+    };
+
+    // report which messages of a best effort dispatch went through
+    let DispatchResponse { outcomes, .. } = encoding.decode(&data)?;
+    let succeeded = outcomes.iter().filter(|o| o.is_success()).count();
+    let errors = outcomes
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, o)| match o {
+            MsgOutcome::Error(err) => Some((format!("error_{}", i), err)),
+            MsgOutcome::Success(_) => None,
+        });
+    Ok(res
+        .add_attribute("succeeded", succeeded.to_string())
+        .add_attributes(errors))
 }
 
 // receive PacketMsg::WhoAmI response
@@ -125,32 +340,92 @@ fn acknowledge_dispatch(
 fn acknowledge_who_am_i(
     deps: DepsMut,
     caller: String,
+    account_id: u64,
+    encoding: Encoding,
     ack: StdAck,
 ) -> Result<IbcBasicResponse, ContractError> {
-    // ignore errors (but mention in log)
-    let WhoAmIResponse { account } = match ack {
-        StdAck::Result(res) => from_slice(&res)?,
-        StdAck::Error(e) => {
-            return Ok(IbcBasicResponse::new()
-                .add_attribute("action", "acknowledge_who_am_i")
-                .add_attribute("error", e))
+    let res = IbcBasicResponse::new().add_attribute("action", "acknowledge_who_am_i");
+    // keep errors on the account until a refresh works
+    let WhoAmIResponse { account } = match ack.into_result() {
+        Ok(res) => encoding.decode(&res)?,
+        Err(err) => {
+            let key = (caller.as_str(), account_id);
+            if let Some(mut acct) = accounts().may_load(deps.storage, key)? {
+                acct.remote_addr_error = Some(err.to_string());
+                accounts().save(deps.storage, key, &acct)?;
+            }
+            return Ok(with_remote_error(res, err));
         }
     };
 
-    ACCOUNTS.update(deps.storage, &caller, |acct| {
-        match acct {
-            Some(mut acct) => {
-                // set the account the first time
-                if acct.remote_addr.is_none() {
-                    acct.remote_addr = Some(account);
-                }
-                Ok(acct)
-            }
-            None => Err(ContractError::UnregisteredChannel(caller.clone())),
+    let mut acct = accounts()
+        .may_load(deps.storage, (&caller, account_id))?
+        .ok_or_else(|| ContractError::UnregisteredChannel(caller.clone()))?;
+    acct.remote_addr_error = None;
+    acct.who_am_i_retries = 0;
+    let res = match acct.confirm(&account) {
+        Some(predicted) => res
+            .add_attribute("predicted_account", predicted)
+            .add_attribute("account", &account),
+        None => res,
+    };
+    accounts().save(deps.storage, (&caller, account_id), &acct)?;
+
+    if account_id == DEFAULT_ACCOUNT_ID {
+        return Ok(complete_replacement(deps.storage, &caller, &account, res)?);
+    }
+    Ok(res)
+}
+
+// the channel takes over from the one it replaces if the host confirmed the same
+// account, otherwise the replacement is dropped
+fn complete_replacement(
+    storage: &mut dyn Storage,
+    channel_id: &str,
+    account: &str,
+    res: IbcBasicResponse,
+) -> StdResult<IbcBasicResponse> {
+    let old = match REPLACEMENTS.may_load(storage, channel_id)? {
+        Some(old) => old,
+        None => return Ok(res),
+    };
+    REPLACEMENTS.remove(storage, channel_id);
+    let old_addr = accounts()
+        .may_load(storage, (&old, DEFAULT_ACCOUNT_ID))?
+        .and_then(|acct| acct.remote_addr);
+    let mut old_info = match CHANNELS.may_load(storage, &old)? {
+        Some(info) if old_addr.as_deref() == Some(account) => info,
+        _ => return Ok(res.add_attribute("replacement_failed", old)),
+    };
+
+    // move the accounts with their labels and cached balances
+    let old_accounts: Vec<(u64, AccountData)> = accounts()
+        .prefix(&old)
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for (account_id, data) in old_accounts {
+        let data = AccountData {
+            remote_addr_error: None,
+            who_am_i_retries: 0,
+            ..data
+        };
+        accounts().remove(storage, (&old, account_id))?;
+        accounts().save(storage, (channel_id, account_id), &data)?;
+    }
+
+    // and move the alias
+    let mut info = CHANNELS.load(storage, channel_id)?;
+    if let Some(alias) = old_info.alias.take() {
+        if let Some(own) = info.alias.replace(alias.clone()) {
+            CHANNEL_ALIASES.remove(storage, &own);
         }
-    })?;
+        CHANNEL_ALIASES.save(storage, &alias, &channel_id.to_string())?;
+    }
+    old_info.replaced_by = Some(channel_id.to_string());
+    CHANNELS.save(storage, &old, &old_info)?;
+    CHANNELS.save(storage, channel_id, &info)?;
 
-    Ok(IbcBasicResponse::new().add_attribute("action", "acknowledge_who_am_i"))
+    Ok(res.add_attribute("replaced_channel", old))
 }
 
 // receive PacketMsg::Balances response
@@ -158,6 +433,8 @@ fn acknowledge_balances(
     deps: DepsMut,
     env: Env,
     caller: String,
+    account_id: u64,
+    encoding: Encoding,
     ack: StdAck,
 ) -> Result<IbcBasicResponse, ContractError> {
     // ignore errors (but mention in log)
@@ -165,30 +442,34 @@ fn acknowledge_balances(
         account,
         balances,
         execute_callback,
-    } = match ack {
-        StdAck::Result(res) => from_slice(&res)?,
-        StdAck::Error(e) => {
-            return Ok(IbcBasicResponse::new()
-                .add_attribute("action", "acknowledge_balances")
-                .add_attribute("error", e))
+    } = match ack.into_result() {
+        Ok(res) => encoding.decode(&res)?,
+        Err(err) => {
+            let res = IbcBasicResponse::new().add_attribute("action", "acknowledge_balances");
+            return Ok(with_remote_error(res, err));
         }
     };
 
-    ACCOUNTS.update(deps.storage, &caller, |acct| match acct {
-        Some(acct) => {
-            if let Some(old) = acct.remote_addr {
-                if old != account {
-                    return Err(ContractError::RemoteAccountChanged { old, addr: account });
-                }
-            }
-            Ok(AccountData {
-                last_update_time: env.block.time,
-                remote_addr: Some(account),
-                remote_balance: balances.clone(),
-            })
+    let mut acct = accounts()
+        .may_load(deps.storage, (&caller, account_id))?
+        .ok_or_else(|| ContractError::UnregisteredChannel(caller.clone()))?;
+    // only a confirmed address must not change, a prediction may be wrong
+    match acct.remote_addr.clone() {
+        Some(old) if acct.confirmed && old != account => {
+            return Err(ContractError::RemoteAccountChanged { old, addr: account });
         }
-        None => Err(ContractError::UnregisteredChannel(caller.clone())),
-    })?;
+        _ => {}
+    }
+    let res = IbcBasicResponse::new().add_attribute("action", "acknowledge_balances");
+    let res = match acct.confirm(&account) {
+        Some(predicted) => res
+            .add_attribute("predicted_account", predicted)
+            .add_attribute("account", &account),
+        None => res,
+    };
+    acct.last_update_time = env.block.time;
+    acct.remote_balance = balances.clone();
+    accounts().save(deps.storage, (&caller, account_id), &acct)?;
 
     // simple callback fn
     if execute_callback {
@@ -204,34 +485,102 @@ fn acknowledge_balances(
         let _res = execute_cosmos_msgs(deps, env, info, msgs)?;
     }
 
-    Ok(IbcBasicResponse::new().add_attribute("action", "acknowledge_balances"))
+    Ok(res)
+}
+
+// wasmd writes its own error acks (e.g. when the host fails or runs out of gas)
+// as json, whatever the channel encoding. An ack we cannot read at all is kept
+// as an error, so the packet is still acknowledged.
+fn decode_ack(encoding: Encoding, data: &[u8]) -> StdAck {
+    encoding
+        .decode(data)
+        .or_else(|_| from_slice(data))
+        .unwrap_or_else(|err| StdAck::Error(format!("Unreadable ack: {}", err)))
+}
+
+/// Logs an error ack of the host, along with its code if it sent one
+fn with_remote_error(res: IbcBasicResponse, err: RemoteError) -> IbcBasicResponse {
+    let res = res.add_attribute("error", err.to_string());
+    match err.code() {
+        Some(code) => res.add_attribute("error_code", code.to_string()),
+        None => res,
+    }
 }
 
 #[entry_point]
-/// we just ignore these now. shall we store some info?
+/// resends a `WhoAmI` until the host answered (up to `MAX_WHO_AM_I_RETRIES`),
+/// other timeouts are ignored
 pub fn ibc_packet_timeout(
-    _deps: DepsMut,
-    _env: Env,
-    _msg: IbcPacketTimeoutMsg,
+    deps: DepsMut,
+    env: Env,
+    msg: IbcPacketTimeoutMsg,
 ) -> StdResult<IbcBasicResponse> {
-    Ok(IbcBasicResponse::new().add_attribute("action", "ibc_packet_timeout"))
+    let res = IbcBasicResponse::new().add_attribute("action", "ibc_packet_timeout");
+    let packet = msg.packet;
+    let channel_id = packet.src.channel_id;
+    // the channel may be closed by now
+    let encoding = match CHANNELS.may_load(deps.storage, &channel_id)? {
+        Some(info) => info.encoding,
+        None => return Ok(res),
+    };
+    let account_id = match encoding.decode(&packet.data)? {
+        PacketMsg::WhoAmI { account_id } => account_id,
+        _ => return Ok(res),
+    };
+    let key = (channel_id.as_str(), account_id);
+    let mut acct = match accounts().may_load(deps.storage, key)? {
+        Some(acct) => acct,
+        None => return Ok(res),
+    };
+
+    acct.remote_addr_error = Some("WhoAmI packet timed out".to_string());
+    let retry = !acct.confirmed && acct.who_am_i_retries < MAX_WHO_AM_I_RETRIES;
+    if retry {
+        acct.who_am_i_retries += 1;
+    }
+    accounts().save(deps.storage, key, &acct)?;
+    let res = res.add_attribute("account_id", account_id.to_string());
+    if !retry {
+        return Ok(res);
+    }
+    let msg = who_am_i_msg(&env, &channel_id, encoding, account_id)?;
+    Ok(res
+        .add_message(msg)
+        .add_attribute("who_am_i_retry", acct.who_am_i_retries.to_string()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::contract::{execute, instantiate, query};
-    use crate::msg::{AccountResponse, ExecuteMsg, InstantiateMsg, QueryMsg};
+    use crate::msg::{
+        AccountFilter, AccountInfo, AccountResponse, ChannelAccount, ChannelResponse,
+        ClosedAccountInfo, ExecuteMsg, InstantiateMsg, ListAccountsResponse,
+        ListClosedAccountsResponse, ListHostsResponse, QueryMsg,
+    };
+    use crate::state::AccountPrediction;
 
+    use bech32::{ToBase32, Variant};
     use cosmwasm_std::testing::{
-        mock_dependencies, mock_env, mock_ibc_channel_connect_ack, mock_ibc_channel_open_init,
-        mock_ibc_channel_open_try, mock_ibc_packet_ack, mock_info, MockApi, MockQuerier,
-        MockStorage,
+        mock_dependencies, mock_env, mock_ibc_channel, mock_ibc_channel_close_init,
+        mock_ibc_channel_connect_ack, mock_ibc_channel_open_init, mock_ibc_channel_open_try,
+        mock_ibc_packet_ack, mock_ibc_packet_timeout, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{
+        coin, coins, from_slice, instantiate2_address, BankMsg, Binary, CanonicalAddr, CosmosMsg,
+        Deps, HexBinary, IbcAcknowledgement, OwnedDeps,
+    };
+    use simple_ica::{
+        account_salt, lookup_version, propose_versions, ErrorCode, OutflowLimit, RemoteMsg,
+        APP_ORDER, BAD_APP_ORDER, FEATURE_BEST_EFFORT_DISPATCH, FEATURE_CUSTOM_MSGS,
+        FEATURE_MIGRATE_ACCOUNT, FEATURE_MSG_POLICIES, FEATURE_OUTFLOW_LIMITS,
+        FEATURE_RETURN_ADDRESS, IBC_APP_VERSION, IBC_APP_VERSION_PROTO, IBC_APP_VERSION_V3,
     };
-    use cosmwasm_std::{coin, coins, BankMsg, CosmosMsg, IbcAcknowledgement, OwnedDeps};
-    use simple_ica::{APP_ORDER, BAD_APP_ORDER, IBC_APP_VERSION};
 
     const CREATOR: &str = "creator";
+    // connection and counterparty port used by the ibc mocks
+    const CONNECTION_ID: &str = "connection-2";
+    const HOST_PORT: &str = "their_port";
 
     fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
         let mut deps = mock_dependencies();
@@ -242,6 +591,15 @@ mod tests {
         let info = mock_info(CREATOR, &[]);
         let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
         assert_eq!(0, res.messages.len());
+
+        // trust the host the mocks connect to
+        let msg = ExecuteMsg::RegisterHost {
+            connection_id: CONNECTION_ID.into(),
+            port_id: HOST_PORT.into(),
+            chain_id: "remote-1".into(),
+            prediction: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
         deps
     }
 
@@ -268,8 +626,16 @@ mod tests {
         };
     }
 
+    fn list_accounts_msg(filter: Option<AccountFilter>) -> QueryMsg {
+        QueryMsg::ListAccounts {
+            start_after: None,
+            limit: None,
+            filter: filter.unwrap_or_default(),
+        }
+    }
+
     fn who_am_i_response(deps: DepsMut, channel_id: &str, account: impl Into<String>) {
-        let packet = PacketMsg::WhoAmI {};
+        let packet = PacketMsg::WhoAmI { account_id: 0 };
         let res = StdAck::success(WhoAmIResponse {
             account: account.into(),
         });
@@ -293,6 +659,213 @@ mod tests {
         ibc_channel_open(deps.as_mut(), mock_env(), valid_handshake).unwrap();
     }
 
+    #[test]
+    fn enforce_trusted_host_in_handshake() {
+        let mut deps = setup();
+
+        // the host is registered for another port
+        let msg = ExecuteMsg::RegisterHost {
+            connection_id: CONNECTION_ID.into(),
+            port_id: "wasm.host".into(),
+            chain_id: "remote-1".into(),
+            prediction: None,
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+        let open = mock_ibc_channel_open_init("channel-12", APP_ORDER, IBC_APP_VERSION);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::UntrustedHost {
+                connection: CONNECTION_ID.into(),
+                port: HOST_PORT.into(),
+            }
+        );
+
+        // no host at all on this connection
+        let msg = ExecuteMsg::RemoveHost {
+            connection_id: CONNECTION_ID.into(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+        let open = mock_ibc_channel_open_try("channel-12", APP_ORDER, IBC_APP_VERSION);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert_eq!(err, ContractError::UnknownHost(CONNECTION_ID.into()));
+
+        let r = query(deps.as_ref(), mock_env(), QueryMsg::ListHosts {}).unwrap();
+        let hosts: ListHostsResponse = from_slice(&r).unwrap();
+        assert!(hosts.hosts.is_empty());
+    }
+
+    #[test]
+    fn predict_remote_account() {
+        let mut deps = setup();
+        let channel_id = "channel-3";
+        let host = bech32::encode("osmo", [7u8; 32].to_base32(), Variant::Bech32).unwrap();
+        let host_port = format!("wasm.{}", host);
+        let register = |port_id: &str, checksum: Vec<u8>| ExecuteMsg::RegisterHost {
+            connection_id: CONNECTION_ID.into(),
+            port_id: port_id.into(),
+            chain_id: "remote-1".into(),
+            prediction: Some(AccountPrediction {
+                counterparty_connection_id: "connection-7".into(),
+                checksum: HexBinary::from(checksum),
+            }),
+        };
+
+        // we need a valid checksum and a contract port
+        let creator = mock_info(CREATOR, &[]);
+        let msg = register(&host_port, vec![1u8; 20]);
+        execute(deps.as_mut(), mock_env(), creator.clone(), msg).unwrap_err();
+        let msg = register(HOST_PORT, vec![1u8; 32]);
+        execute(deps.as_mut(), mock_env(), creator.clone(), msg).unwrap_err();
+        let msg = register(&host_port, vec![1u8; 32]);
+        execute(deps.as_mut(), mock_env(), creator, msg).unwrap();
+
+        // the account is known as soon as the channel connects
+        let mut channel = mock_ibc_channel(channel_id, APP_ORDER, IBC_APP_VERSION);
+        channel.counterparty_endpoint.port_id = host_port;
+        let connect = IbcChannelConnectMsg::new_ack(channel.clone(), IBC_APP_VERSION);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+
+        let salt = account_salt("connection-7", &channel.endpoint.port_id, 0);
+        let creator = CanonicalAddr::from(vec![7u8; 32]);
+        let expected = instantiate2_address(&[1u8; 32], &creator, &salt).unwrap();
+        let expected =
+            bech32::encode("osmo", expected.as_slice().to_base32(), Variant::Bech32).unwrap();
+        let account_query = QueryMsg::Account {
+            channel_id: channel_id.into(),
+            account_id: 0,
+        };
+        let r = query(deps.as_ref(), mock_env(), account_query.clone()).unwrap();
+        let acct: AccountResponse = from_slice(&r).unwrap();
+        assert_eq!(acct.remote_addr, Some(expected.clone()));
+
+        // until the host confirms it, a lost WhoAmI is sent again
+        let packet = PacketMsg::WhoAmI { account_id: 0 };
+        let msg = mock_ibc_packet_timeout(channel_id, &packet).unwrap();
+        let res = ibc_packet_timeout(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        // the host confirms it
+        who_am_i_response(deps.as_mut(), channel_id, &expected);
+        let r = query(deps.as_ref(), mock_env(), account_query.clone()).unwrap();
+        let acct: AccountResponse = from_slice(&r).unwrap();
+        assert_eq!(acct.remote_addr, Some(expected.clone()));
+        assert_eq!(acct.prediction_mismatch, None);
+
+        // or disagrees, then it wins and the prediction is kept for the admin
+        who_am_i_response(deps.as_mut(), channel_id, "account-1");
+        let r = query(deps.as_ref(), mock_env(), account_query).unwrap();
+        let acct: AccountResponse = from_slice(&r).unwrap();
+        assert_eq!(acct.remote_addr, Some("account-1".into()));
+        assert_eq!(acct.prediction_mismatch, Some(expected.clone()));
+        let r = query(deps.as_ref(), mock_env(), list_accounts_msg(None)).unwrap();
+        let res: ListAccountsResponse = from_slice(&r).unwrap();
+        assert_eq!(res.accounts[0].prediction_mismatch, Some(expected.clone()));
+
+        // a balances answer can also correct an unconfirmed prediction
+        channel.endpoint.channel_id = "channel-4".into();
+        let connect = IbcChannelConnectMsg::new_ack(channel, IBC_APP_VERSION);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+        let balances = |account: &str| {
+            IbcAcknowledgement::new(StdAck::success(BalancesResponse {
+                account: account.into(),
+                balances: coins(5, "uatom"),
+                execute_callback: false,
+            }))
+        };
+        let packet = PacketMsg::Balances {
+            callback: false,
+            account_id: 0,
+        };
+        let msg = mock_ibc_packet_ack("channel-4", &packet, balances("account-2")).unwrap();
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert!(res
+            .attributes
+            .contains(&("predicted_account", expected.as_str()).into()));
+        let account_query = QueryMsg::Account {
+            channel_id: "channel-4".into(),
+            account_id: 0,
+        };
+        let r = query(deps.as_ref(), mock_env(), account_query).unwrap();
+        let acct: AccountResponse = from_slice(&r).unwrap();
+        assert_eq!(acct.remote_addr.as_deref(), Some("account-2"));
+        assert_eq!(acct.prediction_mismatch, Some(expected));
+        assert_eq!(acct.remote_balance, coins(5, "uatom"));
+
+        // once confirmed, the address must not change
+        let msg = mock_ibc_packet_ack("channel-4", &packet, balances("account-3")).unwrap();
+        let err = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::RemoteAccountChanged {
+                old: "account-2".into(),
+                addr: "account-3".into()
+            }
+        );
+    }
+
+    #[test]
+    fn negotiate_version_in_handshake() {
+        let mut deps = setup();
+
+        // with no version requested we propose everything we support
+        let init = mock_ibc_channel_open_init("channel-12", APP_ORDER, "");
+        let res = ibc_channel_open(deps.as_mut(), mock_env(), init).unwrap();
+        assert_eq!(res.unwrap().version, propose_versions("").unwrap());
+
+        // we pick the highest version we know out of the counterparty proposal
+        let proposal = format!("simple-ica-v99,{}", IBC_APP_VERSION);
+        let try_open = mock_ibc_channel_open_try("channel-12", APP_ORDER, &proposal);
+        let res = ibc_channel_open(deps.as_mut(), mock_env(), try_open).unwrap();
+        assert_eq!(res.unwrap().version, IBC_APP_VERSION);
+
+        // the connect step only accepts a single negotiated version
+        let connect = mock_ibc_channel_connect_ack("channel-12", APP_ORDER, &proposal);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap_err();
+
+        let connect = mock_ibc_channel_connect_ack("channel-12", APP_ORDER, IBC_APP_VERSION);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+        let info = CHANNELS.load(&deps.storage, "channel-12").unwrap();
+        assert_eq!(info.version, IBC_APP_VERSION);
+    }
+
+    #[test]
+    fn handshake_with_metadata() {
+        let mut deps = setup();
+
+        // metadata is validated and echoed
+        let requested = r#"{"version":"simple-ica-v3","encoding":"json","max_accounts":4}"#;
+        let init = mock_ibc_channel_open_init("channel-12", APP_ORDER, requested);
+        let res = ibc_channel_open(deps.as_mut(), mock_env(), init).unwrap();
+        assert_eq!(res.unwrap().version, requested);
+        let bad = r#"{"version":"simple-ica-v3","encoding":"proto3"}"#;
+        let init = mock_ibc_channel_open_init("channel-12", APP_ORDER, bad);
+        ibc_channel_open(deps.as_mut(), mock_env(), init).unwrap_err();
+
+        // we keep what the host answered
+        let answer = r#"{"version":"simple-ica-v3","encoding":"json","account_type":"cw1_whitelist","reflect_code_id":7,"max_accounts":4}"#;
+        let connect = mock_ibc_channel_connect_ack("channel-12", APP_ORDER, answer);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+        let msg = QueryMsg::Channel {
+            channel_id: "channel-12".into(),
+        };
+        let raw = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let channel: ChannelResponse = from_slice(&raw).unwrap();
+        assert_eq!(channel.version, IBC_APP_VERSION_V3);
+        assert_eq!(channel.encoding, Encoding::Json);
+        let metadata = channel.metadata.unwrap();
+        assert_eq!(metadata.account_type.as_deref(), Some("cw1_whitelist"));
+        assert_eq!(metadata.reflect_code_id, Some(7));
+        assert_eq!(metadata.max_accounts, Some(4));
+    }
+
     #[test]
     fn proper_handshake_flow() {
         // setup and connect handshake
@@ -303,6 +876,7 @@ mod tests {
         // check for empty account
         let q = QueryMsg::Account {
             channel_id: channel_id.into(),
+            account_id: 0,
         };
         let r = query(deps.as_ref(), mock_env(), q).unwrap();
         let acct: AccountResponse = from_slice(&r).unwrap();
@@ -317,6 +891,7 @@ mod tests {
         // account should be set up
         let q = QueryMsg::Account {
             channel_id: channel_id.into(),
+            account_id: 0,
         };
         let r = query(deps.as_ref(), mock_env(), q).unwrap();
         let acct: AccountResponse = from_slice(&r).unwrap();
@@ -325,6 +900,54 @@ mod tests {
         assert_eq!(0, acct.last_update_time.nanos());
     }
 
+    #[test]
+    fn proto_channel_encodes_packets_and_acks() {
+        let mut deps = setup();
+        let channel_id = "channel-1234";
+        let encoding = Encoding::Proto3;
+
+        let connect = mock_ibc_channel_connect_ack(channel_id, APP_ORDER, IBC_APP_VERSION_PROTO);
+        let res = ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+        let data = match &res.messages[0].msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => data.clone(),
+            o => panic!("Unexpected message: {:?}", o),
+        };
+        let packet: PacketMsg = encoding.decode(&data).unwrap();
+        assert_eq!(packet, PacketMsg::WhoAmI { account_id: 0 });
+
+        // the ack comes back in proto as well
+        let response = WhoAmIResponse {
+            account: "account-789".into(),
+        };
+        let ack = IbcAcknowledgement::new(StdAck::success_with(encoding, &response));
+        let mut msg = mock_ibc_packet_ack(channel_id, &(), ack).unwrap();
+        msg.original_packet.data = data;
+        ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let acct = accounts().load(&deps.storage, (channel_id, 0)).unwrap();
+        assert_eq!(acct.remote_addr.unwrap(), "account-789");
+
+        // error acks written by wasmd itself are json
+        let packet = PacketMsg::Balances {
+            callback: false,
+            account_id: 0,
+        };
+        let data = encoding.encode(&packet).unwrap();
+        let ack = IbcAcknowledgement::new(br#"{"error":"out of gas"}"#);
+        let mut msg = mock_ibc_packet_ack(channel_id, &(), ack).unwrap();
+        msg.original_packet.data = data.clone();
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert!(res.attributes.contains(&("error", "out of gas").into()));
+
+        // and anything else is still acknowledged
+        let ack = IbcAcknowledgement::new(b"\xff\xff");
+        let mut msg = mock_ibc_packet_ack(channel_id, &(), ack).unwrap();
+        msg.original_packet.data = data;
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.attributes[0], ("action", "acknowledge_balances"));
+        assert!(res.attributes[1].value.starts_with("Unreadable ack"));
+    }
+
     #[test]
     fn dispatch_message_send_and_ack() {
         let channel_id = "channel-1234";
@@ -346,6 +969,8 @@ mod tests {
         let handle_msg = ExecuteMsg::SendMsgs {
             channel_id: channel_id.into(),
             msgs: msgs_to_dispatch,
+            mode: None,
+            account_id: 0,
         };
         let info = mock_info(CREATOR, &[]);
         let mut res = execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap();
@@ -354,7 +979,7 @@ mod tests {
             CosmosMsg::Ibc(IbcMsg::SendPacket {
                 channel_id, data, ..
             }) => {
-                let ack = IbcAcknowledgement::new(StdAck::success(&()));
+                let ack = IbcAcknowledgement::new(StdAck::success(()));
                 let mut msg = mock_ibc_packet_ack(&channel_id, &1u32, ack).unwrap();
                 msg.original_packet.data = data;
                 msg
@@ -367,6 +992,518 @@ mod tests {
         assert_eq!(vec![("action", "acknowledge_dispatch")], res.attributes)
     }
 
+    #[test]
+    fn custom_msgs_need_supporting_channel() {
+        let mut deps = setup();
+        let v1_channel = "channel-1";
+        let v3_channel = "channel-2";
+        connect(deps.as_mut(), v1_channel);
+        let connect_v3 = mock_ibc_channel_connect_ack(v3_channel, APP_ORDER, IBC_APP_VERSION_V3);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect_v3).unwrap();
+
+        let msgs = vec![
+            RemoteMsg::Stargate {
+                type_url: "/cosmos.bank.v1beta1.MsgSend".into(),
+                value: b"\x0a\x05hello".into(),
+            },
+            RemoteMsg::Custom(br#"{"create_denom":{"subdenom":"ica"}}"#.into()),
+        ];
+
+        // the v1 protocol has no such packet
+        let handle_msg = ExecuteMsg::SendCustomMsgs {
+            channel_id: v1_channel.into(),
+            msgs: msgs.clone(),
+            mode: None,
+            account_id: 0,
+        };
+        let info = mock_info(CREATOR, &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::FeatureNotSupported {
+                channel: v1_channel.into(),
+                feature: FEATURE_CUSTOM_MSGS.into(),
+            }
+        );
+
+        let handle_msg = ExecuteMsg::SendCustomMsgs {
+            channel_id: v3_channel.into(),
+            msgs: msgs.clone(),
+            mode: None,
+            account_id: 0,
+        };
+        let info = mock_info(CREATOR, &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => {
+                let packet: PacketMsg = from_slice(data).unwrap();
+                assert_eq!(
+                    packet,
+                    PacketMsg::DispatchCustom {
+                        msgs,
+                        mode: DispatchMode::Atomic,
+                        account_id: 0,
+                    }
+                );
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        }
+    }
+
+    #[test]
+    fn best_effort_dispatch_reports_outcomes() {
+        let mut deps = setup();
+        let v1_channel = "channel-1";
+        let v3_channel = "channel-2";
+        connect(deps.as_mut(), v1_channel);
+        let connect_v3 = mock_ibc_channel_connect_ack(v3_channel, APP_ORDER, IBC_APP_VERSION_V3);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect_v3).unwrap();
+
+        let msgs: Vec<CosmosMsg> = vec![
+            BankMsg::Send {
+                to_address: "my-friend".into(),
+                amount: coins(123, "uatom"),
+            }
+            .into(),
+            BankMsg::Burn {
+                amount: coins(456, "uatom"),
+            }
+            .into(),
+        ];
+        let mode = DispatchMode::BestEffort {
+            gas_limit: Some(100_000),
+        };
+
+        // the v1 protocol only dispatches atomically
+        let handle_msg = ExecuteMsg::SendMsgs {
+            channel_id: v1_channel.into(),
+            msgs: msgs.clone(),
+            mode: Some(mode),
+            account_id: 0,
+        };
+        let info = mock_info(CREATOR, &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::FeatureNotSupported {
+                channel: v1_channel.into(),
+                feature: FEATURE_BEST_EFFORT_DISPATCH.into(),
+            }
+        );
+
+        let handle_msg = ExecuteMsg::SendMsgs {
+            channel_id: v3_channel.into(),
+            msgs,
+            mode: Some(mode),
+            account_id: 0,
+        };
+        let info = mock_info(CREATOR, &[]);
+        let mut res = execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap();
+        let data = match res.messages.swap_remove(0).msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => data,
+            o => panic!("Unexpected message: {:?}", o),
+        };
+        let packet: PacketMsg = from_slice(&data).unwrap();
+        assert!(matches!(packet, PacketMsg::Dispatch { mode: m, .. } if m == mode));
+
+        // the ack lists every failed message by index
+        let response = DispatchResponse {
+            results: vec![],
+            outcomes: vec![
+                MsgOutcome::Success(b"sent".into()),
+                MsgOutcome::Error("insufficient funds".into()),
+            ],
+        };
+        let ack = IbcAcknowledgement::new(StdAck::success(response));
+        let mut msg = mock_ibc_packet_ack(v3_channel, &(), ack).unwrap();
+        msg.original_packet.data = data;
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(
+            vec![
+                ("action", "acknowledge_dispatch"),
+                ("succeeded", "1"),
+                ("error_1", "insufficient funds")
+            ],
+            res.attributes
+        );
+    }
+
+    #[test]
+    fn set_return_address_needs_supporting_channel() {
+        let mut deps = setup();
+        let v1_channel = "channel-1";
+        let v3_channel = "channel-2";
+        connect(deps.as_mut(), v1_channel);
+        let connect_v3 = mock_ibc_channel_connect_ack(v3_channel, APP_ORDER, IBC_APP_VERSION_V3);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect_v3).unwrap();
+
+        let handle_msg = ExecuteMsg::SetReturnAddress {
+            channel_id: v1_channel.into(),
+            address: Some("wasm1recover".into()),
+        };
+        let info = mock_info(CREATOR, &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::FeatureNotSupported {
+                channel: v1_channel.into(),
+                feature: FEATURE_RETURN_ADDRESS.into(),
+            }
+        );
+
+        // the channel can also be named by its alias
+        let set_alias = ExecuteMsg::SetChannelAlias {
+            channel_id: v3_channel.into(),
+            alias: Some("recovery".into()),
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(CREATOR, &[]),
+            set_alias,
+        )
+        .unwrap();
+        let handle_msg = ExecuteMsg::SetReturnAddress {
+            channel_id: "recovery".into(),
+            address: Some("wasm1recover".into()),
+        };
+        let info = mock_info(CREATOR, &[]);
+        let mut res = execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap();
+        let data = match res.messages.swap_remove(0).msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket {
+                data, channel_id, ..
+            }) => {
+                assert_eq!(channel_id, v3_channel);
+                data
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        };
+        let packet: PacketMsg = from_slice(&data).unwrap();
+        assert_eq!(
+            packet,
+            PacketMsg::SetReturnAddress {
+                address: Some("wasm1recover".into())
+            }
+        );
+
+        let ack = IbcAcknowledgement::new(StdAck::Result(Binary::default()).ack());
+        let mut msg = mock_ibc_packet_ack(v3_channel, &1u32, ack).unwrap();
+        msg.original_packet.data = data;
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(0, res.messages.len());
+        assert_eq!(
+            vec![("action", "acknowledge_set_return_address")],
+            res.attributes
+        );
+    }
+
+    #[test]
+    fn set_outflow_limits_needs_supporting_channel() {
+        let mut deps = setup();
+        let v1_channel = "channel-1";
+        let v3_channel = "channel-2";
+        connect(deps.as_mut(), v1_channel);
+        let connect_v3 = mock_ibc_channel_connect_ack(v3_channel, APP_ORDER, IBC_APP_VERSION_V3);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect_v3).unwrap();
+
+        let limits = vec![OutflowLimit {
+            amount: coin(500, "uatom"),
+            window: 3600,
+        }];
+        let set_limits = |channel_id: &str| ExecuteMsg::SetOutflowLimits {
+            channel_id: channel_id.into(),
+            account_id: 0,
+            limits: limits.clone(),
+        };
+        let info = mock_info(CREATOR, &[]);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            info.clone(),
+            set_limits(v1_channel),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::FeatureNotSupported {
+                channel: v1_channel.into(),
+                feature: FEATURE_OUTFLOW_LIMITS.into(),
+            }
+        );
+
+        let mut res = execute(deps.as_mut(), mock_env(), info, set_limits(v3_channel)).unwrap();
+        let data = match res.messages.swap_remove(0).msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => data,
+            o => panic!("Unexpected message: {:?}", o),
+        };
+        let packet: PacketMsg = from_slice(&data).unwrap();
+        assert_eq!(
+            packet,
+            PacketMsg::SetOutflowLimits {
+                limits: limits.clone(),
+                account_id: 0,
+            }
+        );
+
+        // a rejected limit shows up in the logs
+        let ack = IbcAcknowledgement::new(
+            StdAck::CodedError {
+                code: ErrorCode::UnsupportedMsg.code(),
+                message: "Outflow limits can only be tightened".into(),
+            }
+            .ack(),
+        );
+        let mut msg = mock_ibc_packet_ack(v3_channel, &1u32, ack).unwrap();
+        msg.original_packet.data = data;
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.attributes[0].value, "acknowledge_set_outflow_limits");
+        assert_eq!(res.attributes[1].value, "0");
+        assert!(res.attributes.iter().any(|a| a.value.contains("tightened")));
+    }
+
+    #[test]
+    fn block_msgs_needs_supporting_channel() {
+        let mut deps = setup();
+        let v1_channel = "channel-1";
+        let v3_channel = "channel-2";
+        connect(deps.as_mut(), v1_channel);
+        let connect_v3 = mock_ibc_channel_connect_ack(v3_channel, APP_ORDER, IBC_APP_VERSION_V3);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect_v3).unwrap();
+
+        let block = |channel_id: &str| ExecuteMsg::BlockMsgs {
+            channel_id: channel_id.into(),
+            account_id: 0,
+            kinds: vec!["wasm/migrate".into()],
+        };
+        let info = mock_info(CREATOR, &[]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), block(v1_channel)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::FeatureNotSupported {
+                channel: v1_channel.into(),
+                feature: FEATURE_MSG_POLICIES.into(),
+            }
+        );
+
+        let mut res = execute(deps.as_mut(), mock_env(), info, block(v3_channel)).unwrap();
+        let data = match res.messages.swap_remove(0).msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => data,
+            o => panic!("Unexpected message: {:?}", o),
+        };
+        let packet: PacketMsg = from_slice(&data).unwrap();
+        assert_eq!(
+            packet,
+            PacketMsg::BlockMsgs {
+                kinds: vec!["wasm/migrate".into()],
+                account_id: 0,
+            }
+        );
+
+        let ack = IbcAcknowledgement::new(StdAck::Result(Binary::default()).ack());
+        let mut msg = mock_ibc_packet_ack(v3_channel, &1u32, ack).unwrap();
+        msg.original_packet.data = data;
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.attributes[0].value, "acknowledge_block_msgs");
+    }
+
+    #[test]
+    fn migrate_account_needs_supporting_channel() {
+        let mut deps = setup();
+        let v1_channel = "channel-1";
+        let v3_channel = "channel-2";
+        connect(deps.as_mut(), v1_channel);
+        let connect_v3 = mock_ibc_channel_connect_ack(v3_channel, APP_ORDER, IBC_APP_VERSION_V3);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect_v3).unwrap();
+
+        let migrate = |channel_id: &str| ExecuteMsg::MigrateAccount {
+            channel_id: channel_id.into(),
+            account_id: 0,
+            new_code_id: 17,
+        };
+        let info = mock_info(CREATOR, &[]);
+        let err =
+            execute(deps.as_mut(), mock_env(), info.clone(), migrate(v1_channel)).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::FeatureNotSupported {
+                channel: v1_channel.into(),
+                feature: FEATURE_MIGRATE_ACCOUNT.into(),
+            }
+        );
+
+        let mut res = execute(deps.as_mut(), mock_env(), info, migrate(v3_channel)).unwrap();
+        let data = match res.messages.swap_remove(0).msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => data,
+            o => panic!("Unexpected message: {:?}", o),
+        };
+        let packet: PacketMsg = from_slice(&data).unwrap();
+        assert_eq!(
+            packet,
+            PacketMsg::MigrateAccount {
+                new_code_id: 17,
+                account_id: 0,
+            }
+        );
+
+        // the outcome only shows up in the logs, the host keeps the status
+        let response = MigrateAccountResponse {
+            account_id: 0,
+            code_id: 17,
+        };
+        let ack = IbcAcknowledgement::new(StdAck::success(response));
+        let mut msg = mock_ibc_packet_ack(v3_channel, &1u32, ack).unwrap();
+        msg.original_packet.data = data.clone();
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(
+            vec![
+                ("action", "acknowledge_migrate_account"),
+                ("account_id", "0"),
+                ("code_id", "17")
+            ],
+            res.attributes
+        );
+
+        let ack = IbcAcknowledgement::new(StdAck::fail("Migration failed: out of gas".into()));
+        let mut msg = mock_ibc_packet_ack(v3_channel, &1u32, ack).unwrap();
+        msg.original_packet.data = data;
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.attributes[0], ("action", "acknowledge_migrate_account"));
+        assert_eq!(res.attributes.len(), 2);
+    }
+
+    #[test]
+    fn create_sub_accounts() {
+        let mut deps = setup();
+        let channel_id = "channel-2";
+        let connect_v3 = mock_ibc_channel_connect_ack(channel_id, APP_ORDER, IBC_APP_VERSION_V3);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect_v3).unwrap();
+        let set_alias = ExecuteMsg::SetChannelAlias {
+            channel_id: channel_id.into(),
+            alias: Some("strategies".into()),
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(CREATOR, &[]),
+            set_alias,
+        )
+        .unwrap();
+
+        // sent by alias
+        let handle_msg = ExecuteMsg::CreateAccount {
+            channel_id: "strategies".into(),
+            label: "strategy-a".into(),
+        };
+        let info = mock_info(CREATOR, &[]);
+        let mut res = execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap();
+        let data = match res.messages.swap_remove(0).msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket {
+                data, channel_id, ..
+            }) => {
+                assert_eq!(channel_id, "channel-2");
+                data
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        };
+
+        // the host answers with the id of the new account
+        let response = CreateAccountResponse {
+            account_id: 1,
+            account: "sub-account".into(),
+        };
+        let ack = IbcAcknowledgement::new(StdAck::success(&response));
+        let mut msg = mock_ibc_packet_ack(channel_id, &1u32, ack).unwrap();
+        msg.original_packet.data = data;
+        ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let r = query(deps.as_ref(), mock_env(), list_accounts_msg(None)).unwrap();
+        let res: ListAccountsResponse = from_slice(&r).unwrap();
+        let accounts: Vec<_> = res
+            .accounts
+            .iter()
+            .map(|a| (a.account_id, a.remote_addr.as_deref(), a.label.as_deref()))
+            .collect();
+        assert_eq!(
+            accounts,
+            vec![
+                (0, None, None),
+                (1, Some("sub-account"), Some("strategy-a"))
+            ]
+        );
+
+        // packets are addressed to the account
+        let handle_msg = ExecuteMsg::CheckRemoteBalance {
+            channel_id: channel_id.into(),
+            callback: false,
+            account_id: 1,
+        };
+        let info = mock_info(CREATOR, &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => {
+                let packet: PacketMsg = from_slice(data).unwrap();
+                assert_eq!(packet.account_id(), 1);
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        }
+        let handle_msg = ExecuteMsg::CheckRemoteBalance {
+            channel_id: channel_id.into(),
+            callback: false,
+            account_id: 2,
+        };
+        let info = mock_info(CREATOR, &[]);
+        execute(deps.as_mut(), mock_env(), info, handle_msg).unwrap_err();
+
+        // closing the channel drops all of them
+        let close = mock_ibc_channel_close_init(channel_id, APP_ORDER, IBC_APP_VERSION_V3);
+        ibc_channel_close(deps.as_mut(), mock_env(), close).unwrap();
+        let r = query(deps.as_ref(), mock_env(), list_accounts_msg(None)).unwrap();
+        let res: ListAccountsResponse = from_slice(&r).unwrap();
+        assert!(res.accounts.is_empty());
+    }
+
+    #[test]
+    fn coded_error_acks_are_decoded() {
+        let mut deps = setup();
+        let channel_id = "channel-2";
+        let connect = mock_ibc_channel_connect_ack(channel_id, APP_ORDER, IBC_APP_VERSION_V3);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+
+        let version = lookup_version(IBC_APP_VERSION_V3).unwrap();
+        let ack = StdAck::fail_for(version, ErrorCode::UnknownChannel, "lost".into());
+        let msg = mock_ibc_packet_ack(
+            channel_id,
+            &PacketMsg::WhoAmI { account_id: 0 },
+            IbcAcknowledgement::new(ack),
+        )
+        .unwrap();
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(
+            vec![
+                ("action", "acknowledge_who_am_i"),
+                ("error", "Remote has no account for channel: lost"),
+                ("error_code", "3"),
+            ],
+            res.attributes
+        );
+
+        // plain errors from v1 hosts have no code
+        let ack = StdAck::fail("oops".into());
+        let msg = mock_ibc_packet_ack(
+            channel_id,
+            &PacketMsg::WhoAmI { account_id: 0 },
+            IbcAcknowledgement::new(ack),
+        )
+        .unwrap();
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(
+            vec![("action", "acknowledge_who_am_i"), ("error", "oops")],
+            res.attributes
+        );
+        let acct = accounts().load(&deps.storage, (channel_id, 0)).unwrap();
+        assert!(acct.remote_addr.is_none());
+    }
+
     #[test]
     fn send_remote_funds() {
         let reflect_channel_id = "channel-1234";
@@ -384,6 +1521,7 @@ mod tests {
         let msg = ExecuteMsg::SendFunds {
             reflect_channel_id: "random-channel".into(),
             transfer_channel_id: transfer_channel_id.into(),
+            account_id: 0,
         };
         let info = mock_info(CREATOR, &coins(12344, "utrgd"));
         execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
@@ -392,6 +1530,7 @@ mod tests {
         let msg = ExecuteMsg::SendFunds {
             reflect_channel_id: reflect_channel_id.into(),
             transfer_channel_id: transfer_channel_id.into(),
+            account_id: 0,
         };
         let info = mock_info(CREATOR, &[]);
         execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
@@ -400,6 +1539,7 @@ mod tests {
         let msg = ExecuteMsg::SendFunds {
             reflect_channel_id: reflect_channel_id.into(),
             transfer_channel_id: transfer_channel_id.into(),
+            account_id: 0,
         };
         let info = mock_info(CREATOR, &coins(12344, "utrgd"));
         let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
@@ -420,4 +1560,454 @@ mod tests {
             o => panic!("unexpected message: {:?}", o),
         }
     }
+
+    #[test]
+    fn list_accounts_in_pages_and_by_remote_addr() {
+        let mut deps = setup();
+        for channel_id in ["channel-1", "channel-2", "channel-3"] {
+            connect(deps.as_mut(), channel_id);
+        }
+        who_am_i_response(deps.as_mut(), "channel-1", "remote-a");
+        who_am_i_response(deps.as_mut(), "channel-2", "remote-b");
+        // only the first account has a fresh balance
+        let packet = PacketMsg::Balances {
+            callback: false,
+            account_id: 0,
+        };
+        let ack = IbcAcknowledgement::new(StdAck::success(BalancesResponse {
+            account: "remote-a".into(),
+            balances: coins(5, "uatom"),
+            execute_callback: false,
+        }));
+        let msg = mock_ibc_packet_ack("channel-1", &packet, ack).unwrap();
+        ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let list = |msg: QueryMsg| -> Vec<String> {
+            let r = query(deps.as_ref(), mock_env(), msg).unwrap();
+            let res: ListAccountsResponse = from_slice(&r).unwrap();
+            res.accounts.into_iter().map(|a| a.channel_id).collect()
+        };
+        let page = list(QueryMsg::ListAccounts {
+            start_after: None,
+            limit: Some(2),
+            filter: AccountFilter::default(),
+        });
+        assert_eq!(page, vec!["channel-1", "channel-2"]);
+        let page = list(QueryMsg::ListAccounts {
+            start_after: Some(ChannelAccount {
+                channel_id: "channel-2".into(),
+                account_id: 0,
+            }),
+            limit: Some(2),
+            filter: AccountFilter::default(),
+        });
+        assert_eq!(page, vec!["channel-3"]);
+
+        let missing = AccountFilter {
+            missing_remote_addr: true,
+            ..AccountFilter::default()
+        };
+        assert_eq!(list(list_accounts_msg(Some(missing))), vec!["channel-3"]);
+        let stale = AccountFilter {
+            stale_for: Some(60),
+            ..AccountFilter::default()
+        };
+        assert_eq!(
+            list(list_accounts_msg(Some(stale))),
+            vec!["channel-2", "channel-3"]
+        );
+        // nothing is that old, even the accounts never updated
+        let forever = AccountFilter {
+            stale_for: Some(u64::MAX),
+            ..AccountFilter::default()
+        };
+        assert!(list(list_accounts_msg(Some(forever))).is_empty());
+        let remote = |chain_id: &str| AccountFilter {
+            chain_id: Some(chain_id.into()),
+            stale_for: Some(60),
+            ..AccountFilter::default()
+        };
+        assert_eq!(list(list_accounts_msg(Some(remote("remote-1")))).len(), 2);
+        assert!(list(list_accounts_msg(Some(remote("other-1")))).is_empty());
+
+        let msg = QueryMsg::AccountByRemoteAddr {
+            remote_addr: "remote-b".into(),
+        };
+        let r = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let account: AccountInfo = from_slice(&r).unwrap();
+        assert_eq!(account.channel_id, "channel-2");
+        assert_eq!(account.chain_id, "remote-1");
+        let msg = QueryMsg::AccountByRemoteAddr {
+            remote_addr: "remote-c".into(),
+        };
+        query(deps.as_ref(), mock_env(), msg).unwrap_err();
+    }
+
+    #[test]
+    fn channels_have_metadata_and_aliases() {
+        let mut deps = setup();
+        connect(deps.as_mut(), "channel-1");
+        connect(deps.as_mut(), "channel-2");
+        who_am_i_response(deps.as_mut(), "channel-1", "remote-a");
+
+        // only the first channel to the host gets the chain id
+        let channel = |deps: Deps, channel: &str| -> ChannelResponse {
+            let msg = QueryMsg::Channel {
+                channel_id: channel.into(),
+            };
+            from_slice(&query(deps, mock_env(), msg).unwrap()).unwrap()
+        };
+        let info = channel(deps.as_ref(), "remote-1");
+        assert_eq!(info.alias.as_deref(), Some("remote-1"));
+        assert_eq!(info.connection_id, CONNECTION_ID);
+        assert_eq!(info.counterparty_port, HOST_PORT);
+        assert_eq!(info.counterparty_channel_id, "channel-7");
+        assert_eq!(channel(deps.as_ref(), "channel-2").alias, None);
+
+        // messages take the alias instead of the channel id
+        let msg = ExecuteMsg::SendMsgs {
+            channel_id: "remote-1".into(),
+            msgs: vec![],
+            mode: None,
+            account_id: 0,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { channel_id, .. }) => {
+                assert_eq!(channel_id, "channel-1")
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        }
+
+        // aliases are unique and cannot look like channel ids
+        let set_alias = |channel_id: &str, alias: Option<&str>| ExecuteMsg::SetChannelAlias {
+            channel_id: channel_id.into(),
+            alias: alias.map(Into::into),
+        };
+        let msg = set_alias("channel-2", Some("remote-1"));
+        let err = execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::AliasTaken {
+                alias: "remote-1".into(),
+                channel: "channel-1".into()
+            }
+        );
+        let msg = set_alias("channel-2", Some("channel-1"));
+        let err = execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidAlias("channel-1".into()));
+        let msg = set_alias("channel-2", Some("backup"));
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("stranger", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+        // renaming frees the old alias
+        let msg = set_alias("remote-1", Some("main"));
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+        let msg = QueryMsg::Channel {
+            channel_id: "remote-1".into(),
+        };
+        query(deps.as_ref(), mock_env(), msg).unwrap_err();
+
+        let r = query(deps.as_ref(), mock_env(), list_accounts_msg(None)).unwrap();
+        let res: ListAccountsResponse = from_slice(&r).unwrap();
+        let aliases: Vec<_> = res.accounts.iter().map(|a| a.alias.as_deref()).collect();
+        assert_eq!(aliases, vec![Some("main"), Some("backup")]);
+        assert_eq!(res.accounts[0].counterparty_port, HOST_PORT);
+
+        // closing the channel drops its alias
+        let close = mock_ibc_channel_close_init("channel-2", APP_ORDER, IBC_APP_VERSION);
+        ibc_channel_close(deps.as_mut(), mock_env(), close).unwrap();
+        let msg = set_alias("channel-1", Some("backup"));
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+    }
+
+    #[test]
+    fn who_am_i_is_retried_and_refreshed() {
+        let channel_id = "channel-1";
+        let mut deps = setup();
+        connect(deps.as_mut(), channel_id);
+        let account = |deps: Deps| -> AccountResponse {
+            let msg = QueryMsg::Account {
+                channel_id: channel_id.into(),
+                account_id: 0,
+            };
+            from_slice(&query(deps, mock_env(), msg).unwrap()).unwrap()
+        };
+
+        // timeouts are resent a few times
+        let packet = PacketMsg::WhoAmI { account_id: 0 };
+        for retry in 1..=MAX_WHO_AM_I_RETRIES {
+            let msg = mock_ibc_packet_timeout(channel_id, &packet).unwrap();
+            let res = ibc_packet_timeout(deps.as_mut(), mock_env(), msg).unwrap();
+            assert_eq!(1, res.messages.len());
+            assert!(res
+                .attributes
+                .contains(&("who_am_i_retry", retry.to_string()).into()));
+        }
+        let msg = mock_ibc_packet_timeout(channel_id, &packet).unwrap();
+        let res = ibc_packet_timeout(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(0, res.messages.len());
+        let acct = account(deps.as_ref());
+        assert_eq!(acct.remote_addr, None);
+        assert_eq!(
+            acct.remote_addr_error.as_deref(),
+            Some("WhoAmI packet timed out")
+        );
+
+        // error acks show up on the account
+        let ack = IbcAcknowledgement::new(StdAck::fail("no account yet".into()));
+        let msg = mock_ibc_packet_ack(channel_id, &packet, ack).unwrap();
+        ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        let acct = account(deps.as_ref());
+        assert_eq!(acct.remote_addr_error.as_deref(), Some("no account yet"));
+
+        // the admin asks again, which also allows new retries
+        let msg = ExecuteMsg::RefreshRemoteAddress {
+            channel_id: channel_id.into(),
+            account_id: 0,
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("stranger", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        let res = execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => {
+                assert_eq!(from_slice::<PacketMsg>(data).unwrap(), packet)
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        }
+        let msg = mock_ibc_packet_timeout(channel_id, &packet).unwrap();
+        let res = ibc_packet_timeout(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        who_am_i_response(deps.as_mut(), channel_id, "remote-a");
+        let acct = account(deps.as_ref());
+        assert_eq!(acct.remote_addr.as_deref(), Some("remote-a"));
+        assert_eq!(acct.remote_addr_error, None);
+
+        // once the host answered, timeouts are not resent
+        let msg = mock_ibc_packet_timeout(channel_id, &packet).unwrap();
+        let res = ibc_packet_timeout(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(0, res.messages.len());
+    }
+
+    #[test]
+    fn closed_channels_leave_tombstones() {
+        let mut deps = setup();
+        connect(deps.as_mut(), "channel-1");
+        connect(deps.as_mut(), "channel-2");
+        who_am_i_response(deps.as_mut(), "channel-1", "remote-a");
+        let packet = PacketMsg::Balances {
+            callback: false,
+            account_id: 0,
+        };
+        let ack = IbcAcknowledgement::new(StdAck::success(BalancesResponse {
+            account: "remote-a".into(),
+            balances: coins(5, "uatom"),
+            execute_callback: false,
+        }));
+        let msg = mock_ibc_packet_ack("channel-1", &packet, ack).unwrap();
+        ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(100);
+        let close = mock_ibc_channel_close_init("channel-1", APP_ORDER, IBC_APP_VERSION);
+        ibc_channel_close(deps.as_mut(), env.clone(), close).unwrap();
+
+        // only the open channel is listed as active
+        let r = query(deps.as_ref(), mock_env(), list_accounts_msg(None)).unwrap();
+        let res: ListAccountsResponse = from_slice(&r).unwrap();
+        let channels: Vec<_> = res.accounts.iter().map(|a| a.channel_id.as_str()).collect();
+        assert_eq!(channels, vec!["channel-2"]);
+        let msg = QueryMsg::Account {
+            channel_id: "channel-1".into(),
+            account_id: 0,
+        };
+        query(deps.as_ref(), mock_env(), msg).unwrap_err();
+
+        // but we still know where the funds are
+        let msg = QueryMsg::ListClosedAccounts {
+            start_after: None,
+            limit: None,
+        };
+        let r = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let res: ListClosedAccountsResponse = from_slice(&r).unwrap();
+        assert_eq!(res.accounts.len(), 1);
+        let closed = &res.accounts[0];
+        assert_eq!(closed.closed_at, env.block.time);
+        assert_eq!(closed.account.channel_id, "channel-1");
+        assert_eq!(closed.account.remote_addr.as_deref(), Some("remote-a"));
+        assert_eq!(closed.account.remote_balance, coins(5, "uatom"));
+        assert_eq!(closed.account.connection_id, CONNECTION_ID);
+        assert_eq!(closed.account.counterparty_port, HOST_PORT);
+
+        let msg = QueryMsg::ClosedAccount {
+            channel_id: "channel-1".into(),
+            account_id: 0,
+        };
+        let r = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let single: ClosedAccountInfo = from_slice(&r).unwrap();
+        assert_eq!(&single, closed);
+        let msg = QueryMsg::ClosedAccount {
+            channel_id: "channel-2".into(),
+            account_id: 0,
+        };
+        query(deps.as_ref(), mock_env(), msg).unwrap_err();
+    }
+
+    #[test]
+    fn replace_and_close_channels() {
+        let mut deps = setup();
+        for channel_id in ["channel-1", "channel-2", "channel-3"] {
+            connect(deps.as_mut(), channel_id);
+        }
+        who_am_i_response(deps.as_mut(), "channel-1", "remote-a");
+        let packet = PacketMsg::Balances {
+            callback: false,
+            account_id: 0,
+        };
+        let ack = IbcAcknowledgement::new(StdAck::success(BalancesResponse {
+            account: "remote-a".into(),
+            balances: coins(5, "uatom"),
+            execute_callback: false,
+        }));
+        let msg = mock_ibc_packet_ack("channel-1", &packet, ack).unwrap();
+        ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let replace = |old: &str, new: &str| ExecuteMsg::ReplaceChannel {
+            old_channel_id: old.into(),
+            new_channel_id: new.into(),
+        };
+        let msg = replace("remote-1", "channel-1");
+        let err = execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidReplacement {
+                old: "channel-1".into(),
+                new: "channel-1".into()
+            }
+        );
+        let msg = replace("remote-1", "channel-2");
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("stranger", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        let res = execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        let msg = replace("remote-1", "channel-3");
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+
+        let channel = |deps: Deps, channel: &str| -> ChannelResponse {
+            let msg = QueryMsg::Channel {
+                channel_id: channel.into(),
+            };
+            from_slice(&query(deps, mock_env(), msg).unwrap()).unwrap()
+        };
+        assert_eq!(
+            channel(deps.as_ref(), "channel-2").replacing.as_deref(),
+            Some("channel-1")
+        );
+
+        // another account on the host does not take over
+        let who_am_i = |account: &str| {
+            IbcAcknowledgement::new(StdAck::success(WhoAmIResponse {
+                account: account.into(),
+            }))
+        };
+        let packet = PacketMsg::WhoAmI { account_id: 0 };
+        let msg = mock_ibc_packet_ack("channel-3", &packet, who_am_i("remote-b")).unwrap();
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert!(res
+            .attributes
+            .contains(&("replacement_failed", "channel-1").into()));
+        assert_eq!(channel(deps.as_ref(), "channel-3").replacing, None);
+
+        // the same one does
+        let msg = mock_ibc_packet_ack("channel-2", &packet, who_am_i("remote-a")).unwrap();
+        let res = ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        assert!(res
+            .attributes
+            .contains(&("replaced_channel", "channel-1").into()));
+        let old = channel(deps.as_ref(), "channel-1");
+        assert_eq!(old.alias, None);
+        assert_eq!(old.replaced_by.as_deref(), Some("channel-2"));
+        let new = channel(deps.as_ref(), "remote-1");
+        assert_eq!(new.counterparty_channel_id, "channel-7");
+        assert_eq!(new.replacing, None);
+        let msg = QueryMsg::Account {
+            channel_id: "remote-1".into(),
+            account_id: 0,
+        };
+        let r = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let account: AccountResponse = from_slice(&r).unwrap();
+        assert_eq!(account.remote_addr.as_deref(), Some("remote-a"));
+        assert_eq!(account.remote_balance, coins(5, "uatom"));
+
+        // the accounts moved, so they are only found with the new channel
+        let msg = QueryMsg::AccountByRemoteAddr {
+            remote_addr: "remote-a".into(),
+        };
+        let r = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let account: AccountInfo = from_slice(&r).unwrap();
+        assert_eq!(account.channel_id, "channel-2");
+        let r = query(deps.as_ref(), mock_env(), list_accounts_msg(None)).unwrap();
+        let res: ListAccountsResponse = from_slice(&r).unwrap();
+        let channels: Vec<_> = res.accounts.iter().map(|a| a.channel_id.as_str()).collect();
+        assert_eq!(channels, vec!["channel-2", "channel-3"]);
+
+        // late answers on the old channel update them there
+        let ack = IbcAcknowledgement::new(StdAck::success(BalancesResponse {
+            account: "remote-a".into(),
+            balances: coins(7, "uatom"),
+            execute_callback: false,
+        }));
+        let balances = PacketMsg::Balances {
+            callback: false,
+            account_id: 0,
+        };
+        let msg = mock_ibc_packet_ack("channel-1", &balances, ack).unwrap();
+        ibc_packet_ack(deps.as_mut(), mock_env(), msg).unwrap();
+        let msg = QueryMsg::Account {
+            channel_id: "channel-2".into(),
+            account_id: 0,
+        };
+        let r = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let account: AccountResponse = from_slice(&r).unwrap();
+        assert_eq!(account.remote_balance, coins(7, "uatom"));
+
+        // now the old channel can be retired
+        let msg = ExecuteMsg::CloseChannel {
+            channel_id: "channel-1".into(),
+        };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("stranger", &[]),
+            msg.clone(),
+        )
+        .unwrap_err();
+        let res = execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Ibc(IbcMsg::CloseChannel {
+                channel_id: "channel-1".into()
+            })
+        );
+        let msg = ExecuteMsg::CloseChannel {
+            channel_id: "channel-9".into(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), msg).unwrap_err();
+    }
 }
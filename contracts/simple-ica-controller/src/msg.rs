@@ -1,8 +1,9 @@
 use cosmwasm_std::{Coin, CosmosMsg, Empty, Timestamp};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use simple_ica::{ChannelMetadata, DispatchMode, Encoding, OutflowLimit, RemoteMsg};
 
-use crate::state::AccountData;
+use crate::state::{AccountData, AccountPrediction, ChannelInfo, ClosedAccount, RemoteHost};
 
 /// This needs no info. Owner of the contract is whoever signed the InstantiateMsg.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -12,6 +13,8 @@ pub struct InstantiateMsg {
     pub mutable: bool,
 }
 
+/// Wherever a message or query takes the channel (`channel_id`, `reflect_channel_id`)
+/// of a remote account, the channel's alias works as well.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
@@ -24,12 +27,76 @@ pub enum ExecuteMsg {
     },
     SendMsgs {
         channel_id: String,
-        // Note: we don't handle custom messages on remote chains
         msgs: Vec<CosmosMsg<Empty>>,
+        /// Defaults to an atomic dispatch
+        #[serde(default)]
+        mode: Option<DispatchMode>,
+        /// Defaults to the account created on connect
+        #[serde(default)]
+        account_id: u64,
+    },
+    /// Like `SendMsgs`, but may include Stargate and chain specific custom messages.
+    /// Only works on channels that negotiated a version with custom message support.
+    SendCustomMsgs {
+        channel_id: String,
+        msgs: Vec<RemoteMsg>,
+        /// Defaults to an atomic dispatch
+        #[serde(default)]
+        mode: Option<DispatchMode>,
+        #[serde(default)]
+        account_id: u64,
     },
     CheckRemoteBalance {
         channel_id: String,
         callback: bool,
+        #[serde(default)]
+        account_id: u64,
+    },
+    /// Creates another account on the remote chain, it shows up in the account queries
+    /// once the host answers. Only works on channels with sub-account support.
+    CreateAccount {
+        channel_id: String,
+        label: String,
+    },
+    /// Asks the host to migrate the remote account to `new_code_id`, which must be
+    /// the host's current reflect code. Only works on channels with migration support.
+    MigrateAccount {
+        channel_id: String,
+        #[serde(default)]
+        account_id: u64,
+        new_code_id: u64,
+    },
+    /// Limits what the remote account may send out, on top of the host owner's limits.
+    /// The host only accepts limits at least as strict as the ones set before.
+    /// Only works on channels with outflow limit support.
+    SetOutflowLimits {
+        channel_id: String,
+        #[serde(default)]
+        account_id: u64,
+        limits: Vec<OutflowLimit>,
+    },
+    /// Asks the host to refuse these kinds of messages (e.g. `wasm/migrate`, `gov`
+    /// or a stargate type url) from now on. Blocked kinds cannot be unblocked over
+    /// the channel. Only works on channels with message policy support.
+    BlockMsgs {
+        channel_id: String,
+        #[serde(default)]
+        account_id: u64,
+        kinds: Vec<String>,
+    },
+    /// Tells the host where to send the remote account's funds over ICS-20
+    /// when the channel closes (if the host is configured to return them).
+    /// `None` clears it. Only works on channels with return address support.
+    SetReturnAddress {
+        channel_id: String,
+        address: Option<String>,
+    },
+    /// Asks the host for the address of the account again, e.g. when the `WhoAmI`
+    /// sent on connect failed. This also resets the automatic retries on timeout.
+    RefreshRemoteAddress {
+        channel_id: String,
+        #[serde(default)]
+        account_id: u64,
     },
     /// If you sent funds to this contract, it will attempt to ibc transfer them
     /// to the account on the remote side of this channel.
@@ -41,10 +108,48 @@ pub enum ExecuteMsg {
         /// port and handled by a different module.
         /// It should connect to the same chain as the reflect_channel_id does
         transfer_channel_id: String,
+        /// The account on the remote side that gets the funds
+        #[serde(default)]
+        account_id: u64,
     },
     ExecuteCosmosMsg {
         msgs: Vec<CosmosMsg<Empty>>,
     },
+    /// Closes the channel. Its accounts are kept as closed accounts, see
+    /// `QueryMsg::ListClosedAccounts`.
+    CloseChannel {
+        channel_id: String,
+    },
+    /// Makes `new_channel_id` the successor of `old_channel_id`, another channel to
+    /// the same chain. This sends a `WhoAmI` over the new channel. If the host
+    /// answers with the account of the old channel, the new channel gets the
+    /// old one's alias and its accounts with their cached balances.
+    /// The old channel stays open until closed with `CloseChannel`.
+    ReplaceChannel {
+        old_channel_id: String,
+        new_channel_id: String,
+    },
+    /// Names the channel, replacing its old alias. `None` removes it. Channels to
+    /// a registered host start with its chain id, if no other channel has it.
+    SetChannelAlias {
+        channel_id: String,
+        alias: Option<String>,
+    },
+    /// Trusts the host contract bound to `port_id` on the other side of the connection.
+    /// Channels can only be opened with registered hosts.
+    /// With `prediction` the remote account address is known right on connect
+    /// and checked against the host's answer.
+    RegisterHost {
+        connection_id: String,
+        port_id: String,
+        chain_id: String,
+        #[serde(default)]
+        prediction: Option<AccountPrediction>,
+    },
+    /// Rejects future handshakes over this connection. Open channels are not affected.
+    RemoveHost {
+        connection_id: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -52,10 +157,97 @@ pub enum ExecuteMsg {
 pub enum QueryMsg {
     // Returns current admin
     Admins {},
-    // Shows all open accounts (incl. remote info)
-    ListAccounts {},
-    // Get account for one channel
-    Account { channel_id: String },
+    // Shows the open accounts (incl. remote info) by channel and account id,
+    // at most `limit` (default 10, max 30) after `start_after`
+    ListAccounts {
+        start_after: Option<ChannelAccount>,
+        limit: Option<u32>,
+        #[serde(default)]
+        filter: AccountFilter,
+    },
+    // Finds the account with this address on the remote chain
+    AccountByRemoteAddr {
+        remote_addr: String,
+    },
+    // Get account for one channel, by default the one created on connect
+    Account {
+        channel_id: String,
+        #[serde(default)]
+        account_id: u64,
+    },
+    // Shows the accounts of closed channels, like `ListAccounts`
+    ListClosedAccounts {
+        start_after: Option<ChannelAccount>,
+        limit: Option<u32>,
+    },
+    // Get an account of a closed channel, by channel id only
+    ClosedAccount {
+        channel_id: String,
+        #[serde(default)]
+        account_id: u64,
+    },
+    // Shows all trusted hosts
+    ListHosts {},
+    // Shows the version and metadata negotiated for a channel
+    Channel {
+        channel_id: String,
+    },
+}
+
+/// Identifies an account by its channel
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ChannelAccount {
+    pub channel_id: String,
+    pub account_id: u64,
+}
+
+/// Narrows `QueryMsg::ListAccounts`, all set conditions must hold
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct AccountFilter {
+    /// Only accounts whose remote address is not known yet
+    #[serde(default)]
+    pub missing_remote_addr: bool,
+    /// Only accounts whose balance was not updated for this many seconds
+    pub stale_for: Option<u64>,
+    /// Only accounts on the host of this chain
+    pub chain_id: Option<String>,
+}
+
+impl AccountFilter {
+    pub fn matches(&self, account: &AccountData, now: Timestamp) -> bool {
+        if self.missing_remote_addr && account.remote_addr.is_some() {
+            return false;
+        }
+        if let Some(seconds) = self.stale_for {
+            if now
+                .seconds()
+                .saturating_sub(account.last_update_time.seconds())
+                < seconds
+            {
+                return false;
+            }
+        }
+        match &self.chain_id {
+            Some(chain_id) => account.chain_id == *chain_id,
+            None => true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ChannelResponse {
+    pub version: String,
+    pub encoding: Encoding,
+    /// The account options agreed with the host, if the channel was opened with metadata
+    pub metadata: Option<ChannelMetadata>,
+    pub alias: Option<String>,
+    pub connection_id: String,
+    pub counterparty_port: String,
+    pub counterparty_channel_id: String,
+    /// The channel that took over from this one
+    pub replaced_by: Option<String>,
+    /// The channel this one is about to replace, until the host confirms the account
+    pub replacing: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -63,6 +255,30 @@ pub struct AdminResponse {
     pub admins: Vec<String>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ListHostsResponse {
+    pub hosts: Vec<HostInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HostInfo {
+    pub connection_id: String,
+    pub port_id: String,
+    pub chain_id: String,
+    pub prediction: Option<AccountPrediction>,
+}
+
+impl HostInfo {
+    pub fn convert(connection_id: String, input: RemoteHost) -> Self {
+        HostInfo {
+            connection_id,
+            port_id: input.port_id,
+            chain_id: input.chain_id,
+            prediction: input.prediction,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ListAccountsResponse {
     pub accounts: Vec<AccountInfo>,
@@ -71,21 +287,70 @@ pub struct ListAccountsResponse {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct AccountInfo {
     pub channel_id: String,
+    pub account_id: u64,
     /// last block balance was updated (0 is never)
     pub last_update_time: Timestamp,
     /// in normal cases, it should be set, but there is a delay between binding
     /// the channel and making a query and in that time it is empty
     pub remote_addr: Option<String>,
     pub remote_balance: Vec<Coin>,
+    pub label: Option<String>,
+    pub chain_id: String,
+    /// Why the host could not tell us the remote address, if it failed
+    pub remote_addr_error: Option<String>,
+    /// The predicted remote address, if the host reported a different one
+    pub prediction_mismatch: Option<String>,
+    /// The channel's alias
+    pub alias: Option<String>,
+    pub connection_id: String,
+    pub counterparty_port: String,
+    pub counterparty_channel_id: String,
 }
 
 impl AccountInfo {
-    pub fn convert(channel_id: String, input: AccountData) -> Self {
+    pub fn convert(
+        channel_id: String,
+        account_id: u64,
+        input: AccountData,
+        channel: Option<ChannelInfo>,
+    ) -> Self {
+        let channel = channel.as_ref();
+        let field = |f: fn(&ChannelInfo) -> &String| channel.map(f).cloned().unwrap_or_default();
         AccountInfo {
+            connection_id: field(|c| &c.connection_id),
+            counterparty_port: field(|c| &c.counterparty_port),
+            counterparty_channel_id: field(|c| &c.counterparty_channel_id),
+            alias: channel.and_then(|c| c.alias.clone()),
             channel_id,
+            account_id,
             last_update_time: input.last_update_time,
             remote_addr: input.remote_addr,
             remote_balance: input.remote_balance,
+            label: input.label,
+            chain_id: input.chain_id,
+            remote_addr_error: input.remote_addr_error,
+            prediction_mismatch: input.prediction_mismatch,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ListClosedAccountsResponse {
+    pub accounts: Vec<ClosedAccountInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ClosedAccountInfo {
+    pub closed_at: Timestamp,
+    /// The account as it was when the channel closed
+    pub account: AccountInfo,
+}
+
+impl ClosedAccountInfo {
+    pub fn convert(channel_id: String, account_id: u64, input: ClosedAccount) -> Self {
+        ClosedAccountInfo {
+            closed_at: input.closed_at,
+            account: AccountInfo::convert(channel_id, account_id, input.account, input.channel),
         }
     }
 }
@@ -98,6 +363,12 @@ pub struct AccountResponse {
     /// the channel and making a query and in that time it is empty
     pub remote_addr: Option<String>,
     pub remote_balance: Vec<Coin>,
+    pub label: Option<String>,
+    pub chain_id: String,
+    /// Why the host could not tell us the remote address, if it failed
+    pub remote_addr_error: Option<String>,
+    /// The predicted remote address, if the host reported a different one
+    pub prediction_mismatch: Option<String>,
 }
 
 impl From<AccountData> for AccountResponse {
@@ -106,6 +377,10 @@ impl From<AccountData> for AccountResponse {
             last_update_time: input.last_update_time,
             remote_addr: input.remote_addr,
             remote_balance: input.remote_balance,
+            label: input.label,
+            chain_id: input.chain_id,
+            remote_addr_error: input.remote_addr_error,
+            prediction_mismatch: input.prediction_mismatch,
         }
     }
 }
@@ -1,8 +1,15 @@
+use bech32::{FromBase32, ToBase32, Variant};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Coin, Timestamp};
+use cosmwasm_std::{
+    instantiate2_address, CanonicalAddr, Coin, HexBinary, StdError, StdResult, Storage, Timestamp,
+};
 use cw1_whitelist::state::AdminList;
-use cw_storage_plus::{Item, Map};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
+use simple_ica::{
+    account_salt, lookup_version, ChannelMetadata, Encoding, ProtocolVersion, IBC_APP_VERSION,
+};
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
 pub struct AccountData {
@@ -10,12 +17,220 @@ pub struct AccountData {
     pub last_update_time: Timestamp,
     /// In normal cases, it should be set, but there is a delay between binding
     /// the channel and making a query and in that time it is empty.
+    /// Hosts registered with an `AccountPrediction` fill it in on connect.
     ///
     /// Since we do not have a way to validate the remote address format, this
     /// must not be of type `Addr`.
     pub remote_addr: Option<String>,
     pub remote_balance: Vec<Coin>,
+    /// Set for accounts created with `ExecuteMsg::CreateAccount`
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Chain id of the registered host, empty if the connection had none
+    #[serde(default)]
+    pub chain_id: String,
+    /// Why the last `WhoAmI` failed, cleared once the host answers
+    #[serde(default)]
+    pub remote_addr_error: Option<String>,
+    /// `WhoAmI` packets resent after a timeout since the last refresh
+    #[serde(default)]
+    pub who_am_i_retries: u32,
+    /// Set once the host answered the last `WhoAmI`, a predicted `remote_addr`
+    /// is not confirmed yet
+    #[serde(default)]
+    pub confirmed: bool,
+    /// The predicted address, if the host reported a different one
+    #[serde(default)]
+    pub prediction_mismatch: Option<String>,
+}
+
+impl AccountData {
+    /// Takes the address the host reported, it has the last word over our
+    /// prediction. Returns the prediction if it was wrong.
+    pub fn confirm(&mut self, remote_addr: &str) -> Option<String> {
+        self.confirmed = true;
+        match self.remote_addr.replace(remote_addr.to_string()) {
+            Some(predicted) if predicted != remote_addr => {
+                self.prediction_mismatch = Some(predicted.clone());
+                Some(predicted)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChannelInfo {
+    /// The protocol version negotiated during the handshake
+    pub version: String,
+    /// The wire encoding that comes with this version
+    pub encoding: Encoding,
+    /// The metadata the host answered with, if the channel was opened with any
+    #[serde(default)]
+    pub metadata: Option<ChannelMetadata>,
+    /// Our side of the connection to the host
+    #[serde(default)]
+    pub connection_id: String,
+    /// Port of the host contract
+    #[serde(default)]
+    pub counterparty_port: String,
+    #[serde(default)]
+    pub counterparty_channel_id: String,
+    /// A name to use instead of the channel id, see `CHANNEL_ALIASES`
+    #[serde(default)]
+    pub alias: Option<String>,
+    /// The channel that took over the accounts and alias of this one
+    #[serde(default)]
+    pub replaced_by: Option<String>,
+}
+
+/// What is left of an account once its channel closed. The account itself and
+/// its funds stay on the host chain.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClosedAccount {
+    pub closed_at: Timestamp,
+    /// The account as it was on close
+    pub account: AccountData,
+    /// The channel as it was on close, `None` if it never connected
+    pub channel: Option<ChannelInfo>,
+}
+
+/// The host contract we expect on the other side of a connection
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RemoteHost {
+    /// Port of the host contract, e.g. `wasm.<host address>`
+    pub port_id: String,
+    /// Chain id of the remote chain, only informational
+    pub chain_id: String,
+    /// Lets us compute the account addresses before the host reports them
+    #[serde(default)]
+    pub prediction: Option<AccountPrediction>,
+}
+
+/// What we need to know about the host chain to compute the `Instantiate2`
+/// address of an account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccountPrediction {
+    /// The connection id on the host chain, part of the salt
+    pub counterparty_connection_id: String,
+    /// Checksum of the reflect code the host instantiates
+    pub checksum: HexBinary,
+}
+
+impl RemoteHost {
+    /// Ensures `predict_account` can work with this host
+    pub fn validate(&self) -> StdResult<()> {
+        if let Some(prediction) = &self.prediction {
+            decode_port_address(&self.port_id)?;
+            if prediction.checksum.len() != 32 {
+                return Err(StdError::generic_err("Checksum must be 32 bytes"));
+            }
+        }
+        Ok(())
+    }
+
+    /// The address the host will instantiate this account at, if we can tell.
+    /// `controller_port` is our own port.
+    pub fn predict_account(
+        &self,
+        controller_port: &str,
+        account_id: u64,
+    ) -> StdResult<Option<String>> {
+        let prediction = match &self.prediction {
+            Some(prediction) => prediction,
+            None => return Ok(None),
+        };
+        let (prefix, creator) = decode_port_address(&self.port_id)?;
+        let salt = account_salt(
+            &prediction.counterparty_connection_id,
+            controller_port,
+            account_id,
+        );
+        let address = instantiate2_address(prediction.checksum.as_slice(), &creator, &salt)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        let address = bech32::encode(&prefix, address.as_slice().to_base32(), Variant::Bech32)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        Ok(Some(address))
+    }
+}
+
+/// Splits a `wasm.<address>` port into the bech32 prefix and the raw contract address
+fn decode_port_address(port_id: &str) -> StdResult<(String, CanonicalAddr)> {
+    let address = port_id
+        .strip_prefix("wasm.")
+        .ok_or_else(|| StdError::generic_err(format!("{} is not a wasm port", port_id)))?;
+    let (prefix, data, _) =
+        bech32::decode(address).map_err(|e| StdError::generic_err(e.to_string()))?;
+    let bytes = Vec::<u8>::from_base32(&data).map_err(|e| StdError::generic_err(e.to_string()))?;
+    Ok((prefix, bytes.into()))
 }
 
 pub const ADMIN: Item<AdminList> = Item::new("admin");
-pub const ACCOUNTS: Map<&str, AccountData> = Map::new("accounts");
+/// Trusted hosts by local connection id, handshakes with anyone else are rejected
+pub const HOSTS: Map<&str, RemoteHost> = Map::new("hosts");
+
+pub struct AccountIndexes<'a> {
+    /// Accounts by remote address, the ones still waiting for it under ""
+    pub remote_addr: MultiIndex<'a, String, AccountData, (&'a str, u64)>,
+    /// Accounts by the chain id of their host
+    pub chain_id: MultiIndex<'a, String, AccountData, (&'a str, u64)>,
+}
+
+impl<'a> IndexList<AccountData> for AccountIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<AccountData>> + '_> {
+        let v: Vec<&dyn Index<AccountData>> = vec![&self.remote_addr, &self.chain_id];
+        Box::new(v.into_iter())
+    }
+}
+
+/// Remote accounts by channel and account id, `DEFAULT_ACCOUNT_ID` is created on connect
+pub fn accounts<'a>() -> IndexedMap<'a, (&'a str, u64), AccountData, AccountIndexes<'a>> {
+    let indexes = AccountIndexes {
+        remote_addr: MultiIndex::new(
+            |d: &AccountData| d.remote_addr.clone().unwrap_or_default(),
+            "channel_accounts",
+            "channel_accounts__remote_addr",
+        ),
+        chain_id: MultiIndex::new(
+            |d: &AccountData| d.chain_id.clone(),
+            "channel_accounts",
+            "channel_accounts__chain_id",
+        ),
+    };
+    IndexedMap::new("channel_accounts", indexes)
+}
+pub const CHANNELS: Map<&str, ChannelInfo> = Map::new("channels");
+/// Accounts of closed channels by channel and account id. Channel ids are never
+/// reused, so these are kept for good.
+pub const CLOSED_ACCOUNTS: Map<(&str, u64), ClosedAccount> = Map::new("closed_accounts");
+/// Old channels by the channel replacing them, until the host confirms over the
+/// new channel that it leads to the same account
+pub const REPLACEMENTS: Map<&str, String> = Map::new("channel_replacements");
+/// Channel ids by alias. Aliases never start with `channel-`, so they cannot be
+/// mistaken for a channel id.
+pub const CHANNEL_ALIASES: Map<&str, String> = Map::new("channel_aliases");
+
+/// Accepts a channel id or the alias of one
+pub fn resolve_channel(storage: &dyn Storage, channel: &str) -> StdResult<String> {
+    match CHANNEL_ALIASES.may_load(storage, channel)? {
+        Some(channel_id) => Ok(channel_id),
+        None => Ok(channel.to_string()),
+    }
+}
+
+/// Whether `alias` can name a channel
+pub fn is_valid_alias(alias: &str) -> bool {
+    !alias.is_empty() && !alias.starts_with("channel-")
+}
+
+/// Channels without a recorded version speak the original json protocol
+pub fn channel_version(
+    storage: &dyn Storage,
+    channel_id: &str,
+) -> StdResult<&'static ProtocolVersion> {
+    let version = match CHANNELS.may_load(storage, channel_id)? {
+        Some(info) => info.version,
+        None => IBC_APP_VERSION.to_string(),
+    };
+    lookup_version(&version).map_err(|e| StdError::generic_err(e.to_string()))
+}
@@ -3,7 +3,10 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use simple_ica_host::msg::{InstantiateMsg, QueryMsg};
+use simple_ica_host::msg::{
+    AccountByAddressResponse, AllowlistResponse, ChannelResponse, ConfigResponse, ExecuteMsg,
+    InstantiateMsg, QueryMsg, RecoveryResponse,
+};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -11,6 +14,12 @@ fn main() {
     create_dir_all(&out_dir).unwrap();
     remove_schemas(&out_dir).unwrap();
 
+    export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(ConfigResponse), &out_dir);
+    export_schema(&schema_for!(AllowlistResponse), &out_dir);
+    export_schema(&schema_for!(RecoveryResponse), &out_dir);
+    export_schema(&schema_for!(ChannelResponse), &out_dir);
+    export_schema(&schema_for!(AccountByAddressResponse), &out_dir);
 }
@@ -0,0 +1,86 @@
+//! The contracts the host can use as remote accounts.
+//!
+//! Every account type has its own instantiate and execute messages. The type is
+//! picked per channel when it connects and stays with each account it creates,
+//! so accounts keep working after the host switches to another type.
+
+use std::str::FromStr;
+
+use cosmwasm_std::{to_binary, Addr, Binary, CosmosMsg, CustomMsg, StdResult, WasmMsg};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ContractError;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountType {
+    /// `cw1-whitelist` with the host as its only, immutable admin
+    #[default]
+    Cw1Whitelist,
+    /// The cosmwasm `reflect` contract, owned by the host that instantiates it
+    Reflect,
+}
+
+/// The `InstantiateMsg` of the `reflect` contract, the sender becomes the owner
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+struct ReflectInstantiateMsg {}
+
+/// The part of the `reflect` contract's `ExecuteMsg` we use
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum ReflectExecuteMsg<T> {
+    ReflectMsg { msgs: Vec<CosmosMsg<T>> },
+}
+
+impl AccountType {
+    /// The name used in the channel metadata, same as in json
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountType::Cw1Whitelist => "cw1_whitelist",
+            AccountType::Reflect => "reflect",
+        }
+    }
+
+    /// Instantiates the account with the host in control
+    pub fn instantiate_msg(&self, host: &Addr) -> StdResult<Binary> {
+        match self {
+            AccountType::Cw1Whitelist => to_binary(&cw1_whitelist::msg::InstantiateMsg {
+                admins: vec![host.to_string()],
+                mutable: false,
+            }),
+            AccountType::Reflect => to_binary(&ReflectInstantiateMsg {}),
+        }
+    }
+
+    /// Makes the account at `account` execute the messages
+    pub fn execute_msg<T: CustomMsg>(
+        &self,
+        account: &Addr,
+        msgs: Vec<CosmosMsg<T>>,
+    ) -> StdResult<WasmMsg> {
+        let msg = match self {
+            AccountType::Cw1Whitelist => {
+                to_binary(&cw1_whitelist::msg::ExecuteMsg::Execute { msgs })?
+            }
+            AccountType::Reflect => to_binary(&ReflectExecuteMsg::ReflectMsg { msgs })?,
+        };
+        Ok(WasmMsg::Execute {
+            contract_addr: account.to_string(),
+            msg,
+            funds: vec![],
+        })
+    }
+}
+
+impl FromStr for AccountType {
+    type Err = ContractError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "cw1_whitelist" => Ok(AccountType::Cw1Whitelist),
+            "reflect" => Ok(AccountType::Reflect),
+            _ => Err(ContractError::UnknownAccountType(name.to_string())),
+        }
+    }
+}
@@ -1,295 +1,1640 @@
 use cosmwasm_std::{
-    entry_point, from_slice, to_binary, wasm_execute, BankMsg, CosmosMsg, Deps, DepsMut, Empty,
-    Env, Event, Ibc3ChannelOpenResponse, IbcBasicResponse, IbcChannelCloseMsg,
-    IbcChannelConnectMsg, IbcChannelOpenMsg, IbcChannelOpenResponse, IbcPacketAckMsg,
+    entry_point, to_binary, Addr, BankMsg, Binary, Coin, CosmosMsg, CustomMsg, Deps, DepsMut,
+    Empty, Env, Event, Ibc3ChannelOpenResponse, IbcBasicResponse, IbcChannel, IbcChannelCloseMsg,
+    IbcChannelConnectMsg, IbcChannelOpenMsg, IbcChannelOpenResponse, IbcMsg, IbcPacketAckMsg,
     IbcPacketReceiveMsg, IbcPacketTimeoutMsg, IbcReceiveResponse, MessageInfo, Order,
-    QueryResponse, Reply, Response, StdResult, SubMsg, Uint128, WasmMsg,
+    QueryResponse, Reply, Response, StdResult, Storage, SubMsg, SubMsgResult, Uint128, WasmMsg,
 };
 
+use cw_storage_plus::{Bound, Map};
 use cw_utils::parse_reply_instantiate_data;
 use simple_ica::{
-    check_order, check_version, BalancesResponse, DispatchResponse, PacketMsg, StdAck,
-    WhoAmIResponse, IBC_APP_VERSION,
+    account_salt, channel_open_version, check_order, connected_metadata, connected_version,
+    BalancesResponse, ChannelMetadata, CreateAccountResponse, DispatchMode, DispatchResponse,
+    Encoding, MigrateAccountResponse, MsgOutcome, OutflowLimit, PacketMsg, ProtocolVersion, StdAck,
+    WhoAmIResponse, DEFAULT_ACCOUNT_ID, FEATURE_BEST_EFFORT_DISPATCH, FEATURE_CUSTOM_MSGS,
+    FEATURE_MIGRATE_ACCOUNT, FEATURE_MSG_POLICIES, FEATURE_OUTFLOW_LIMITS, FEATURE_RETURN_ADDRESS,
+    FEATURE_SUB_ACCOUNTS,
 };
 // when I import below, it says recompile.
 // use simple_ica_controller::msg::ExecuteMsg;
 
+use crate::account::AccountType;
+use crate::custom::into_host_msg;
 use crate::error::ContractError;
 use crate::msg::{
-    AccountInfo, AccountResponse, InstantiateMsg, ListAccountsResponse, QueryMsg, ReflectExecuteMsg,
+    AccountByAddressResponse, AccountEscrow, AccountInfo, AccountKey, AccountResponse,
+    AllowlistResponse, ChannelAccount, ChannelResponse, ConfigResponse, ExecuteMsg, InstantiateMsg,
+    ListAccountsResponse, QueryMsg, RecoveryResponse,
+};
+use crate::outflow::{
+    check_countable, check_tightened, outgoing_funds, record_outflow, release_outflow,
+    validate_limits,
+};
+use crate::policy::{check_msgs, validate_rules};
+use crate::spam::{check_new_account, count_packet, pay_for_account, validate_rate_limit};
+use crate::state::{
+    channel_account, channel_version, controller_address, controller_channel, AccountStats,
+    ChannelInfo, ClosePolicy, Config, DispatchResults, MigrationStatus, PendingAccount,
+    PendingMigration, RateLimit, SpamLimits, ACCOUNTS, ACCOUNT_LIMITS, ACCOUNT_OWNERS,
+    ACCOUNT_STATS, ACCOUNT_TYPES, ALLOWED_CONNECTIONS, ALLOWED_PORTS, BLOCKED_MSGS, CHANNELS,
+    CONFIG, DEPOSITS, ESCROW, MIGRATIONS, NEXT_REPLY_ID, PENDING, PENDING_MIGRATIONS, RESULTS,
+    RETURN_ADDRESSES,
 };
-use crate::state::{Config, ACCOUNTS, CONFIG, PENDING, RESULTS};
 
 pub const RECEIVE_DISPATCH_ID: u64 = 1234;
+pub const RECEIVE_BEST_EFFORT_ID: u64 = 1235;
+/// First reply id of account instantiations and migrations, each one counts up from here
 pub const INIT_CALLBACK_ID: u64 = 7890;
 pub const ENOUGH_FUND: Uint128 = Uint128::new(123456789);
+/// ICS-20 transfers returning funds on channel close time out after one hour
+pub const RETURN_TRANSFER_LIFETIME: u64 = 60 * 60;
 
 #[entry_point]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
 ) -> StdResult<Response> {
     // we store the reflect_id for creating accounts later
     let cfg = Config {
+        owner: info.sender,
         reflect_code_id: msg.reflect_code_id,
+        account_type: msg.account_type,
+        channels_paused: false,
+        packets_paused: false,
+        close_policy: ClosePolicy::Escrow,
+        outflow_limits: vec![],
+        blocked_msgs: vec![],
+        spam_limits: SpamLimits::default(),
     };
     CONFIG.save(deps.storage, &cfg)?;
 
     Ok(Response::new())
 }
 
+#[entry_point]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::UpdateConfig {
+            reflect_code_id,
+            account_type,
+            channels_paused,
+            packets_paused,
+            close_policy,
+        } => execute_update_config(
+            deps,
+            info,
+            reflect_code_id,
+            account_type,
+            channels_paused,
+            packets_paused,
+            close_policy,
+        ),
+        ExecuteMsg::TransferOwnership { new_owner } => {
+            execute_transfer_ownership(deps, env, info, new_owner)
+        }
+        ExecuteMsg::UpdateAllowlist {
+            add_connections,
+            remove_connections,
+            add_ports,
+            remove_ports,
+        } => execute_update_allowlist(
+            deps,
+            env,
+            info,
+            add_connections,
+            remove_connections,
+            add_ports,
+            remove_ports,
+        ),
+        ExecuteMsg::MigrateAccounts {
+            new_code_id,
+            start_after,
+            limit,
+        } => execute_migrate_accounts(deps, env, info, new_code_id, start_after, limit),
+        ExecuteMsg::UpdateOutflowLimits { limits } => {
+            execute_update_outflow_limits(deps, info, limits)
+        }
+        ExecuteMsg::UpdateMsgPolicy {
+            account,
+            blocked_msgs,
+        } => execute_update_msg_policy(deps, info, account, blocked_msgs),
+        ExecuteMsg::UpdateSpamLimits {
+            max_accounts_per_connection,
+            account_fee,
+            treasury,
+            rate_limit,
+        } => execute_update_spam_limits(
+            deps,
+            info,
+            max_accounts_per_connection,
+            account_fee,
+            treasury,
+            rate_limit,
+        ),
+        ExecuteMsg::Deposit {
+            connection_id,
+            counterparty_port,
+        } => execute_deposit(deps, info, connection_id, counterparty_port),
+        ExecuteMsg::Withdraw {
+            connection_id,
+            counterparty_port,
+        } => execute_withdraw(deps, info, connection_id, counterparty_port),
+    }
+}
+
+pub fn execute_update_spam_limits(
+    deps: DepsMut,
+    info: MessageInfo,
+    max_accounts_per_connection: Option<u64>,
+    account_fee: Option<Coin>,
+    treasury: Option<String>,
+    rate_limit: Option<RateLimit>,
+) -> Result<Response, ContractError> {
+    let mut cfg = load_config_as_owner(deps.as_ref(), &info)?;
+    if let Some(limit) = &rate_limit {
+        validate_rate_limit(limit)?;
+    }
+    cfg.spam_limits = SpamLimits {
+        max_accounts_per_connection,
+        account_fee,
+        treasury: treasury.map(|t| deps.api.addr_validate(&t)).transpose()?,
+        rate_limit,
+    };
+    CONFIG.save(deps.storage, &cfg)?;
+
+    Ok(Response::new().add_attribute("action", "update_spam_limits"))
+}
+
+pub fn execute_deposit(
+    deps: DepsMut,
+    info: MessageInfo,
+    connection_id: String,
+    counterparty_port: String,
+) -> Result<Response, ContractError> {
+    if info.funds.is_empty() {
+        return Err(ContractError::EmptyDeposit);
+    }
+    let key = (
+        connection_id.as_str(),
+        counterparty_port.as_str(),
+        &info.sender,
+    );
+    let mut deposit = DEPOSITS.may_load(deps.storage, key)?.unwrap_or_default();
+    add_coins(&mut deposit, &info.funds);
+    DEPOSITS.save(deps.storage, key, &deposit)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "deposit")
+        .add_attribute("connection_id", connection_id)
+        .add_attribute("counterparty_port", counterparty_port)
+        .add_attribute("funds", coins_to_string(&info.funds)))
+}
+
+pub fn execute_withdraw(
+    deps: DepsMut,
+    info: MessageInfo,
+    connection_id: String,
+    counterparty_port: String,
+) -> Result<Response, ContractError> {
+    let key = (
+        connection_id.as_str(),
+        counterparty_port.as_str(),
+        &info.sender,
+    );
+    let deposit = DEPOSITS
+        .may_load(deps.storage, key)?
+        .ok_or(ContractError::NoDeposit)?;
+    DEPOSITS.remove(deps.storage, key);
+
+    Ok(Response::new()
+        .add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: deposit.clone(),
+        })
+        .add_attribute("action", "withdraw")
+        .add_attribute("connection_id", connection_id)
+        .add_attribute("counterparty_port", counterparty_port)
+        .add_attribute("funds", coins_to_string(&deposit)))
+}
+
+pub fn execute_update_msg_policy(
+    deps: DepsMut,
+    info: MessageInfo,
+    account: Option<AccountKey>,
+    blocked_msgs: Vec<String>,
+) -> Result<Response, ContractError> {
+    let mut cfg = load_config_as_owner(deps.as_ref(), &info)?;
+    validate_rules(&blocked_msgs)?;
+    let res = Response::new()
+        .add_attribute("action", "update_msg_policy")
+        .add_attribute("blocked_msgs", blocked_msgs.join(","));
+    match account {
+        Some(account) => {
+            let key = (
+                account.connection_id.as_str(),
+                account.counterparty_port.as_str(),
+                account.account_id,
+            );
+            BLOCKED_MSGS.save(deps.storage, key, &blocked_msgs)?;
+            Ok(res
+                .add_attribute("connection_id", account.connection_id.as_str())
+                .add_attribute("counterparty_port", account.counterparty_port.as_str())
+                .add_attribute("account_id", account.account_id.to_string()))
+        }
+        None => {
+            cfg.blocked_msgs = blocked_msgs;
+            CONFIG.save(deps.storage, &cfg)?;
+            Ok(res)
+        }
+    }
+}
+
+pub fn execute_update_outflow_limits(
+    deps: DepsMut,
+    info: MessageInfo,
+    limits: Vec<OutflowLimit>,
+) -> Result<Response, ContractError> {
+    let mut cfg = load_config_as_owner(deps.as_ref(), &info)?;
+    validate_limits(&limits)?;
+    cfg.outflow_limits = limits;
+    CONFIG.save(deps.storage, &cfg)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_outflow_limits")
+        .add_attribute("limits", cfg.outflow_limits.len().to_string()))
+}
+
+const DEFAULT_MIGRATE_LIMIT: u32 = 10;
+const MAX_MIGRATE_LIMIT: u32 = 30;
+
+/// Migrates a page of all accounts, failures are recorded per account
+/// and do not stop the others
+pub fn execute_migrate_accounts(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    new_code_id: u64,
+    start_after: Option<AccountKey>,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    load_config_as_owner(deps.as_ref(), &info)?;
+
+    let limit = limit
+        .unwrap_or(DEFAULT_MIGRATE_LIMIT)
+        .min(MAX_MIGRATE_LIMIT) as usize;
+    let start = start_after.as_ref().map(|key| {
+        Bound::exclusive((
+            key.connection_id.as_str(),
+            key.counterparty_port.as_str(),
+            key.account_id,
+        ))
+    });
+    let accounts = ACCOUNTS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut res = Response::new()
+        .add_attribute("action", "migrate_accounts")
+        .add_attribute("code_id", new_code_id.to_string())
+        .add_attribute("count", accounts.len().to_string());
+    if let Some(((connection_id, counterparty_port, account_id), _)) = accounts.last() {
+        res = res
+            .add_attribute("last_connection_id", connection_id)
+            .add_attribute("last_counterparty_port", counterparty_port)
+            .add_attribute("last_account_id", account_id.to_string());
+    }
+    for ((connection_id, counterparty_port, account_id), account) in accounts {
+        let pending = PendingMigration {
+            connection_id,
+            counterparty_port,
+            account_id,
+            code_id: new_code_id,
+            channel_id: None,
+        };
+        res = res.add_submessage(migrate_account_msg(deps.storage, account, pending)?);
+    }
+    Ok(res)
+}
+
+/// Loads the config, failing unless the sender is the owner
+fn load_config_as_owner(deps: Deps, info: &MessageInfo) -> Result<Config, ContractError> {
+    let cfg = CONFIG.load(deps.storage)?;
+    if cfg.owner != info.sender {
+        return Err(ContractError::Unauthorized);
+    }
+    Ok(cfg)
+}
+
+pub fn execute_update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    reflect_code_id: Option<u64>,
+    account_type: Option<AccountType>,
+    channels_paused: Option<bool>,
+    packets_paused: Option<bool>,
+    close_policy: Option<ClosePolicy>,
+) -> Result<Response, ContractError> {
+    let mut cfg = load_config_as_owner(deps.as_ref(), &info)?;
+
+    if let Some(code_id) = reflect_code_id {
+        cfg.reflect_code_id = code_id;
+    }
+    if let Some(account_type) = account_type {
+        cfg.account_type = account_type;
+    }
+    if let Some(paused) = channels_paused {
+        cfg.channels_paused = paused;
+    }
+    if let Some(paused) = packets_paused {
+        cfg.packets_paused = paused;
+    }
+    if let Some(policy) = close_policy {
+        cfg.close_policy = policy;
+    }
+    CONFIG.save(deps.storage, &cfg)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_config")
+        .add_attribute("reflect_code_id", cfg.reflect_code_id.to_string())
+        .add_attribute("channels_paused", cfg.channels_paused.to_string())
+        .add_attribute("packets_paused", cfg.packets_paused.to_string())
+        .add_attribute("close_policy", policy_name(&cfg.close_policy)))
+}
+
+pub fn execute_transfer_ownership(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    new_owner: String,
+) -> Result<Response, ContractError> {
+    let mut cfg = load_config_as_owner(deps.as_ref(), &info)?;
+    cfg.owner = deps.api.addr_validate(&new_owner)?;
+    CONFIG.save(deps.storage, &cfg)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "transfer_ownership")
+        .add_attribute("owner", new_owner))
+}
+
+pub fn execute_update_allowlist(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    add_connections: Vec<String>,
+    remove_connections: Vec<String>,
+    add_ports: Vec<String>,
+    remove_ports: Vec<String>,
+) -> Result<Response, ContractError> {
+    load_config_as_owner(deps.as_ref(), &info)?;
+
+    for connection in &add_connections {
+        ALLOWED_CONNECTIONS.save(deps.storage, connection, &Empty {})?;
+    }
+    for connection in &remove_connections {
+        ALLOWED_CONNECTIONS.remove(deps.storage, connection);
+    }
+    for port in &add_ports {
+        ALLOWED_PORTS.save(deps.storage, port, &Empty {})?;
+    }
+    for port in &remove_ports {
+        ALLOWED_PORTS.remove(deps.storage, port);
+    }
+
+    Ok(Response::new().add_attribute("action", "update_allowlist"))
+}
+
 #[entry_point]
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<QueryResponse> {
     match msg {
-        QueryMsg::Account { channel_id } => to_binary(&query_account(deps, channel_id)?),
-        QueryMsg::ListAccounts {} => to_binary(&query_list_accounts(deps)?),
+        QueryMsg::Account {
+            channel_id,
+            account_id,
+        } => to_binary(&query_account(deps, channel_id, account_id)?),
+        QueryMsg::ControllerAccount {
+            connection_id,
+            counterparty_port,
+            account_id,
+        } => to_binary(&query_controller_account(
+            deps,
+            connection_id,
+            counterparty_port,
+            account_id,
+        )?),
+        QueryMsg::ListAccounts { start_after, limit } => {
+            to_binary(&query_list_accounts(deps, start_after, limit)?)
+        }
+        QueryMsg::AccountByAddress { address } => {
+            to_binary(&query_account_by_address(deps, address)?)
+        }
+        QueryMsg::Channel { channel_id } => to_binary(&query_channel(deps, channel_id)?),
+        QueryMsg::Config {} => to_binary(&query_config(deps)?),
+        QueryMsg::Allowlist {} => to_binary(&query_allowlist(deps)?),
+        QueryMsg::Recovery {
+            connection_id,
+            counterparty_port,
+        } => to_binary(&query_recovery(deps, connection_id, counterparty_port)?),
+    }
+}
+
+pub fn query_recovery(
+    deps: Deps,
+    connection_id: String,
+    counterparty_port: String,
+) -> StdResult<RecoveryResponse> {
+    let controller = (connection_id.as_str(), counterparty_port.as_str());
+    let escrow = ESCROW
+        .prefix(controller)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(account_id, funds)| AccountEscrow { account_id, funds }))
+        .collect::<StdResult<_>>()?;
+    let mut deposit = vec![];
+    for item in DEPOSITS
+        .prefix(controller)
+        .range(deps.storage, None, None, Order::Ascending)
+    {
+        add_coins(&mut deposit, &item?.1);
+    }
+    Ok(RecoveryResponse {
+        return_address: RETURN_ADDRESSES.may_load(deps.storage, controller)?,
+        escrow,
+        deposit,
+    })
+}
+
+pub fn query_allowlist(deps: Deps) -> StdResult<AllowlistResponse> {
+    let connections = ALLOWED_CONNECTIONS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    let ports = ALLOWED_PORTS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    Ok(AllowlistResponse { connections, ports })
+}
+
+pub fn query_channel(deps: Deps, channel_id: String) -> StdResult<ChannelResponse> {
+    let info = CHANNELS.load(deps.storage, &channel_id)?;
+    Ok(ChannelResponse {
+        version: info.version,
+        encoding: info.encoding,
+        connection_id: info.connection_id,
+        counterparty_port: info.counterparty_port,
+        account_type: info.account_type,
+        metadata: info.metadata,
+    })
+}
+
+pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
+    let cfg = CONFIG.load(deps.storage)?;
+    Ok(ConfigResponse {
+        owner: cfg.owner.into(),
+        reflect_code_id: cfg.reflect_code_id,
+        account_type: cfg.account_type,
+        channels_paused: cfg.channels_paused,
+        packets_paused: cfg.packets_paused,
+        close_policy: cfg.close_policy,
+        outflow_limits: cfg.outflow_limits,
+        blocked_msgs: cfg.blocked_msgs,
+        spam_limits: cfg.spam_limits,
+    })
+}
+
+pub fn query_account(
+    deps: Deps,
+    channel_id: String,
+    account_id: u64,
+) -> StdResult<AccountResponse> {
+    match CHANNELS.may_load(deps.storage, &channel_id)? {
+        Some(info) => {
+            query_controller_account(deps, info.connection_id, info.counterparty_port, account_id)
+        }
+        None => Ok(AccountResponse::default()),
     }
 }
 
-pub fn query_account(deps: Deps, channel_id: String) -> StdResult<AccountResponse> {
-    let account = ACCOUNTS.load(deps.storage, &channel_id)?;
+pub fn query_controller_account(
+    deps: Deps,
+    connection_id: String,
+    counterparty_port: String,
+    account_id: u64,
+) -> StdResult<AccountResponse> {
+    let key = (
+        connection_id.as_str(),
+        counterparty_port.as_str(),
+        account_id,
+    );
+    let account = ACCOUNTS.may_load(deps.storage, key)?;
     Ok(AccountResponse {
-        account: Some(account.into()),
+        account: account.map(Into::into),
+        migration: MIGRATIONS.may_load(deps.storage, key)?,
+        outflow_limits: ACCOUNT_LIMITS
+            .may_load(deps.storage, key)?
+            .unwrap_or_default(),
+        blocked_msgs: BLOCKED_MSGS
+            .may_load(deps.storage, key)?
+            .unwrap_or_default(),
+        stats: ACCOUNT_STATS
+            .may_load(deps.storage, key)?
+            .unwrap_or_default(),
     })
 }
 
-pub fn query_list_accounts(deps: Deps) -> StdResult<ListAccountsResponse> {
-    let accounts = ACCOUNTS
-        .range(deps.storage, None, None, Order::Ascending)
-        .map(|item| {
-            let (channel_id, account) = item?;
-            Ok(AccountInfo {
+const DEFAULT_LIST_LIMIT: u32 = 10;
+const MAX_LIST_LIMIT: u32 = 30;
+
+/// Lists a page of the accounts of all open channels
+pub fn query_list_accounts(
+    deps: Deps,
+    start_after: Option<ChannelAccount>,
+    limit: Option<u32>,
+) -> StdResult<ListAccountsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT) as usize;
+    let start = start_after
+        .as_ref()
+        .map(|key| Bound::inclusive(key.channel_id.as_str()));
+    let mut accounts = vec![];
+    for item in CHANNELS.range(deps.storage, start, None, Order::Ascending) {
+        if accounts.len() >= limit {
+            break;
+        }
+        let (channel_id, info) = item?;
+        // a controller with several open channels is listed under its first one
+        if controller_channel(deps.storage, info.controller())?.as_ref() != Some(&channel_id) {
+            continue;
+        }
+        // continue after the last account of the channel we stopped at
+        let after = match &start_after {
+            Some(key) if key.channel_id == channel_id => Some(Bound::exclusive(key.account_id)),
+            _ => None,
+        };
+        let page = ACCOUNTS
+            .prefix(info.controller())
+            .range(deps.storage, after, None, Order::Ascending)
+            .take(limit - accounts.len());
+        for item in page {
+            let (account_id, account) = item?;
+            let key = info.account(account_id);
+            accounts.push(AccountInfo {
                 account: account.into(),
-                channel_id,
-            })
-        })
-        .collect::<StdResult<_>>()?;
+                account_id,
+                channel_id: channel_id.clone(),
+                connection_id: info.connection_id.clone(),
+                counterparty_port: info.counterparty_port.clone(),
+                controller: controller_address(&info.counterparty_port),
+                migration: MIGRATIONS.may_load(deps.storage, key)?,
+                stats: ACCOUNT_STATS
+                    .may_load(deps.storage, key)?
+                    .unwrap_or_default(),
+            });
+        }
+    }
     Ok(ListAccountsResponse { accounts })
 }
 
+/// Finds the controller of a reflect account
+pub fn query_account_by_address(
+    deps: Deps,
+    address: String,
+) -> StdResult<AccountByAddressResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let (connection_id, counterparty_port, account_id) =
+        ACCOUNT_OWNERS.load(deps.storage, &address)?;
+    let channel_id = controller_channel(deps.storage, (&connection_id, &counterparty_port))?;
+    Ok(AccountByAddressResponse {
+        controller: controller_address(&counterparty_port),
+        connection_id,
+        counterparty_port,
+        account_id,
+        channel_id,
+    })
+}
+
 #[entry_point]
-/// enforces ordering and versioing constraints
+/// enforces ordering and negotiates the protocol version
 pub fn ibc_channel_open(
-    _deps: DepsMut,
+    deps: DepsMut,
     _env: Env,
     msg: IbcChannelOpenMsg,
 ) -> Result<IbcChannelOpenResponse, ContractError> {
     let channel = msg.channel();
+    let cfg = CONFIG.load(deps.storage)?;
+    if cfg.channels_paused {
+        return Err(ContractError::ChannelsPaused);
+    }
+    check_allowlist(deps.as_ref(), channel)?;
+    // a controller without an account must be able to pay for its first one
+    let controller = (
+        channel.connection_id.as_str(),
+        channel.counterparty_endpoint.port_id.as_str(),
+    );
+    if !ACCOUNTS.has(
+        deps.storage,
+        (controller.0, controller.1, DEFAULT_ACCOUNT_ID),
+    ) {
+        check_new_account(deps.storage, &cfg.spam_limits, controller)?;
+    }
 
     check_order(&channel.order)?;
     // In ibcv3 we don't check the version string passed in the message
     // and only check the counterparty version.
-    if let Some(counter_version) = msg.counterparty_version() {
-        check_version(counter_version)?;
+    // We return the version we picked (which could be different than the counterparty proposal)
+    let version = channel_open_version(&msg)?;
+    // with metadata we answer with the account options we apply
+    let version = match ChannelMetadata::parse(&version)? {
+        Some(metadata) => accept_metadata(&cfg, metadata)?.to_version()?,
+        None => version,
+    };
+    Ok(Some(Ibc3ChannelOpenResponse { version }))
+}
+
+/// Checks the account options the controller asks for and fills in our defaults
+fn accept_metadata(
+    cfg: &Config,
+    mut metadata: ChannelMetadata,
+) -> Result<ChannelMetadata, ContractError> {
+    let account_type = match &metadata.account_type {
+        Some(name) => name.parse()?,
+        None => cfg.account_type,
+    };
+    let code_id = metadata.reflect_code_id.unwrap_or(cfg.reflect_code_id);
+    if code_id != cfg.reflect_code_id {
+        return Err(ContractError::CodeNotAllowed {
+            code_id,
+            expected: cfg.reflect_code_id,
+        });
+    }
+    metadata.account_type = Some(account_type.as_str().to_string());
+    metadata.reflect_code_id = Some(code_id);
+    Ok(metadata)
+}
+
+/// Only allowed connections and counterparty ports may open channels,
+/// unless the respective list is empty
+fn check_allowlist(deps: Deps, channel: &IbcChannel) -> Result<(), ContractError> {
+    let connection = &channel.connection_id;
+    if !is_allowed(deps, ALLOWED_CONNECTIONS, connection)? {
+        return Err(ContractError::ConnectionNotAllowed(connection.clone()));
     }
+    let port = &channel.counterparty_endpoint.port_id;
+    if !is_allowed(deps, ALLOWED_PORTS, port)? {
+        return Err(ContractError::PortNotAllowed(port.clone()));
+    }
+    Ok(())
+}
 
-    // We return the version we need (which could be different than the counterparty version)
-    Ok(Some(Ibc3ChannelOpenResponse {
-        version: IBC_APP_VERSION.to_string(),
-    }))
+fn is_allowed(deps: Deps, allowlist: Map<&str, Empty>, key: &str) -> StdResult<bool> {
+    let is_empty = allowlist
+        .keys(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_none();
+    Ok(is_empty || allowlist.has(deps.storage, key))
 }
 
 #[entry_point]
-/// once it's established, we create the reflect contract
+/// once it's established, we create the reflect contract,
+/// unless the controller already has one from an earlier channel
 pub fn ibc_channel_connect(
     deps: DepsMut,
     env: Env,
     msg: IbcChannelConnectMsg,
-) -> StdResult<IbcBasicResponse> {
+) -> Result<IbcBasicResponse, ContractError> {
+    let version = connected_version(&msg)?;
+    let metadata = connected_metadata(&msg)?;
     let channel = msg.channel();
     let cfg = CONFIG.load(deps.storage)?;
     let chan_id = &channel.endpoint.channel_id;
+    let account_type = match metadata.as_ref().and_then(|m| m.account_type.as_ref()) {
+        Some(name) => name.parse()?,
+        None => cfg.account_type,
+    };
+
+    // remember which protocol version this channel speaks and who is behind it
+    let info = ChannelInfo {
+        version: version.version.to_string(),
+        encoding: version.encoding,
+        connection_id: channel.connection_id.clone(),
+        counterparty_port: channel.counterparty_endpoint.port_id.clone(),
+        account_type,
+        metadata,
+    };
+    CHANNELS.save(deps.storage, chan_id, &info)?;
+
+    let res = IbcBasicResponse::new()
+        .add_attribute("action", "ibc_connect")
+        .add_attribute("channel_id", chan_id)
+        .add_attribute("version", version.version)
+        .add_event(Event::new("ibc").add_attribute("channel", "connect"));
+
+    // the controller gets its accounts back, along with anything we held in escrow
+    let default_account = info.account(DEFAULT_ACCOUNT_ID);
+    if let Some(account) = ACCOUNTS.may_load(deps.storage, default_account)? {
+        let res = res.add_attribute("account", account);
+        return release_escrow(deps, &info, res);
+    }
 
-    let init_msg = cw1_whitelist::msg::InstantiateMsg {
-        admins: vec![env.contract.address.into_string()],
-        mutable: false,
+    let pending = PendingAccount {
+        channel_id: chan_id.clone(),
+        account_id: DEFAULT_ACCOUNT_ID,
+        account_type: info.account_type,
     };
-    let msg = WasmMsg::Instantiate {
-        admin: None,
-        code_id: cfg.reflect_code_id,
-        msg: to_binary(&init_msg)?,
+    // the fee is paid once the account exists
+    check_new_account(deps.storage, &cfg.spam_limits, info.controller())?;
+    let label = format!("ibc-reflect-{}", chan_id);
+    let msg = create_account_msg(deps.storage, &cfg, &env, &info, pending, label)?;
+
+    Ok(res.add_submessage(msg))
+}
+
+/// Instantiates a new reflect contract controlled by the host.
+/// The address only depends on the controller identity, see `account_salt`.
+fn create_account_msg(
+    storage: &mut dyn Storage,
+    cfg: &Config,
+    env: &Env,
+    info: &ChannelInfo,
+    pending: PendingAccount,
+    label: String,
+) -> StdResult<SubMsg> {
+    let account_id = pending.account_id;
+    // we are the admin, so the account can be migrated
+    let msg = WasmMsg::Instantiate2 {
+        admin: Some(env.contract.address.to_string()),
+        code_id: info.reflect_code_id(cfg),
+        label,
+        msg: pending
+            .account_type
+            .instantiate_msg(&env.contract.address)?,
         funds: vec![],
-        label: format!("ibc-reflect-{}", chan_id),
+        salt: account_salt(&info.connection_id, &info.counterparty_port, account_id),
     };
-    let msg = SubMsg::reply_on_success(msg, INIT_CALLBACK_ID);
 
-    // store the channel id for the reply handler
-    PENDING.save(deps.storage, chan_id)?;
+    let reply_id = next_reply_id(storage)?;
+    PENDING.save(storage, reply_id, &pending)?;
+    Ok(SubMsg::reply_always(msg, reply_id))
+}
 
-    Ok(IbcBasicResponse::new()
-        .add_submessage(msg)
-        .add_attribute("action", "ibc_connect")
-        .add_attribute("channel_id", chan_id)
-        .add_event(Event::new("ibc").add_attribute("channel", "connect")))
+/// Migrates an account, the reply records the outcome whether it worked or not
+fn migrate_account_msg(
+    storage: &mut dyn Storage,
+    account: Addr,
+    pending: PendingMigration,
+) -> StdResult<SubMsg> {
+    let msg = WasmMsg::Migrate {
+        contract_addr: account.into(),
+        new_code_id: pending.code_id,
+        msg: to_binary(&Empty {})?,
+    };
+    let reply_id = next_reply_id(storage)?;
+    PENDING_MIGRATIONS.save(storage, reply_id, &pending)?;
+    Ok(SubMsg::reply_always(msg, reply_id))
+}
+
+/// Every account instantiation and migration gets its own reply id,
+/// so several can be in flight
+fn next_reply_id(storage: &mut dyn Storage) -> StdResult<u64> {
+    let reply_id = NEXT_REPLY_ID.may_load(storage)?.unwrap_or(INIT_CALLBACK_ID);
+    NEXT_REPLY_ID.save(storage, &(reply_id + 1))?;
+    Ok(reply_id)
+}
+
+/// Pays the escrow of all the controller's accounts back into them
+fn release_escrow(
+    deps: DepsMut,
+    info: &ChannelInfo,
+    res: IbcBasicResponse,
+) -> Result<IbcBasicResponse, ContractError> {
+    let escrow: Vec<(u64, Vec<Coin>)> = ESCROW
+        .prefix(info.controller())
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    let mut res = res;
+    for (account_id, amount) in escrow {
+        let account = ACCOUNTS.load(deps.storage, info.account(account_id))?;
+        ESCROW.remove(deps.storage, info.account(account_id));
+        let event = Event::new("release_escrow")
+            .add_attribute("account_id", account_id.to_string())
+            .add_attribute("account", &account)
+            .add_attribute("funds", coins_to_string(&amount));
+        res = res.add_event(event).add_message(BankMsg::Send {
+            to_address: account.into(),
+            amount,
+        });
+    }
+    Ok(res)
 }
 
 #[entry_point]
-/// On closed channel, we take all tokens from reflect contract to this contract.
-/// We also delete the channel entry from accounts.
+/// On closed channel, the funds of the account are handled according to the close policy.
+/// We only delete the channel entry, the account stays with the controller
+/// for its next channel.
 pub fn ibc_channel_close(
     deps: DepsMut,
     env: Env,
     msg: IbcChannelCloseMsg,
 ) -> StdResult<IbcBasicResponse> {
     let channel = msg.channel();
-    // get contract address and remove lookup
     let channel_id = channel.endpoint.channel_id.as_str();
-    let reflect_addr = ACCOUNTS.load(deps.storage, channel_id)?;
-    ACCOUNTS.remove(deps.storage, channel_id);
-
-    // transfer current balance if any (steal the money)
-    let amount = deps.querier.query_all_balances(&reflect_addr)?;
-    let messages: Vec<SubMsg<Empty>> = if !amount.is_empty() {
-        let bank_msg = BankMsg::Send {
-            to_address: env.contract.address.into(),
-            amount,
-        };
-        let reflect_msg = ReflectExecuteMsg::ReflectMsg {
-            msgs: vec![bank_msg.into()],
-        };
-        let wasm_msg = wasm_execute(reflect_addr, &reflect_msg, vec![])?;
-        vec![SubMsg::new(wasm_msg)]
-    } else {
-        vec![]
+    let res = IbcBasicResponse::new()
+        .add_attribute("action", "ibc_close")
+        .add_attribute("channel_id", channel_id);
+
+    // get contract address and remove lookup
+    let info = match CHANNELS.may_load(deps.storage, channel_id)? {
+        Some(info) => info,
+        None => return Ok(res),
     };
-    let steal_funds = !messages.is_empty();
+    CHANNELS.remove(deps.storage, channel_id);
+    let accounts: Vec<(u64, Addr)> = ACCOUNTS
+        .prefix(info.controller())
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    if accounts.is_empty() {
+        return Ok(res);
+    }
 
-    Ok(IbcBasicResponse::new()
-        .add_submessages(messages)
-        .add_attribute("action", "ibc_close")
-        .add_attribute("channel_id", channel_id)
-        .add_attribute("steal_funds", steal_funds.to_string()))
+    // without a return address there is nowhere to send the funds to,
+    // and the accounts stay in use while the controller has another channel open
+    let mut policy = CONFIG.load(deps.storage)?.close_policy;
+    let return_address = RETURN_ADDRESSES.may_load(deps.storage, info.controller())?;
+    if matches!(policy, ClosePolicy::Return { .. }) && return_address.is_none() {
+        policy = ClosePolicy::Keep;
+    }
+    if controller_channel(deps.storage, info.controller())?.is_some() {
+        policy = ClosePolicy::Keep;
+    }
+    let mut res = res.add_attribute("close_policy", policy_name(&policy));
+
+    for (account_id, reflect_addr) in accounts {
+        let amount = deps.querier.query_all_balances(&reflect_addr)?;
+        let event = Event::new("close_account")
+            .add_attribute("account_id", account_id.to_string())
+            .add_attribute("account", &reflect_addr)
+            .add_attribute("funds", coins_to_string(&amount));
+        res = res.add_event(event);
+        if amount.is_empty() {
+            continue;
+        }
+
+        let msgs: Vec<CosmosMsg> = match &policy {
+            ClosePolicy::Keep => continue,
+            ClosePolicy::Return { transfer_channel } => {
+                let to_address = return_address.clone().unwrap_or_default();
+                let timeout = env.block.time.plus_seconds(RETURN_TRANSFER_LIFETIME);
+                amount
+                    .iter()
+                    .map(|coin| {
+                        IbcMsg::Transfer {
+                            channel_id: transfer_channel.clone(),
+                            to_address: to_address.clone(),
+                            amount: coin.clone(),
+                            timeout: timeout.into(),
+                        }
+                        .into()
+                    })
+                    .collect()
+            }
+            ClosePolicy::Escrow => {
+                let key = info.account(account_id);
+                let mut escrow = ESCROW.may_load(deps.storage, key)?.unwrap_or_default();
+                add_coins(&mut escrow, &amount);
+                ESCROW.save(deps.storage, key, &escrow)?;
+                vec![BankMsg::Send {
+                    to_address: env.contract.address.to_string(),
+                    amount,
+                }
+                .into()]
+            }
+        };
+        let account_type = load_account_type(deps.storage, info.account(account_id))?;
+        res = res.add_message(account_type.execute_msg(&reflect_addr, msgs)?);
+    }
+    Ok(res)
+}
+
+fn policy_name(policy: &ClosePolicy) -> &'static str {
+    match policy {
+        ClosePolicy::Keep => "keep",
+        ClosePolicy::Return { .. } => "return",
+        ClosePolicy::Escrow => "escrow",
+    }
+}
+
+fn coins_to_string(coins: &[Coin]) -> String {
+    let coins: Vec<String> = coins.iter().map(Coin::to_string).collect();
+    coins.join(",")
+}
+
+fn add_coins(total: &mut Vec<Coin>, amount: &[Coin]) {
+    for coin in amount {
+        match total.iter_mut().find(|c| c.denom == coin.denom) {
+            Some(c) => c.amount += coin.amount,
+            None => total.push(coin.clone()),
+        }
+    }
 }
 
 #[entry_point]
-pub fn reply(deps: DepsMut, _env: Env, reply: Reply) -> Result<Response, ContractError> {
+pub fn reply(deps: DepsMut, env: Env, reply: Reply) -> Result<Response, ContractError> {
     match reply.id {
-        RECEIVE_DISPATCH_ID => reply_dispatch_callback(deps, reply),
-        INIT_CALLBACK_ID => reply_init_callback(deps, reply),
+        RECEIVE_DISPATCH_ID => reply_dispatch_callback(deps, env, reply),
+        RECEIVE_BEST_EFFORT_ID => reply_best_effort_callback(deps, env, reply),
+        id if PENDING_MIGRATIONS.has(deps.storage, id) => reply_migrate_callback(deps, reply),
+        id if id >= INIT_CALLBACK_ID => reply_init_callback(deps, env, reply),
         _ => Err(ContractError::InvalidReplyId),
     }
 }
 
-pub fn reply_dispatch_callback(deps: DepsMut, reply: Reply) -> Result<Response, ContractError> {
+/// Called for an atomic dispatch, whether it failed or not. A failure reverts
+/// the messages of the dispatch and is acked with an error.
+pub fn reply_dispatch_callback(
+    deps: DepsMut,
+    env: Env,
+    reply: Reply,
+) -> Result<Response, ContractError> {
     // add the new result to the current tracker
-    let mut results = RESULTS.load(deps.storage)?;
-    results.push(reply.result.unwrap().data.unwrap_or_default());
-    RESULTS.save(deps.storage, &results)?;
+    let mut dispatch = RESULTS.load(deps.storage)?;
+    let data = match reply.result.into_result() {
+        Ok(res) => res.data.unwrap_or_default(),
+        Err(err) => {
+            release_dispatch_outflow(deps.storage, &env, &dispatch, 0)?;
+            let version = channel_version(deps.storage, &dispatch.channel_id)?;
+            let err = ContractError::DispatchFailed(err);
+            let ack = StdAck::fail_for(version, err.error_code(), err.to_string());
+            return Ok(Response::new()
+                .add_attribute("error", err.to_string())
+                .set_data(ack));
+        }
+    };
+    dispatch.results.push(data);
+    RESULTS.save(deps.storage, &dispatch)?;
 
     // update result data if this is the last
-    let data = StdAck::success(&DispatchResponse { results });
+    let DispatchResults {
+        encoding,
+        results,
+        outcomes,
+        ..
+    } = dispatch;
+    let data = StdAck::success_with(encoding, &DispatchResponse { results, outcomes });
+    Ok(Response::new().set_data(data))
+}
+
+/// Called for every message of a best effort dispatch, whether it failed or not.
+/// A failed message only reverts its own state changes, as we return `Ok` here.
+pub fn reply_best_effort_callback(
+    deps: DepsMut,
+    env: Env,
+    reply: Reply,
+) -> Result<Response, ContractError> {
+    let mut dispatch = RESULTS.load(deps.storage)?;
+    let outcome = match reply.result.into_result() {
+        Ok(res) => MsgOutcome::Success(res.data.unwrap_or_default()),
+        Err(err) => {
+            let index = dispatch.outcomes.len();
+            release_dispatch_outflow(deps.storage, &env, &dispatch, index)?;
+            MsgOutcome::Error(err)
+        }
+    };
+    dispatch.outcomes.push(outcome);
+    RESULTS.save(deps.storage, &dispatch)?;
+
+    // the data of the last reply becomes the ack
+    let DispatchResults {
+        encoding,
+        results,
+        outcomes,
+        ..
+    } = dispatch;
+    let data = StdAck::success_with(encoding, &DispatchResponse { results, outcomes });
     Ok(Response::new().set_data(data))
 }
 
-pub fn reply_init_callback(deps: DepsMut, reply: Reply) -> Result<Response, ContractError> {
+// the funds of a failed submessage were never sent
+fn release_dispatch_outflow(
+    storage: &mut dyn Storage,
+    env: &Env,
+    dispatch: &DispatchResults,
+    index: usize,
+) -> Result<(), ContractError> {
+    let funds = match dispatch.funds.get(index) {
+        Some(funds) if !funds.is_empty() => funds,
+        _ => return Ok(()),
+    };
+    let info = CHANNELS.load(storage, &dispatch.channel_id)?;
+    let key = info.account(dispatch.account_id);
+    release_outflow(storage, key, env.block.time, funds)
+}
+
+pub fn reply_init_callback(
+    deps: DepsMut,
+    env: Env,
+    reply: Reply,
+) -> Result<Response, ContractError> {
     // we use storage to pass info from the caller to the reply
-    let id = PENDING.load(deps.storage)?;
-    PENDING.remove(deps.storage);
+    let pending = PENDING
+        .may_load(deps.storage, reply.id)?
+        .ok_or(ContractError::InvalidReplyId)?;
+    PENDING.remove(deps.storage, reply.id);
+    let info = CHANNELS.load(deps.storage, &pending.channel_id)?;
+
+    // a failed sub-account is acked with the error, the connect of a channel fails
+    // without its default account
+    if let SubMsgResult::Err(err) = &reply.result {
+        let err = ContractError::AccountCreationFailed(err.clone());
+        if pending.account_id == DEFAULT_ACCOUNT_ID {
+            return Err(err);
+        }
+        let version = channel_version(deps.storage, &pending.channel_id)?;
+        let ack = StdAck::fail_for(version, err.error_code(), err.to_string());
+        return Ok(Response::new()
+            .add_attribute("error", err.to_string())
+            .set_data(ack));
+    }
 
     // parse contract info from data
     let raw_addr = parse_reply_instantiate_data(reply)?.contract_address;
     let contract_addr = deps.api.addr_validate(&raw_addr)?;
 
-    // store id -> contract_addr if it is empty
+    // store controller -> contract_addr if it is empty
     // id comes from: `let chan_id = msg.endpoint.channel_id;` in `ibc_channel_connect`
-    if ACCOUNTS.may_load(deps.storage, &id)?.is_some() {
+    let key = info.account(pending.account_id);
+    if ACCOUNTS.has(deps.storage, key) {
         return Err(ContractError::ChannelAlreadyRegistered);
     }
-    ACCOUNTS.save(deps.storage, &id, &contract_addr)?;
+    // only a created account costs the fee
+    let cfg = CONFIG.load(deps.storage)?;
+    let fee = pay_for_account(deps.storage, &cfg.spam_limits, info.controller())?;
+    let res = Response::new().add_messages(fee);
+    ACCOUNTS.save(deps.storage, key, &contract_addr)?;
+    ACCOUNT_TYPES.save(deps.storage, key, &pending.account_type)?;
+    let owner = (
+        info.connection_id.clone(),
+        info.counterparty_port.clone(),
+        pending.account_id,
+    );
+    ACCOUNT_OWNERS.save(deps.storage, &contract_addr, &owner)?;
+    let stats = AccountStats {
+        created: Some(env.block.time),
+        ..AccountStats::default()
+    };
+    ACCOUNT_STATS.save(deps.storage, key, &stats)?;
 
-    Ok(Response::new())
+    // the default account is created on connect, where there is no ack to answer
+    if pending.account_id == DEFAULT_ACCOUNT_ID {
+        return Ok(res);
+    }
+    let response = CreateAccountResponse {
+        account_id: pending.account_id,
+        account: contract_addr.into(),
+    };
+    let data = StdAck::success_with(info.encoding, &response);
+    Ok(res.set_data(data))
+}
+
+/// Records the outcome of a migration, and acks it if a controller asked for it
+pub fn reply_migrate_callback(deps: DepsMut, reply: Reply) -> Result<Response, ContractError> {
+    let pending = PENDING_MIGRATIONS.load(deps.storage, reply.id)?;
+    PENDING_MIGRATIONS.remove(deps.storage, reply.id);
+
+    // a failed migration only reverts itself, the account keeps its old code
+    let error = reply.result.into_result().err();
+    let status = MigrationStatus {
+        code_id: pending.code_id,
+        error: error.clone(),
+    };
+    MIGRATIONS.save(deps.storage, pending.account(), &status)?;
+    let res = Response::new()
+        .add_attribute("action", "migrate_account")
+        .add_attribute("account_id", pending.account_id.to_string())
+        .add_attribute("code_id", pending.code_id.to_string())
+        .add_attribute("success", error.is_none().to_string());
+
+    let channel_id = match &pending.channel_id {
+        Some(channel_id) => channel_id,
+        None => return Ok(res),
+    };
+    let version = channel_version(deps.storage, channel_id)?;
+    let ack = match error {
+        None => {
+            let response = MigrateAccountResponse {
+                account_id: pending.account_id,
+                code_id: pending.code_id,
+            };
+            StdAck::success_with(version.encoding, &response)
+        }
+        Some(err) => {
+            let err = ContractError::MigrationFailed(err);
+            StdAck::fail_for(version, err.error_code(), err.to_string())
+        }
+    };
+    Ok(res.set_data(ack))
 }
 
 #[entry_point]
 /// we look for a the proper reflect contract to relay to and send the message
 /// We cannot return any meaningful response value as we do not know the response value
-/// of execution. We just return ok if we dispatched, error if we failed to dispatch
+/// of execution. We just return ok if we dispatched, error if we failed to dispatch.
+/// Failures are returned as error acks (coded if the channel allows it) instead of `Err`,
+/// so the controller always learns what went wrong.
 pub fn ibc_packet_receive(
     deps: DepsMut,
     env: Env,
     msg: IbcPacketReceiveMsg,
 ) -> Result<IbcReceiveResponse, ContractError> {
+    // the channel version tells us how to read the packet and write the ack
+    let version = channel_version(deps.storage, &msg.packet.dest.channel_id)?;
+    // every packet counts against the rate limit, also the ones we reject, so the
+    // count is saved even when the packet is answered with an error ack
+    if let Some(limit) = CONFIG.load(deps.storage)?.spam_limits.rate_limit {
+        let channel_id = msg.packet.dest.channel_id.clone();
+        if let Err(err) = count_packet(deps.storage, &limit, &channel_id, env.block.height) {
+            let res = error_response(version, err);
+            if !limit.close_channel {
+                return Ok(res);
+            }
+            return Ok(res.add_message(IbcMsg::CloseChannel { channel_id }));
+        }
+    }
+    // the handlers only write to storage once their checks passed, so a packet
+    // answered with an error ack leaves nothing behind but its count above
+    do_ibc_packet_receive(deps, env, version, msg).or_else(|err| Ok(error_response(version, err)))
+}
+
+fn error_response(version: &ProtocolVersion, err: ContractError) -> IbcReceiveResponse {
+    let ack = StdAck::fail_for(version, err.error_code(), err.to_string());
+    IbcReceiveResponse::new()
+        .set_ack(ack)
+        .add_attribute("action", "receive_error")
+        .add_attribute("error", err.to_string())
+}
+
+fn do_ibc_packet_receive(
+    mut deps: DepsMut,
+    env: Env,
+    version: &ProtocolVersion,
+    msg: IbcPacketReceiveMsg,
+) -> Result<IbcReceiveResponse, ContractError> {
+    if CONFIG.load(deps.storage)?.packets_paused {
+        return Err(ContractError::PacketsPaused);
+    }
     let packet = msg.packet;
     // which local channel did this packet come on
     let caller = packet.dest.channel_id;
-    let msg: PacketMsg = from_slice(&packet.data)?;
-    match msg {
-        PacketMsg::Dispatch { msgs } => receive_dispatch(deps, caller, msgs),
-        PacketMsg::WhoAmI {} => receive_who_am_i(deps, caller),
-        PacketMsg::Balances { callback } => receive_balances(deps, env, caller, callback),
+    let msg: PacketMsg = version
+        .encoding
+        .decode(&packet.data)
+        .map_err(|e| ContractError::InvalidPacket(e.to_string()))?;
+    let account_id = msg.account_id();
+    if account_id != DEFAULT_ACCOUNT_ID {
+        check_feature(version, FEATURE_SUB_ACCOUNTS)?;
     }
+    let is_dispatch = matches!(
+        msg,
+        PacketMsg::Dispatch { .. } | PacketMsg::DispatchCustom { .. }
+    );
+    let res = receive_packet(deps.branch(), env, version, caller.clone(), msg)?;
+    record_activity(deps.storage, &caller, account_id, is_dispatch)?;
+    Ok(res)
 }
 
-// processes PacketMsg::WhoAmI variant
-fn receive_who_am_i(deps: DepsMut, caller: String) -> Result<IbcReceiveResponse, ContractError> {
-    let account = ACCOUNTS.load(deps.storage, &caller)?;
-    let response = WhoAmIResponse {
-        account: account.into(),
-    };
-    let acknowledgement = StdAck::success(&response);
-    // and we are golden
-    Ok(IbcReceiveResponse::new()
-        .set_ack(acknowledgement)
-        .add_attribute("action", "receive_who_am_i"))
+/// Counts a processed packet for the account it was for
+fn record_activity(
+    storage: &mut dyn Storage,
+    channel_id: &str,
+    account_id: u64,
+    is_dispatch: bool,
+) -> StdResult<()> {
+    let info = CHANNELS.load(storage, channel_id)?;
+    let key = info.account(account_id);
+    // e.g. the default account of a channel still waiting for its reply
+    if !ACCOUNTS.has(storage, key) {
+        return Ok(());
+    }
+    let mut stats = ACCOUNT_STATS.may_load(storage, key)?.unwrap_or_default();
+    stats.packets += 1;
+    if is_dispatch {
+        stats.dispatches += 1;
+    }
+    ACCOUNT_STATS.save(storage, key, &stats)
 }
 
-// processes PacketMsg::Balances variant
-fn receive_balances(
+fn receive_packet(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
+    version: &ProtocolVersion,
     caller: String,
-    callback: bool,
+    msg: PacketMsg,
 ) -> Result<IbcReceiveResponse, ContractError> {
-    let account = ACCOUNTS.load(deps.storage, &caller)?;
-    let balances = deps.querier.query_all_balances(&account)?;
+    let encoding = version.encoding;
+    match msg {
+        PacketMsg::Dispatch {
+            msgs,
+            mode,
+            account_id,
+        } => {
+            check_mode(version, &mode)?;
+            receive_dispatch(deps, env, caller, account_id, encoding, msgs, mode)
+        }
+        PacketMsg::DispatchCustom {
+            msgs,
+            mode,
+            account_id,
+        } => {
+            check_feature(version, FEATURE_CUSTOM_MSGS)?;
+            check_mode(version, &mode)?;
+            let msgs = msgs
+                .into_iter()
+                .map(into_host_msg)
+                .collect::<Result<_, _>>()?;
+            receive_dispatch(deps, env, caller, account_id, encoding, msgs, mode)
+        }
+        PacketMsg::WhoAmI { account_id } => receive_who_am_i(deps, caller, account_id, encoding),
+        PacketMsg::Balances {
+            callback,
+            account_id,
+        } => receive_balances(deps, env, caller, account_id, encoding, callback),
+        PacketMsg::SetReturnAddress { address } => {
+            check_feature(version, FEATURE_RETURN_ADDRESS)?;
+            receive_set_return_address(deps, caller, encoding, address)
+        }
+        PacketMsg::CreateAccount { label } => {
+            check_feature(version, FEATURE_SUB_ACCOUNTS)?;
+            receive_create_account(deps, env, caller, encoding, label)
+        }
+        PacketMsg::MigrateAccount {
+            new_code_id,
+            account_id,
+        } => {
+            check_feature(version, FEATURE_MIGRATE_ACCOUNT)?;
+            receive_migrate_account(deps, caller, account_id, new_code_id)
+        }
+        PacketMsg::SetOutflowLimits { limits, account_id } => {
+            check_feature(version, FEATURE_OUTFLOW_LIMITS)?;
+            receive_set_outflow_limits(deps, caller, account_id, encoding, limits)
+        }
+        PacketMsg::BlockMsgs { kinds, account_id } => {
+            check_feature(version, FEATURE_MSG_POLICIES)?;
+            receive_block_msgs(deps, caller, account_id, encoding, kinds)
+        }
+    }
+}
 
-    // make a condition to trigger a callback fn on the controller contract
-    let mut execute_callback = false;
-    if callback && balances[0].amount < ENOUGH_FUND {
-        execute_callback = true;
+fn check_feature(version: &ProtocolVersion, feature: &str) -> Result<(), ContractError> {
+    if !version.supports(feature) {
+        return Err(ContractError::FeatureNotSupported(feature.to_string()));
     }
+    Ok(())
+}
 
-    let response = BalancesResponse {
+fn check_mode(version: &ProtocolVersion, mode: &DispatchMode) -> Result<(), ContractError> {
+    if !mode.is_atomic() {
+        check_feature(version, FEATURE_BEST_EFFORT_DISPATCH)?;
+    }
+    Ok(())
+}
+
+/// Loads the reflect contract bound to the channel
+/// Accounts created before there were types are cw1-whitelist contracts
+fn load_account_type(storage: &dyn Storage, key: (&str, &str, u64)) -> StdResult<AccountType> {
+    Ok(ACCOUNT_TYPES.may_load(storage, key)?.unwrap_or_default())
+}
+
+fn load_account(deps: Deps, channel_id: &str, account_id: u64) -> Result<Addr, ContractError> {
+    channel_account(deps.storage, channel_id, account_id)?.ok_or_else(|| {
+        if account_id == DEFAULT_ACCOUNT_ID {
+            ContractError::UnregisteredChannel(channel_id.to_string())
+        } else {
+            ContractError::UnknownAccount {
+                channel: channel_id.to_string(),
+                account_id,
+            }
+        }
+    })
+}
+
+// processes PacketMsg::WhoAmI variant
+fn receive_who_am_i(
+    deps: DepsMut,
+    caller: String,
+    account_id: u64,
+    encoding: Encoding,
+) -> Result<IbcReceiveResponse, ContractError> {
+    let account = load_account(deps.as_ref(), &caller, account_id)?;
+    let response = WhoAmIResponse {
+        account: account.into(),
+    };
+    let acknowledgement = StdAck::success_with(encoding, &response);
+    // and we are golden
+    Ok(IbcReceiveResponse::new()
+        .set_ack(acknowledgement)
+        .add_attribute("action", "receive_who_am_i"))
+}
+
+// processes PacketMsg::Balances variant
+fn receive_balances(
+    deps: DepsMut,
+    _env: Env,
+    caller: String,
+    account_id: u64,
+    encoding: Encoding,
+    callback: bool,
+) -> Result<IbcReceiveResponse, ContractError> {
+    let account = load_account(deps.as_ref(), &caller, account_id)?;
+    let balances = deps
+        .querier
+        .query_all_balances(&account)
+        .map_err(|e| ContractError::QueryFailed(e.to_string()))?;
+
+    // make a condition to trigger a callback fn on the controller contract
+    // (an empty account has nothing, so it is short on funds as well)
+    let first = balances.first().map(|c| c.amount).unwrap_or_default();
+    let execute_callback = callback && first < ENOUGH_FUND;
+
+    let response = BalancesResponse {
         account: account.into(),
         balances,
         // insert the bool val to decide to execute callback fn or not
-        execute_callback: execute_callback,
+        execute_callback,
     };
-    let acknowledgement = StdAck::success(&response);
+    let acknowledgement = StdAck::success_with(encoding, &response);
     // and we are golden
     Ok(IbcReceiveResponse::new()
         .set_ack(acknowledgement)
         .add_attribute("action", "receive_balances"))
 }
 
-// processes PacketMsg::Dispatch variant
-fn receive_dispatch(
+// processes PacketMsg::SetReturnAddress variant
+fn receive_set_return_address(
+    deps: DepsMut,
+    caller: String,
+    encoding: Encoding,
+    address: Option<String>,
+) -> Result<IbcReceiveResponse, ContractError> {
+    // the address lives on the controller chain, so we cannot validate it here
+    let info = CHANNELS
+        .may_load(deps.storage, &caller)?
+        .ok_or_else(|| ContractError::UnregisteredChannel(caller.clone()))?;
+    match &address {
+        Some(address) if address.is_empty() => {
+            return Err(ContractError::InvalidPacket("empty return address".into()))
+        }
+        Some(address) => RETURN_ADDRESSES.save(deps.storage, info.controller(), address)?,
+        None => RETURN_ADDRESSES.remove(deps.storage, info.controller()),
+    }
+
+    let acknowledgement = StdAck::Result(Binary::default()).ack_with(encoding);
+    Ok(IbcReceiveResponse::new()
+        .set_ack(acknowledgement)
+        .add_attribute("action", "receive_set_return_address")
+        .add_attribute("return_address", address.unwrap_or_default()))
+}
+
+// processes PacketMsg::SetOutflowLimits variant
+fn receive_set_outflow_limits(
+    deps: DepsMut,
+    caller: String,
+    account_id: u64,
+    encoding: Encoding,
+    limits: Vec<OutflowLimit>,
+) -> Result<IbcReceiveResponse, ContractError> {
+    load_account(deps.as_ref(), &caller, account_id)?;
+    let info = CHANNELS.load(deps.storage, &caller)?;
+    let key = info.account(account_id);
+    validate_limits(&limits)?;
+    let current = ACCOUNT_LIMITS
+        .may_load(deps.storage, key)?
+        .unwrap_or_default();
+    check_tightened(&current, &limits)?;
+    ACCOUNT_LIMITS.save(deps.storage, key, &limits)?;
+
+    let acknowledgement = StdAck::Result(Binary::default()).ack_with(encoding);
+    Ok(IbcReceiveResponse::new()
+        .set_ack(acknowledgement)
+        .add_attribute("action", "receive_set_outflow_limits")
+        .add_attribute("account_id", account_id.to_string())
+        .add_attribute("limits", limits.len().to_string()))
+}
+
+// processes PacketMsg::BlockMsgs variant
+fn receive_block_msgs(
+    deps: DepsMut,
+    caller: String,
+    account_id: u64,
+    encoding: Encoding,
+    kinds: Vec<String>,
+) -> Result<IbcReceiveResponse, ContractError> {
+    load_account(deps.as_ref(), &caller, account_id)?;
+    validate_rules(&kinds)?;
+    let info = CHANNELS.load(deps.storage, &caller)?;
+    let key = info.account(account_id);
+    // the controller can only add to what is blocked
+    let mut blocked = BLOCKED_MSGS
+        .may_load(deps.storage, key)?
+        .unwrap_or_default();
+    for kind in kinds {
+        if !blocked.contains(&kind) {
+            blocked.push(kind);
+        }
+    }
+    BLOCKED_MSGS.save(deps.storage, key, &blocked)?;
+
+    let acknowledgement = StdAck::Result(Binary::default()).ack_with(encoding);
+    Ok(IbcReceiveResponse::new()
+        .set_ack(acknowledgement)
+        .add_attribute("action", "receive_block_msgs")
+        .add_attribute("account_id", account_id.to_string())
+        .add_attribute("blocked_msgs", blocked.join(",")))
+}
+
+// processes PacketMsg::CreateAccount variant
+fn receive_create_account(
+    deps: DepsMut,
+    env: Env,
+    caller: String,
+    encoding: Encoding,
+    label: String,
+) -> Result<IbcReceiveResponse, ContractError> {
+    if label.is_empty() {
+        return Err(ContractError::InvalidPacket("empty account label".into()));
+    }
+    // sub-accounts are numbered after the default one, which must exist already
+    load_account(deps.as_ref(), &caller, DEFAULT_ACCOUNT_ID)?;
+    let info = CHANNELS.load(deps.storage, &caller)?;
+    let mut last = ACCOUNTS
+        .prefix(info.controller())
+        .keys(deps.storage, None, None, Order::Descending)
+        .next()
+        .transpose()?
+        .unwrap_or_default();
+    // skip ids still waiting for their instantiate reply
+    for item in PENDING.range(deps.storage, None, None, Order::Ascending) {
+        let (_, pending) = item?;
+        let other = CHANNELS.load(deps.storage, &pending.channel_id)?;
+        if other.controller() == info.controller() {
+            last = last.max(pending.account_id);
+        }
+    }
+    let account_id = last + 1;
+    if let Some(max) = info.metadata.as_ref().and_then(|m| m.max_accounts) {
+        if account_id >= max {
+            return Err(ContractError::TooManyAccounts(max));
+        }
+    }
+
+    let cfg = CONFIG.load(deps.storage)?;
+    check_new_account(deps.storage, &cfg.spam_limits, info.controller())?;
+    let pending = PendingAccount {
+        channel_id: caller.clone(),
+        account_id,
+        account_type: info.account_type,
+    };
+    let account_label = format!("ibc-reflect-{}-{}", caller, label);
+    let msg = create_account_msg(deps.storage, &cfg, &env, &info, pending, account_label)?;
+
+    // the reply sets the address, this is only sent if it never comes
+    let response = CreateAccountResponse {
+        account_id,
+        account: String::new(),
+    };
+    let acknowledgement = StdAck::success_with(encoding, &response);
+    Ok(IbcReceiveResponse::new()
+        .set_ack(acknowledgement)
+        .add_submessage(msg)
+        .add_attribute("action", "receive_create_account")
+        .add_attribute("account_id", account_id.to_string())
+        .add_attribute("label", label))
+}
+
+// processes PacketMsg::MigrateAccount variant
+fn receive_migrate_account(
+    deps: DepsMut,
+    caller: String,
+    account_id: u64,
+    new_code_id: u64,
+) -> Result<IbcReceiveResponse, ContractError> {
+    let account = load_account(deps.as_ref(), &caller, account_id)?;
+    // controllers may only follow the code the owner picked for new accounts
+    let cfg = CONFIG.load(deps.storage)?;
+    if new_code_id != cfg.reflect_code_id {
+        return Err(ContractError::CodeNotAllowed {
+            code_id: new_code_id,
+            expected: cfg.reflect_code_id,
+        });
+    }
+
+    let info = CHANNELS.load(deps.storage, &caller)?;
+    let pending = PendingMigration {
+        connection_id: info.connection_id,
+        counterparty_port: info.counterparty_port,
+        account_id,
+        code_id: new_code_id,
+        channel_id: Some(caller),
+    };
+    let msg = migrate_account_msg(deps.storage, account, pending)?;
+
+    // the reply writes the ack, whether the migration worked or not
+    Ok(IbcReceiveResponse::new()
+        .add_submessage(msg)
+        .add_attribute("action", "receive_migrate_account")
+        .add_attribute("account_id", account_id.to_string())
+        .add_attribute("code_id", new_code_id.to_string()))
+}
+
+// processes PacketMsg::Dispatch and PacketMsg::DispatchCustom variants
+fn receive_dispatch<T: CustomMsg>(
     deps: DepsMut,
+    env: Env,
     caller: String,
-    msgs: Vec<CosmosMsg>,
+    account_id: u64,
+    encoding: Encoding,
+    msgs: Vec<CosmosMsg<T>>,
+    mode: DispatchMode,
 ) -> Result<IbcReceiveResponse, ContractError> {
     // what is the reflect contract here
-    let reflect_addr = ACCOUNTS.load(deps.storage, &caller)?;
+    let reflect_addr = load_account(deps.as_ref(), &caller, account_id)?;
+    let info = CHANNELS.load(deps.storage, &caller)?;
+    let key = info.account(account_id);
+    let account_type = load_account_type(deps.storage, key)?;
 
+    let cfg = CONFIG.load(deps.storage)?;
+    // the owner or the controller may have blocked some kinds of messages
+    let mut rules = cfg.blocked_msgs;
+    rules.extend(
+        BLOCKED_MSGS
+            .may_load(deps.storage, key)?
+            .unwrap_or_default(),
+    );
+    check_msgs(&rules, &msgs)?;
+
+    // the funds leaving the account must stay within the owner's and the controller's limits
+    let mut limits = cfg.outflow_limits;
+    limits.extend(
+        ACCOUNT_LIMITS
+            .may_load(deps.storage, key)?
+            .unwrap_or_default(),
+    );
+    check_countable(&msgs, &limits)?;
+    let funds: Vec<Vec<Coin>> = match mode {
+        DispatchMode::Atomic => vec![outgoing_funds(&msgs)],
+        DispatchMode::BestEffort { .. } => msgs
+            .iter()
+            .map(|msg| outgoing_funds(std::slice::from_ref(msg)))
+            .collect(),
+    };
     // let them know we're fine
-    let response = DispatchResponse { results: vec![] };
-    let acknowledgement = StdAck::success(&response);
-    // create the message to re-dispatch to the reflect contract
-    let reflect_msg = cw1_whitelist::msg::ExecuteMsg::Execute { msgs };
-    let wasm_msg = wasm_execute(reflect_addr, &reflect_msg, vec![])?;
-
-    // we wrap it in a submessage to properly report results
-    let msg = SubMsg::reply_on_success(wasm_msg, RECEIVE_DISPATCH_ID);
+    let response = DispatchResponse {
+        results: vec![],
+        outcomes: vec![],
+    };
+    let acknowledgement = StdAck::success_with(encoding, &response);
+
+    let submsgs = match mode {
+        DispatchMode::Atomic => {
+            // create the message to re-dispatch to the reflect contract
+            let wasm_msg = account_type.execute_msg(&reflect_addr, msgs)?;
+            // we wrap it in a submessage to properly report results and errors
+            vec![SubMsg::reply_always(wasm_msg, RECEIVE_DISPATCH_ID)]
+        }
+        DispatchMode::BestEffort { gas_limit } => msgs
+            .into_iter()
+            .map(|msg| {
+                // every message goes through the reflect contract on its own
+                let wasm_msg = account_type.execute_msg(&reflect_addr, vec![msg])?;
+                let submsg = SubMsg::reply_always(wasm_msg, RECEIVE_BEST_EFFORT_ID);
+                Ok(match gas_limit {
+                    Some(limit) => submsg.with_gas_limit(limit),
+                    None => submsg,
+                })
+            })
+            .collect::<StdResult<_>>()?,
+    };
+    let total = funds.iter().flatten().cloned().collect();
+    record_outflow(deps.storage, key, env.block.time, &limits, total)?;
 
     // reset the data field
-    RESULTS.save(deps.storage, &vec![])?;
+    let results = DispatchResults {
+        encoding,
+        channel_id: caller,
+        results: vec![],
+        outcomes: vec![],
+        account_id,
+        funds,
+    };
+    RESULTS.save(deps.storage, &results)?;
 
     Ok(IbcReceiveResponse::new()
         .set_ack(acknowledgement)
-        .add_submessage(msg)
-        .add_attribute("action", "receive_dispatch"))
+        .add_submessages(submsgs)
+        .add_attribute("action", "receive_dispatch")
+        .add_attribute("best_effort", (!mode.is_atomic()).to_string()))
 }
 
 #[entry_point]
@@ -316,15 +1661,19 @@ pub fn ibc_packet_timeout(
 mod tests {
     use super::*;
     use cosmwasm_std::testing::{
-        mock_dependencies, mock_env, mock_ibc_channel_close_init, mock_ibc_channel_connect_ack,
-        mock_ibc_channel_open_init, mock_ibc_channel_open_try, mock_ibc_packet_recv, mock_info,
-        mock_wasmd_attr, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR,
+        mock_dependencies, mock_env, mock_ibc_channel, mock_ibc_channel_close_init,
+        mock_ibc_channel_connect_ack, mock_ibc_channel_open_init, mock_ibc_channel_open_try,
+        mock_ibc_packet_recv, mock_info, mock_wasmd_attr, MockApi, MockQuerier, MockStorage,
+        MOCK_CONTRACT_ADDR,
     };
     use cosmwasm_std::{
-        attr, coin, coins, from_slice, BankMsg, Binary, OwnedDeps, SubMsgResponse, SubMsgResult,
-        WasmMsg,
+        attr, coin, coins, from_slice, BankMsg, Binary, OwnedDeps, ReplyOn, SubMsgResponse,
+        SubMsgResult, WasmMsg,
+    };
+    use simple_ica::{
+        ErrorCode, RemoteError, RemoteMsg, APP_ORDER, BAD_APP_ORDER, IBC_APP_VERSION,
+        IBC_APP_VERSION_PROTO, IBC_APP_VERSION_V3, IBC_APP_VERSION_V3_PROTO,
     };
-    use simple_ica::{APP_ORDER, BAD_APP_ORDER};
 
     const CREATOR: &str = "creator";
     // code id of the reflect contract
@@ -336,6 +1685,7 @@ mod tests {
         let mut deps = mock_dependencies();
         let msg = InstantiateMsg {
             reflect_code_id: REFLECT_ID,
+            account_type: AccountType::Cw1Whitelist,
         };
         let info = mock_info(CREATOR, &[]);
         let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
@@ -343,6 +1693,21 @@ mod tests {
         deps
     }
 
+    fn list_accounts_msg() -> QueryMsg {
+        QueryMsg::ListAccounts {
+            start_after: None,
+            limit: None,
+        }
+    }
+
+    // a failed receive must not dispatch anything and answer with an error ack
+    fn receive_error(deps: DepsMut, msg: IbcPacketReceiveMsg, encoding: Encoding) -> RemoteError {
+        let res = ibc_packet_receive(deps, mock_env(), msg).unwrap();
+        assert_eq!(0, res.messages.len());
+        let ack: StdAck = encoding.decode(&res.acknowledgement).unwrap();
+        ack.into_result().unwrap_err()
+    }
+
     fn fake_data(reflect_addr: &str) -> Binary {
         // works with length < 128
         let mut encoded = vec![0x0a, reflect_addr.len() as u8];
@@ -361,23 +1726,44 @@ mod tests {
 
     // connect will run through the entire handshake to set up a proper connect and
     // save the account (tested in detail in `proper_handshake_flow`)
-    fn connect(mut deps: DepsMut, channel_id: &str, account: impl Into<String>) {
+    fn connect(deps: DepsMut, channel_id: &str, account: impl Into<String>) {
+        connect_with_version(deps, channel_id, account, IBC_APP_VERSION)
+    }
+
+    fn controller_port(account: &str) -> String {
+        format!("wasm.controller-of-{}", account)
+    }
+
+    fn connect_with_version(
+        mut deps: DepsMut,
+        channel_id: &str,
+        account: impl Into<String>,
+        version: &str,
+    ) {
         let account: String = account.into();
 
-        let handshake_open = mock_ibc_channel_open_init(channel_id, APP_ORDER, IBC_APP_VERSION);
+        let handshake_open = mock_ibc_channel_open_init(channel_id, APP_ORDER, version);
         // first we try to open with a valid handshake
         ibc_channel_open(deps.branch(), mock_env(), handshake_open).unwrap();
 
-        // then we connect (with counter-party version set)
-        let handshake_connect =
-            mock_ibc_channel_connect_ack(channel_id, APP_ORDER, IBC_APP_VERSION);
+        // then we connect (with counter-party version set),
+        // each account has its own controller
+        let mut channel = mock_ibc_channel(channel_id, APP_ORDER, version);
+        channel.counterparty_endpoint.port_id = controller_port(&account);
+        let handshake_connect = IbcChannelConnectMsg::new_ack(channel, version);
         let res = ibc_channel_connect(deps.branch(), mock_env(), handshake_connect).unwrap();
-        assert_eq!(1, res.messages.len());
-        assert_eq!(1, res.events.len());
         assert_eq!(
             Event::new("ibc").add_attribute("channel", "connect"),
             res.events[0]
         );
+
+        // a controller that connected before is bound to its account right away
+        if res.messages.iter().all(|m| m.reply_on == ReplyOn::Never) {
+            let bound = channel_account(deps.storage, channel_id, 0).unwrap();
+            assert_eq!(bound.unwrap(), account);
+            return;
+        }
+        assert_eq!(1, res.messages.len());
         let id = res.messages[0].id;
 
         // fake a reply and ensure this works
@@ -397,12 +1783,190 @@ mod tests {
 
         let msg = InstantiateMsg {
             reflect_code_id: 17,
+            account_type: AccountType::Cw1Whitelist,
         };
         let info = mock_info("creator", &[]);
         let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
         assert_eq!(0, res.messages.len())
     }
 
+    #[test]
+    fn owner_updates_config() {
+        let mut deps = setup();
+
+        let raw = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+        let cfg: ConfigResponse = from_slice(&raw).unwrap();
+        assert_eq!(
+            cfg,
+            ConfigResponse {
+                owner: CREATOR.into(),
+                reflect_code_id: REFLECT_ID,
+                account_type: AccountType::Cw1Whitelist,
+                channels_paused: false,
+                packets_paused: false,
+                close_policy: ClosePolicy::Escrow,
+                outflow_limits: vec![],
+                blocked_msgs: vec![],
+                spam_limits: SpamLimits::default(),
+            }
+        );
+
+        let update = ExecuteMsg::UpdateConfig {
+            reflect_code_id: Some(202),
+            account_type: None,
+            channels_paused: Some(true),
+            packets_paused: None,
+            close_policy: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("stranger", &[]),
+            update.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), update).unwrap();
+
+        let cfg = CONFIG.load(&deps.storage).unwrap();
+        assert_eq!(cfg.reflect_code_id, 202);
+        assert!(cfg.channels_paused);
+        assert!(!cfg.packets_paused);
+
+        // hand over to a new owner, the old one is locked out
+        let transfer = ExecuteMsg::TransferOwnership {
+            new_owner: "new-owner".into(),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), transfer).unwrap();
+        let update = ExecuteMsg::UpdateConfig {
+            reflect_code_id: None,
+            account_type: None,
+            channels_paused: Some(false),
+            packets_paused: None,
+            close_policy: None,
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(CREATOR, &[]),
+            update.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("new-owner", &[]),
+            update,
+        )
+        .unwrap();
+        assert!(!CONFIG.load(&deps.storage).unwrap().channels_paused);
+    }
+
+    #[test]
+    fn pause_channels_and_packets() {
+        let mut deps = setup();
+        let channel_id = "channel-1";
+        connect(deps.as_mut(), channel_id, "acct-123");
+
+        let pause = ExecuteMsg::UpdateConfig {
+            reflect_code_id: None,
+            account_type: None,
+            channels_paused: Some(true),
+            packets_paused: Some(true),
+            close_policy: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), pause).unwrap();
+
+        // no new handshakes
+        let open = mock_ibc_channel_open_try("channel-2", APP_ORDER, IBC_APP_VERSION);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert_eq!(err, ContractError::ChannelsPaused);
+
+        // and existing channels get an error ack
+        let msg = mock_ibc_packet_recv(channel_id, &PacketMsg::WhoAmI { account_id: 0 }).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::Uncoded(ContractError::PacketsPaused.to_string())
+        );
+
+        let resume = ExecuteMsg::UpdateConfig {
+            reflect_code_id: None,
+            account_type: None,
+            channels_paused: Some(false),
+            packets_paused: Some(false),
+            close_policy: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), resume).unwrap();
+        let open = mock_ibc_channel_open_try("channel-2", APP_ORDER, IBC_APP_VERSION);
+        ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap();
+        let msg = mock_ibc_packet_recv(channel_id, &PacketMsg::WhoAmI { account_id: 0 }).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let ack: StdAck = from_slice(&res.acknowledgement).unwrap();
+        ack.unwrap();
+    }
+
+    #[test]
+    fn enforce_allowlist_in_handshake() {
+        let mut deps = setup();
+
+        // the mocks use "connection-2" and the counterparty port "their_port"
+        let allow = ExecuteMsg::UpdateAllowlist {
+            add_connections: vec!["connection-1".into()],
+            remove_connections: vec![],
+            add_ports: vec!["wasm.controller".into()],
+            remove_ports: vec![],
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("stranger", &[]),
+            allow.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), allow).unwrap();
+
+        let open = mock_ibc_channel_open_try("channel-1", APP_ORDER, IBC_APP_VERSION);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::ConnectionNotAllowed("connection-2".into())
+        );
+
+        let allow = ExecuteMsg::UpdateAllowlist {
+            add_connections: vec!["connection-2".into()],
+            remove_connections: vec!["connection-1".into()],
+            add_ports: vec![],
+            remove_ports: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), allow).unwrap();
+        let open = mock_ibc_channel_open_init("channel-1", APP_ORDER, IBC_APP_VERSION);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert_eq!(err, ContractError::PortNotAllowed("their_port".into()));
+
+        let allow = ExecuteMsg::UpdateAllowlist {
+            add_connections: vec![],
+            remove_connections: vec![],
+            add_ports: vec!["their_port".into()],
+            remove_ports: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), allow).unwrap();
+        let open = mock_ibc_channel_open_try("channel-1", APP_ORDER, IBC_APP_VERSION);
+        ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap();
+
+        let raw = query(deps.as_ref(), mock_env(), QueryMsg::Allowlist {}).unwrap();
+        let res: AllowlistResponse = from_slice(&raw).unwrap();
+        assert_eq!(
+            res,
+            AllowlistResponse {
+                connections: vec!["connection-2".into()],
+                ports: vec!["their_port".into(), "wasm.controller".into()],
+            }
+        );
+    }
+
     #[test]
     fn enforce_version_in_handshake() {
         let mut deps = setup();
@@ -417,6 +1981,27 @@ mod tests {
         ibc_channel_open(deps.as_mut(), mock_env(), valid_handshake).unwrap();
     }
 
+    #[test]
+    fn negotiate_version_in_handshake() {
+        let mut deps = setup();
+
+        // we answer a proposal with the highest version we know
+        let proposal = format!("simple-ica-v99,{}", IBC_APP_VERSION);
+        let try_open = mock_ibc_channel_open_try("channel-12", APP_ORDER, &proposal);
+        let res = ibc_channel_open(deps.as_mut(), mock_env(), try_open).unwrap();
+        assert_eq!(res.unwrap().version, IBC_APP_VERSION);
+
+        // and record it once connected
+        let connect = mock_ibc_channel_connect_ack("channel-12", APP_ORDER, IBC_APP_VERSION);
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+        let info = CHANNELS.load(&deps.storage, "channel-12").unwrap();
+        assert_eq!(info.version, IBC_APP_VERSION);
+
+        // unknown versions are never connected
+        let connect = mock_ibc_channel_connect_ack("channel-13", APP_ORDER, "simple-ica-v99");
+        ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap_err();
+    }
+
     #[test]
     fn proper_handshake_flow() {
         let mut deps = setup();
@@ -429,28 +2014,37 @@ mod tests {
         // then we connect (with counter-party version set)
         let handshake_connect =
             mock_ibc_channel_connect_ack(channel_id, APP_ORDER, IBC_APP_VERSION);
+        let channel = handshake_connect.channel().clone();
         let res = ibc_channel_connect(deps.as_mut(), mock_env(), handshake_connect).unwrap();
-        // and set up a reflect account
+        // and set up a reflect account at an address the controller can predict
         assert_eq!(1, res.messages.len());
         let id = res.messages[0].id;
-        if let CosmosMsg::Wasm(WasmMsg::Instantiate {
+        if let CosmosMsg::Wasm(WasmMsg::Instantiate2 {
             admin,
             code_id,
+            label,
             msg: _,
             funds,
-            label,
+            salt,
         }) = &res.messages[0].msg
         {
-            assert_eq!(*admin, None);
+            // the host can migrate the account later
+            assert_eq!(*admin, Some(mock_env().contract.address.to_string()));
             assert_eq!(*code_id, REFLECT_ID);
             assert_eq!(funds.len(), 0);
             assert!(label.contains(channel_id));
+            let expected = account_salt(
+                &channel.connection_id,
+                &channel.counterparty_endpoint.port_id,
+                DEFAULT_ACCOUNT_ID,
+            );
+            assert_eq!(*salt, expected);
         } else {
             panic!("invalid return message: {:?}", res.messages[0]);
         }
 
         // no accounts set yet
-        let raw = query(deps.as_ref(), mock_env(), QueryMsg::ListAccounts {}).unwrap();
+        let raw = query(deps.as_ref(), mock_env(), list_accounts_msg()).unwrap();
         let res: ListAccountsResponse = from_slice(&raw).unwrap();
         assert_eq!(0, res.accounts.len());
 
@@ -465,14 +2059,23 @@ mod tests {
         reply(deps.as_mut(), mock_env(), response).unwrap();
 
         // ensure this is now registered
-        let raw = query(deps.as_ref(), mock_env(), QueryMsg::ListAccounts {}).unwrap();
+        let raw = query(deps.as_ref(), mock_env(), list_accounts_msg()).unwrap();
         let res: ListAccountsResponse = from_slice(&raw).unwrap();
         assert_eq!(1, res.accounts.len());
         assert_eq!(
             &res.accounts[0],
             &AccountInfo {
                 account: REFLECT_ADDR.into(),
+                account_id: 0,
                 channel_id: channel_id.to_string(),
+                connection_id: "connection-2".into(),
+                counterparty_port: "their_port".into(),
+                controller: None,
+                migration: None,
+                stats: AccountStats {
+                    created: Some(mock_env().block.time),
+                    ..AccountStats::default()
+                },
             }
         );
 
@@ -482,6 +2085,7 @@ mod tests {
             mock_env(),
             QueryMsg::Account {
                 channel_id: channel_id.to_string(),
+                account_id: 0,
             },
         )
         .unwrap();
@@ -504,10 +2108,16 @@ mod tests {
         .into()];
         let ibc_msg = PacketMsg::Dispatch {
             msgs: msgs_to_dispatch.clone(),
+            mode: DispatchMode::Atomic,
+            account_id: 0,
         };
         let msg = mock_ibc_packet_recv(channel_id, &ibc_msg).unwrap();
         // this returns an error
-        ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap_err();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::Uncoded(ContractError::UnregisteredChannel(channel_id.into()).to_string())
+        );
 
         // register the channel
         connect(deps.as_mut(), channel_id, account);
@@ -548,63 +2158,1438 @@ mod tests {
         // invalid packet format on registered channel also returns error
         let bad_data = InstantiateMsg {
             reflect_code_id: 12345,
+            account_type: AccountType::Cw1Whitelist,
         };
         let msg = mock_ibc_packet_recv(channel_id, &bad_data).unwrap();
-        ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap_err();
+        receive_error(deps.as_mut(), msg, Encoding::Json);
     }
 
     #[test]
-    fn check_close_channel() {
+    fn handle_packets_in_channel_encoding() {
         let mut deps = setup();
 
         let channel_id = "channel-123";
         let account = "acct-123";
+        connect_with_version(deps.as_mut(), channel_id, account, IBC_APP_VERSION_PROTO);
+
+        // a proto packet is answered with a proto ack
+        let encoding = Encoding::Proto3;
+        let mut msg = mock_ibc_packet_recv(channel_id, &()).unwrap();
+        msg.packet.data = encoding
+            .encode(&PacketMsg::WhoAmI { account_id: 0 })
+            .unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let ack: StdAck = encoding.decode(&res.acknowledgement).unwrap();
+        let who: WhoAmIResponse = encoding.decode(&ack.unwrap()).unwrap();
+        assert_eq!(who.account, account);
+
+        // json is not accepted on this channel
+        let msg = mock_ibc_packet_recv(channel_id, &PacketMsg::WhoAmI { account_id: 0 }).unwrap();
+        receive_error(deps.as_mut(), msg, encoding);
+
+        // the dispatch result is also written in proto
+        let mut msg = mock_ibc_packet_recv(channel_id, &()).unwrap();
+        msg.packet.data = encoding
+            .encode(&PacketMsg::Dispatch {
+                msgs: vec![],
+                mode: DispatchMode::Atomic,
+                account_id: 0,
+            })
+            .unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let response = Reply {
+            id: res.messages[0].id,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: Some(b"result".into()),
+            }),
+        };
+        let res = reply(deps.as_mut(), mock_env(), response).unwrap();
+        let ack: StdAck = encoding.decode(&res.data.unwrap()).unwrap();
+        let dispatch: DispatchResponse = encoding.decode(&ack.unwrap()).unwrap();
+        assert_eq!(dispatch.results, vec![Binary::from(b"result")]);
+    }
 
-        // register the channel
-        connect(deps.as_mut(), channel_id, account);
-        // assign it some funds
-        let funds = vec![coin(123456, "uatom"), coin(7654321, "tgrd")];
-        deps.querier.update_balance(account, funds.clone());
+    #[test]
+    fn handle_dispatch_custom_packet() {
+        let mut deps = setup();
 
-        // channel should be listed and have balance
-        let raw = query(deps.as_ref(), mock_env(), QueryMsg::ListAccounts {}).unwrap();
-        let res: ListAccountsResponse = from_slice(&raw).unwrap();
-        assert_eq!(1, res.accounts.len());
-        let balance = deps.as_ref().querier.query_all_balances(account).unwrap();
-        assert_eq!(funds, balance);
+        let v1_channel = "channel-1";
+        let v3_channel = "channel-2";
+        let account = "acct-123";
+        connect(deps.as_mut(), v1_channel, account);
+        connect_with_version(deps.as_mut(), v3_channel, account, IBC_APP_VERSION_V3);
+
+        let ibc_msg = PacketMsg::DispatchCustom {
+            msgs: vec![RemoteMsg::Stargate {
+                type_url: "/cosmos.bank.v1beta1.MsgSend".into(),
+                value: b"\x0a\x05hello".into(),
+            }],
+            mode: DispatchMode::Atomic,
+            account_id: 0,
+        };
 
-        // close the channel
-        let channel = mock_ibc_channel_close_init(channel_id, APP_ORDER, IBC_APP_VERSION);
-        let res = ibc_channel_close(deps.as_mut(), mock_env(), channel).unwrap();
+        // only channels with the feature accept it
+        let msg = mock_ibc_packet_recv(v1_channel, &ibc_msg).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::Uncoded(
+                ContractError::FeatureNotSupported(FEATURE_CUSTOM_MSGS.into()).to_string()
+            )
+        );
 
-        // it pulls out all money from the reflect contract
-        assert_eq!(1, res.messages.len());
-        if let CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr, msg, ..
-        }) = &res.messages[0].msg
-        {
-            assert_eq!(contract_addr.as_str(), account);
-            let reflect: ReflectExecuteMsg = from_slice(msg).unwrap();
-            match reflect {
-                ReflectExecuteMsg::ReflectMsg { msgs } => {
-                    assert_eq!(1, msgs.len());
-                    assert_eq!(
-                        &msgs[0],
-                        &BankMsg::Send {
-                            to_address: MOCK_CONTRACT_ADDR.into(),
-                            amount: funds
-                        }
-                        .into()
-                    )
+        let msg = mock_ibc_packet_recv(v3_channel, &ibc_msg).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(RECEIVE_DISPATCH_ID, res.messages[0].id);
+        if let CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) = &res.messages[0].msg {
+            let rmsg: cw1_whitelist::msg::ExecuteMsg = from_slice(msg).unwrap();
+            assert_eq!(
+                rmsg,
+                cw1_whitelist::msg::ExecuteMsg::Execute {
+                    msgs: vec![CosmosMsg::Stargate {
+                        type_url: "/cosmos.bank.v1beta1.MsgSend".into(),
+                        value: b"\x0a\x05hello".into(),
+                    }]
                 }
-            }
+            );
         } else {
-            panic!("Unexpected message: {:?}", &res.messages[0]);
+            panic!("invalid return message: {:?}", res.messages[0]);
         }
+    }
 
-        // and removes the account lookup
-        let raw = query(deps.as_ref(), mock_env(), QueryMsg::ListAccounts {}).unwrap();
-        let res: ListAccountsResponse = from_slice(&raw).unwrap();
-        assert_eq!(0, res.accounts.len());
+    #[test]
+    fn handle_best_effort_dispatch() {
+        let mut deps = setup();
+
+        let v1_channel = "channel-1";
+        let v3_channel = "channel-2";
+        let account = "acct-123";
+        connect(deps.as_mut(), v1_channel, account);
+        connect_with_version(deps.as_mut(), v3_channel, account, IBC_APP_VERSION_V3);
+
+        let msgs: Vec<CosmosMsg> = vec![
+            BankMsg::Send {
+                to_address: "my-friend".into(),
+                amount: coins(123, "uatom"),
+            }
+            .into(),
+            BankMsg::Burn {
+                amount: coins(456, "uatom"),
+            }
+            .into(),
+        ];
+        let ibc_msg = PacketMsg::Dispatch {
+            msgs: msgs.clone(),
+            mode: DispatchMode::BestEffort {
+                gas_limit: Some(100_000),
+            },
+            account_id: 0,
+        };
+
+        // v1 channels only know atomic dispatches
+        let msg = mock_ibc_packet_recv(v1_channel, &ibc_msg).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::Uncoded(
+                ContractError::FeatureNotSupported(FEATURE_BEST_EFFORT_DISPATCH.into()).to_string()
+            )
+        );
+
+        // one submessage per message, each with its gas limit
+        let msg = mock_ibc_packet_recv(v3_channel, &ibc_msg).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(2, res.messages.len());
+        for (submsg, expected) in res.messages.iter().zip(msgs) {
+            assert_eq!(RECEIVE_BEST_EFFORT_ID, submsg.id);
+            assert_eq!(ReplyOn::Always, submsg.reply_on);
+            assert_eq!(Some(100_000), submsg.gas_limit);
+            if let CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) = &submsg.msg {
+                let rmsg: cw1_whitelist::msg::ExecuteMsg = from_slice(msg).unwrap();
+                assert_eq!(
+                    rmsg,
+                    cw1_whitelist::msg::ExecuteMsg::Execute {
+                        msgs: vec![expected]
+                    }
+                );
+            } else {
+                panic!("invalid return message: {:?}", submsg);
+            }
+        }
+
+        // the first succeeds, the second fails, both are reported
+        let ok = Reply {
+            id: RECEIVE_BEST_EFFORT_ID,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: Some(b"sent".into()),
+            }),
+        };
+        reply(deps.as_mut(), mock_env(), ok).unwrap();
+        let failed = Reply {
+            id: RECEIVE_BEST_EFFORT_ID,
+            result: SubMsgResult::Err("insufficient funds".into()),
+        };
+        let res = reply(deps.as_mut(), mock_env(), failed).unwrap();
+        let ack: StdAck = from_slice(&res.data.unwrap()).unwrap();
+        let dispatch: DispatchResponse = from_slice(&ack.unwrap()).unwrap();
+        assert_eq!(
+            dispatch.outcomes,
+            vec![
+                MsgOutcome::Success(b"sent".into()),
+                MsgOutcome::Error("insufficient funds".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn structured_error_acks() {
+        let mut deps = setup();
+
+        let channel_id = "channel-2";
+        let account = "acct-123";
+        connect_with_version(deps.as_mut(), channel_id, account, IBC_APP_VERSION_V3_PROTO);
+        let encoding = Encoding::Proto3;
+
+        // garbage is reported as an invalid packet
+        let mut msg = mock_ibc_packet_recv(channel_id, &()).unwrap();
+        msg.packet.data = b"not a packet".into();
+        let err = receive_error(deps.as_mut(), msg, encoding);
+        assert!(matches!(err, RemoteError::InvalidPacket(_)));
+        assert_eq!(err.code(), Some(ErrorCode::InvalidPacket.code()));
+
+        // custom payloads this host cannot decode
+        let mut msg = mock_ibc_packet_recv(channel_id, &()).unwrap();
+        let packet = PacketMsg::DispatchCustom {
+            msgs: vec![RemoteMsg::Custom(br#"{"unknown":{}}"#.into())],
+            mode: DispatchMode::Atomic,
+            account_id: 0,
+        };
+        msg.packet.data = encoding.encode(&packet).unwrap();
+        let err = receive_error(deps.as_mut(), msg, encoding);
+        #[cfg(not(feature = "osmosis"))]
+        assert_eq!(
+            err,
+            RemoteError::UnsupportedMsg(ContractError::CustomMsgsNotSupported.to_string())
+        );
+        #[cfg(feature = "osmosis")]
+        assert!(matches!(err, RemoteError::InvalidPacket(_)));
+
+        // a channel that lost its account
+        let controller = controller_port(account);
+        ACCOUNTS.remove(deps.as_mut().storage, ("connection-2", &controller, 0));
+        let mut msg = mock_ibc_packet_recv(channel_id, &()).unwrap();
+        msg.packet.data = encoding
+            .encode(&PacketMsg::Balances {
+                callback: false,
+                account_id: 0,
+            })
+            .unwrap();
+        let err = receive_error(deps.as_mut(), msg, encoding);
+        assert_eq!(
+            err,
+            RemoteError::UnknownChannel(
+                ContractError::UnregisteredChannel(channel_id.into()).to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn failed_executions_are_acked_with_a_code() {
+        let mut deps = setup();
+        let channel_id = "channel-2";
+        connect_with_version(deps.as_mut(), channel_id, "acct-123", IBC_APP_VERSION_V3);
+        let failed = |id: u64| Reply {
+            id,
+            result: SubMsgResult::Err("insufficient funds".into()),
+        };
+
+        // the reflect contract fails to run the dispatch
+        let packet = PacketMsg::Dispatch {
+            msgs: vec![BankMsg::Burn {
+                amount: coins(1, "uatom"),
+            }
+            .into()],
+            mode: DispatchMode::Atomic,
+            account_id: 0,
+        };
+        let msg = mock_ibc_packet_recv(channel_id, &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.messages[0].reply_on, ReplyOn::Always);
+        let res = reply(deps.as_mut(), mock_env(), failed(RECEIVE_DISPATCH_ID)).unwrap();
+        let ack: StdAck = from_slice(&res.data.unwrap()).unwrap();
+        let err = ack.into_result().unwrap_err();
+        assert_eq!(
+            err,
+            RemoteError::ExecutionFailed("Dispatch failed: insufficient funds".into())
+        );
+        assert_eq!(err.code(), Some(ErrorCode::ExecutionFailed.code()));
+
+        // and so does the instantiation of a sub-account
+        let packet = PacketMsg::CreateAccount {
+            label: "strategy-a".into(),
+        };
+        let msg = mock_ibc_packet_recv(channel_id, &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let id = res.messages[0].id;
+        let res = reply(deps.as_mut(), mock_env(), failed(id)).unwrap();
+        let ack: StdAck = from_slice(&res.data.unwrap()).unwrap();
+        assert_eq!(
+            ack.into_result().unwrap_err(),
+            RemoteError::ExecutionFailed("Creating the account failed: insufficient funds".into())
+        );
+        assert!(!PENDING.has(&deps.storage, id));
+    }
+
+    #[test]
+    fn balances_of_empty_account() {
+        let mut deps = setup();
+
+        let channel_id = "channel-1";
+        let account = "acct-123";
+        connect(deps.as_mut(), channel_id, account);
+
+        let msg = mock_ibc_packet_recv(
+            channel_id,
+            &PacketMsg::Balances {
+                callback: true,
+                account_id: 0,
+            },
+        )
+        .unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let ack: StdAck = from_slice(&res.acknowledgement).unwrap();
+        let balances: BalancesResponse = ack.unwrap_into();
+        assert!(balances.balances.is_empty());
+        assert!(balances.execute_callback);
+    }
+
+    #[test]
+    fn reopened_channel_keeps_account() {
+        let mut deps = setup();
+        let account = "acct-123";
+        connect(deps.as_mut(), "channel-1", account);
+
+        let channel = mock_ibc_channel_close_init("channel-1", APP_ORDER, IBC_APP_VERSION);
+        ibc_channel_close(deps.as_mut(), mock_env(), channel).unwrap();
+        let raw = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Account {
+                channel_id: "channel-1".into(),
+                account_id: 0,
+            },
+        )
+        .unwrap();
+        let res: AccountResponse = from_slice(&raw).unwrap();
+        assert_eq!(res.account, None);
+
+        // the account is still known by its controller
+        let query_msg = QueryMsg::ControllerAccount {
+            connection_id: "connection-2".into(),
+            counterparty_port: controller_port(account),
+            account_id: 0,
+        };
+        let raw = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let res: AccountResponse = from_slice(&raw).unwrap();
+        assert_eq!(res.account.unwrap(), account);
+
+        // a new channel of the same controller gets it back without a new instance
+        let mut channel = mock_ibc_channel("channel-7", APP_ORDER, IBC_APP_VERSION);
+        channel.counterparty_endpoint.port_id = controller_port(account);
+        let connect_msg = IbcChannelConnectMsg::new_ack(channel, IBC_APP_VERSION);
+        let res = ibc_channel_connect(deps.as_mut(), mock_env(), connect_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        let msg = mock_ibc_packet_recv("channel-7", &PacketMsg::WhoAmI { account_id: 0 }).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let ack: StdAck = from_slice(&res.acknowledgement).unwrap();
+        let who: WhoAmIResponse = ack.unwrap_into();
+        assert_eq!(who.account, account);
+
+        // other controllers still get their own account
+        connect(deps.as_mut(), "channel-8", "acct-456");
+        let raw = query(deps.as_ref(), mock_env(), list_accounts_msg()).unwrap();
+        let res: ListAccountsResponse = from_slice(&raw).unwrap();
+        let accounts: Vec<_> = res.accounts.iter().map(|a| a.account.as_str()).collect();
+        assert_eq!(accounts, vec![account, "acct-456"]);
+    }
+
+    // the messages the reflect contract is asked to execute on close
+    fn reflected_msgs(res: &IbcBasicResponse, account: &str) -> Vec<CosmosMsg> {
+        assert_eq!(1, res.messages.len());
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr, msg, ..
+            }) => {
+                assert_eq!(contract_addr.as_str(), account);
+                match from_slice(msg).unwrap() {
+                    cw1_whitelist::msg::ExecuteMsg::Execute { msgs } => msgs,
+                    o => panic!("Unexpected message: {:?}", o),
+                }
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+    }
+
+    fn query_recovery(deps: Deps, account: &str) -> RecoveryResponse {
+        let msg = QueryMsg::Recovery {
+            connection_id: "connection-2".into(),
+            counterparty_port: controller_port(account),
+        };
+        from_slice(&query(deps, mock_env(), msg).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn create_sub_accounts() {
+        let mut deps = setup();
+        let account = "acct-123";
+        let sub_account = "sub-acct-1";
+        connect_with_version(deps.as_mut(), "channel-1", account, IBC_APP_VERSION_V3);
+
+        let packet = PacketMsg::CreateAccount {
+            label: "strategy-a".into(),
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Instantiate2 { label, salt, .. }) => {
+                assert_eq!(label, "ibc-reflect-channel-1-strategy-a");
+                let info = CHANNELS.load(&deps.storage, "channel-1").unwrap();
+                let expected = account_salt(&info.connection_id, &info.counterparty_port, 1);
+                assert_eq!(*salt, expected);
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        }
+
+        // the reply answers with the new account
+        let response = Reply {
+            id: res.messages[0].id,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: fake_events(sub_account),
+                data: Some(fake_data(sub_account)),
+            }),
+        };
+        let res = reply(deps.as_mut(), mock_env(), response).unwrap();
+        let ack: StdAck = from_slice(&res.data.unwrap()).unwrap();
+        let created: CreateAccountResponse = ack.unwrap_into();
+        assert_eq!(
+            created,
+            CreateAccountResponse {
+                account_id: 1,
+                account: sub_account.into(),
+            }
+        );
+
+        // packets pick the account by id
+        let packet = PacketMsg::Dispatch {
+            msgs: vec![BankMsg::Burn {
+                amount: coins(1, "uatom"),
+            }
+            .into()],
+            mode: DispatchMode::Atomic,
+            account_id: 1,
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) => {
+                assert_eq!(contract_addr, sub_account)
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        }
+        let msg = mock_ibc_packet_recv("channel-1", &PacketMsg::WhoAmI { account_id: 2 }).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::UnknownChannel("No account 2 for channel channel-1".into())
+        );
+
+        let raw = query(deps.as_ref(), mock_env(), list_accounts_msg()).unwrap();
+        let res: ListAccountsResponse = from_slice(&raw).unwrap();
+        let accounts: Vec<_> = res
+            .accounts
+            .iter()
+            .map(|a| (a.account_id, a.account.as_str()))
+            .collect();
+        assert_eq!(accounts, vec![(0, account), (1, sub_account)]);
+
+        // v1 channels only know the default account
+        connect(deps.as_mut(), "channel-2", "acct-456");
+        let msg = mock_ibc_packet_recv("channel-2", &PacketMsg::WhoAmI { account_id: 1 }).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::Uncoded("Channel version does not support sub-accounts".into())
+        );
+    }
+
+    #[test]
+    fn check_close_channel() {
+        let mut deps = setup();
+
+        let channel_id = "channel-123";
+        let account = "acct-123";
+
+        // register the channel
+        connect(deps.as_mut(), channel_id, account);
+        // assign it some funds
+        let funds = vec![coin(123456, "uatom"), coin(7654321, "tgrd")];
+        deps.querier.update_balance(account, funds.clone());
+
+        // channel should be listed and have balance
+        let raw = query(deps.as_ref(), mock_env(), list_accounts_msg()).unwrap();
+        let res: ListAccountsResponse = from_slice(&raw).unwrap();
+        assert_eq!(1, res.accounts.len());
+        let balance = deps.as_ref().querier.query_all_balances(account).unwrap();
+        assert_eq!(funds, balance);
+
+        // close the channel
+        let channel = mock_ibc_channel_close_init(channel_id, APP_ORDER, IBC_APP_VERSION);
+        let res = ibc_channel_close(deps.as_mut(), mock_env(), channel).unwrap();
+
+        // by default it escrows all money from the reflect contract in the host
+        let msgs = reflected_msgs(&res, account);
+        assert_eq!(
+            msgs,
+            vec![BankMsg::Send {
+                to_address: MOCK_CONTRACT_ADDR.into(),
+                amount: funds.clone()
+            }
+            .into()]
+        );
+        assert!(res.attributes.contains(&attr("close_policy", "escrow")));
+        assert_eq!(
+            res.events,
+            vec![Event::new("close_account")
+                .add_attribute("account_id", "0")
+                .add_attribute("account", account)
+                .add_attribute("funds", "123456uatom,7654321tgrd")]
+        );
+        assert_eq!(
+            query_recovery(deps.as_ref(), account).escrow,
+            vec![AccountEscrow {
+                account_id: 0,
+                funds: funds.clone()
+            }]
+        );
+
+        // and removes the account lookup
+        let raw = query(deps.as_ref(), mock_env(), list_accounts_msg()).unwrap();
+        let res: ListAccountsResponse = from_slice(&raw).unwrap();
+        assert_eq!(0, res.accounts.len());
+
+        // the escrow goes back into the account once the controller reconnects
+        let mut channel = mock_ibc_channel("channel-7", APP_ORDER, IBC_APP_VERSION);
+        channel.counterparty_endpoint.port_id = controller_port(account);
+        let connect_msg = IbcChannelConnectMsg::new_ack(channel, IBC_APP_VERSION);
+        let res = ibc_channel_connect(deps.as_mut(), mock_env(), connect_msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            BankMsg::Send {
+                to_address: account.into(),
+                amount: funds
+            }
+            .into()
+        );
+        assert!(query_recovery(deps.as_ref(), account).escrow.is_empty());
+    }
+
+    #[test]
+    fn close_returns_funds_to_controller() {
+        let mut deps = setup();
+        let account = "acct-123";
+        let funds = coins(500, "uatom");
+
+        let update = ExecuteMsg::UpdateConfig {
+            reflect_code_id: None,
+            account_type: None,
+            channels_paused: None,
+            packets_paused: None,
+            close_policy: Some(ClosePolicy::Return {
+                transfer_channel: "channel-99".into(),
+            }),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), update).unwrap();
+
+        // without a return address the funds stay in the account
+        connect(deps.as_mut(), "channel-1", account);
+        deps.querier.update_balance(account, funds.clone());
+        let channel = mock_ibc_channel_close_init("channel-1", APP_ORDER, IBC_APP_VERSION);
+        let res = ibc_channel_close(deps.as_mut(), mock_env(), channel).unwrap();
+        assert_eq!(0, res.messages.len());
+        assert!(res.attributes.contains(&attr("close_policy", "keep")));
+        assert_eq!(res.events[0].attributes[2], attr("funds", "500uatom"));
+
+        // v1 channels cannot set one
+        connect(deps.as_mut(), "channel-2", account);
+        let packet = PacketMsg::SetReturnAddress {
+            address: Some("wasm1recover".into()),
+        };
+        let msg = mock_ibc_packet_recv("channel-2", &packet).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::Uncoded("Channel version does not support return-address".into())
+        );
+
+        // a v3 channel can
+        connect_with_version(deps.as_mut(), "channel-3", account, IBC_APP_VERSION_V3);
+        let msg = mock_ibc_packet_recv("channel-3", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let ack: StdAck = from_slice(&res.acknowledgement).unwrap();
+        assert_eq!(ack.unwrap(), Binary::default());
+        let recovery = query_recovery(deps.as_ref(), account);
+        assert_eq!(recovery.return_address.unwrap(), "wasm1recover");
+
+        // the accounts are still used by channel-3, so closing channel-2 keeps the funds
+        let channel = mock_ibc_channel_close_init("channel-2", APP_ORDER, IBC_APP_VERSION);
+        let res = ibc_channel_close(deps.as_mut(), mock_env(), channel).unwrap();
+        assert_eq!(0, res.messages.len());
+        assert!(res.attributes.contains(&attr("close_policy", "keep")));
+        assert_eq!(
+            deps.as_ref().querier.query_all_balances(account).unwrap(),
+            funds.clone()
+        );
+
+        // once the last channel closes the funds are sent home over ics20
+        let channel = mock_ibc_channel_close_init("channel-3", APP_ORDER, IBC_APP_VERSION_V3);
+        let res = ibc_channel_close(deps.as_mut(), mock_env(), channel).unwrap();
+        assert!(res.attributes.contains(&attr("close_policy", "return")));
+        let msgs = reflected_msgs(&res, account);
+        assert_eq!(
+            msgs,
+            vec![IbcMsg::Transfer {
+                channel_id: "channel-99".into(),
+                to_address: "wasm1recover".into(),
+                amount: funds[0].clone(),
+                timeout: mock_env()
+                    .block
+                    .time
+                    .plus_seconds(RETURN_TRANSFER_LIFETIME)
+                    .into(),
+            }
+            .into()]
+        );
+    }
+
+    // the reply of the instantiate message with the given id
+    fn instantiated(id: u64, reflect_addr: &str) -> Reply {
+        Reply {
+            id,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: fake_events(reflect_addr),
+                data: Some(fake_data(reflect_addr)),
+            }),
+        }
+    }
+
+    #[test]
+    fn connect_several_channels_before_replies() {
+        let mut deps = setup();
+        let controllers = ["acct-1", "acct-2", "acct-3"];
+
+        // all channels connect before any instantiate replies
+        let mut reply_ids = vec![];
+        for (i, controller) in controllers.iter().enumerate() {
+            let channel_id = format!("channel-{}", i);
+            let mut channel = mock_ibc_channel(&channel_id, APP_ORDER, IBC_APP_VERSION);
+            channel.counterparty_endpoint.port_id = controller_port(controller);
+            let connect = IbcChannelConnectMsg::new_ack(channel, IBC_APP_VERSION);
+            let res = ibc_channel_connect(deps.as_mut(), mock_env(), connect).unwrap();
+            assert_eq!(1, res.messages.len());
+            reply_ids.push(res.messages[0].id);
+        }
+        assert_eq!(
+            reply_ids,
+            vec![INIT_CALLBACK_ID, INIT_CALLBACK_ID + 1, INIT_CALLBACK_ID + 2]
+        );
+
+        // replies in any order end up with their own channel
+        for (i, id) in reply_ids.iter().enumerate().rev() {
+            reply(deps.as_mut(), mock_env(), instantiated(*id, controllers[i])).unwrap();
+        }
+        for (i, controller) in controllers.iter().enumerate() {
+            let channel_id = format!("channel-{}", i);
+            let account = channel_account(&deps.storage, &channel_id, 0).unwrap();
+            assert_eq!(account.unwrap(), *controller);
+        }
+
+        // each reply is only handled once
+        let err = reply(
+            deps.as_mut(),
+            mock_env(),
+            instantiated(reply_ids[0], "acct-4"),
+        );
+        assert_eq!(err.unwrap_err(), ContractError::InvalidReplyId);
+        let raw = query(deps.as_ref(), mock_env(), list_accounts_msg()).unwrap();
+        let res: ListAccountsResponse = from_slice(&raw).unwrap();
+        assert_eq!(3, res.accounts.len());
+    }
+
+    #[test]
+    fn create_several_accounts_before_replies() {
+        let mut deps = setup();
+        connect_with_version(deps.as_mut(), "channel-1", "acct-123", IBC_APP_VERSION_V3);
+
+        let mut reply_ids = vec![];
+        for label in ["strategy-a", "strategy-b"] {
+            let packet = PacketMsg::CreateAccount {
+                label: label.into(),
+            };
+            let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+            let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+            reply_ids.push(res.messages[0].id);
+        }
+
+        // the second account does not reuse the id of the first one
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            instantiated(reply_ids[1], "sub-b"),
+        )
+        .unwrap();
+        let ack: StdAck = from_slice(&res.data.unwrap()).unwrap();
+        let created: CreateAccountResponse = ack.unwrap_into();
+        assert_eq!(created.account_id, 2);
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            instantiated(reply_ids[0], "sub-a"),
+        )
+        .unwrap();
+        let ack: StdAck = from_slice(&res.data.unwrap()).unwrap();
+        let created: CreateAccountResponse = ack.unwrap_into();
+        assert_eq!(created.account_id, 1);
+
+        let account = channel_account(&deps.storage, "channel-1", 1).unwrap();
+        assert_eq!(account.unwrap(), "sub-a");
+        let account = channel_account(&deps.storage, "channel-1", 2).unwrap();
+        assert_eq!(account.unwrap(), "sub-b");
+    }
+
+    // the reply of a migrate message, failing with the given error
+    fn migrated(id: u64, error: Option<&str>) -> Reply {
+        let result = match error {
+            Some(err) => SubMsgResult::Err(err.into()),
+            None => SubMsgResult::Ok(SubMsgResponse {
+                events: vec![],
+                data: None,
+            }),
+        };
+        Reply { id, result }
+    }
+
+    #[test]
+    fn controller_migrates_account() {
+        let mut deps = setup();
+        let account = "acct-123";
+        connect_with_version(deps.as_mut(), "channel-1", account, IBC_APP_VERSION_V3);
+        connect(deps.as_mut(), "channel-2", "acct-456");
+
+        // only to the code new accounts get
+        let packet = PacketMsg::MigrateAccount {
+            new_code_id: 42,
+            account_id: 0,
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert!(matches!(err, RemoteError::UnsupportedMsg(_)));
+
+        // and only on channels that know the packet
+        let packet = PacketMsg::MigrateAccount {
+            new_code_id: REFLECT_ID,
+            account_id: 0,
+        };
+        let msg = mock_ibc_packet_recv("channel-2", &packet).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::Uncoded("Channel version does not support migrate-account".into())
+        );
+
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(res.messages[0].reply_on, ReplyOn::Always);
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Migrate {
+                contract_addr,
+                new_code_id,
+                ..
+            }) => {
+                assert_eq!(contract_addr, account);
+                assert_eq!(*new_code_id, REFLECT_ID);
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        }
+
+        // the reply acks the outcome and keeps it for queries
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            migrated(res.messages[0].id, None),
+        )
+        .unwrap();
+        let ack: StdAck = from_slice(&res.data.unwrap()).unwrap();
+        let migrated_to: MigrateAccountResponse = ack.unwrap_into();
+        assert_eq!(
+            migrated_to,
+            MigrateAccountResponse {
+                account_id: 0,
+                code_id: REFLECT_ID,
+            }
+        );
+        let query_msg = QueryMsg::Account {
+            channel_id: "channel-1".into(),
+            account_id: 0,
+        };
+        let raw = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let res: AccountResponse = from_slice(&raw).unwrap();
+        let status = MigrationStatus {
+            code_id: REFLECT_ID,
+            error: None,
+        };
+        assert_eq!(res.migration, Some(status));
+
+        // a failed migration is reported as well
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let failed = migrated(res.messages[0].id, Some("no migrate entry point"));
+        let res = reply(deps.as_mut(), mock_env(), failed).unwrap();
+        let ack: StdAck = from_slice(&res.data.unwrap()).unwrap();
+        assert_eq!(
+            ack.into_result().unwrap_err(),
+            RemoteError::Internal("Migration failed: no migrate entry point".into())
+        );
+        let raw = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let res: AccountResponse = from_slice(&raw).unwrap();
+        let status = MigrationStatus {
+            code_id: REFLECT_ID,
+            error: Some("no migrate entry point".into()),
+        };
+        assert_eq!(res.migration, Some(status));
+    }
+
+    #[test]
+    fn owner_migrates_accounts_in_pages() {
+        let mut deps = setup();
+        for (i, account) in ["acct-1", "acct-2", "acct-3"].iter().enumerate() {
+            connect(deps.as_mut(), &format!("channel-{}", i), *account);
+        }
+
+        let migrate = |start_after: Option<AccountKey>| ExecuteMsg::MigrateAccounts {
+            new_code_id: 202,
+            start_after,
+            limit: Some(2),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("stranger", &[]),
+            migrate(None),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+
+        let info = mock_info(CREATOR, &[]);
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), migrate(None)).unwrap();
+        assert_eq!(2, res.messages.len());
+        // one failure does not stop the others
+        let first = migrated(res.messages[0].id, Some("out of gas"));
+        let res2 = reply(deps.as_mut(), mock_env(), first).unwrap();
+        assert_eq!(res2.data, None);
+        reply(
+            deps.as_mut(),
+            mock_env(),
+            migrated(res.messages[1].id, None),
+        )
+        .unwrap();
+
+        // the next page starts after the last account
+        let next = AccountKey {
+            connection_id: "connection-2".into(),
+            counterparty_port: controller_port("acct-2"),
+            account_id: 0,
+        };
+        let res = execute(deps.as_mut(), mock_env(), info.clone(), migrate(Some(next))).unwrap();
+        assert_eq!(1, res.messages.len());
+        reply(
+            deps.as_mut(),
+            mock_env(),
+            migrated(res.messages[0].id, None),
+        )
+        .unwrap();
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            info,
+            migrate(Some(AccountKey {
+                connection_id: "connection-2".into(),
+                counterparty_port: controller_port("acct-3"),
+                account_id: 0,
+            })),
+        )
+        .unwrap();
+        assert_eq!(0, res.messages.len());
+
+        let raw = query(deps.as_ref(), mock_env(), list_accounts_msg()).unwrap();
+        let res: ListAccountsResponse = from_slice(&raw).unwrap();
+        let errors: Vec<_> = res
+            .accounts
+            .iter()
+            .map(|a| {
+                let status = a.migration.as_ref().unwrap();
+                assert_eq!(status.code_id, 202);
+                status.error.as_deref()
+            })
+            .collect();
+        assert_eq!(errors, vec![Some("out of gas"), None, None]);
+    }
+
+    #[test]
+    fn account_type_is_picked_per_channel() {
+        let mut deps = setup();
+        connect(deps.as_mut(), "channel-1", "acct-1");
+
+        // channels connected from now on get reflect accounts
+        let update = ExecuteMsg::UpdateConfig {
+            reflect_code_id: None,
+            account_type: Some(AccountType::Reflect),
+            channels_paused: None,
+            packets_paused: None,
+            close_policy: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), update).unwrap();
+        let mut channel = mock_ibc_channel("channel-2", APP_ORDER, IBC_APP_VERSION);
+        channel.counterparty_endpoint.port_id = controller_port("acct-2");
+        let connect_msg = IbcChannelConnectMsg::new_ack(channel, IBC_APP_VERSION);
+        let res = ibc_channel_connect(deps.as_mut(), mock_env(), connect_msg).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Instantiate2 { msg, .. }) => {
+                assert_eq!(msg.as_slice(), b"{}")
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        }
+        reply(
+            deps.as_mut(),
+            mock_env(),
+            instantiated(res.messages[0].id, "acct-2"),
+        )
+        .unwrap();
+
+        // each account is executed the way its contract expects
+        let packet = PacketMsg::Dispatch {
+            msgs: vec![BankMsg::Burn {
+                amount: coins(1, "uatom"),
+            }
+            .into()],
+            mode: DispatchMode::Atomic,
+            account_id: 0,
+        };
+        let expected: [(&str, &[u8]); 2] = [
+            (
+                "channel-1",
+                br#"{"execute":{"msgs":[{"bank":{"burn":{"amount":[{"denom":"uatom","amount":"1"}]}}}]}}"#,
+            ),
+            (
+                "channel-2",
+                br#"{"reflect_msg":{"msgs":[{"bank":{"burn":{"amount":[{"denom":"uatom","amount":"1"}]}}}]}}"#,
+            ),
+        ];
+        for (channel_id, expected) in expected {
+            let msg = mock_ibc_packet_recv(channel_id, &packet).unwrap();
+            let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+            match &res.messages[0].msg {
+                CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => {
+                    assert_eq!(msg.as_slice(), expected)
+                }
+                o => panic!("Unexpected message: {:?}", o),
+            }
+        }
+    }
+
+    #[test]
+    fn channel_metadata_handshake() {
+        let mut deps = setup();
+        let requested = r#"{"version":"simple-ica-v3","encoding":"json","account_type":"reflect","max_accounts":2}"#;
+
+        // we reject options we cannot apply
+        let unknown_type = requested.replace("reflect", "proxy");
+        let open = mock_ibc_channel_open_try("channel-1", APP_ORDER, &unknown_type);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert_eq!(err, ContractError::UnknownAccountType("proxy".into()));
+        let other_code =
+            requested.replace("\"max_accounts\"", "\"reflect_code_id\":7,\"max_accounts\"");
+        let open = mock_ibc_channel_open_try("channel-1", APP_ORDER, &other_code);
+        let err = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CodeNotAllowed {
+                code_id: 7,
+                expected: REFLECT_ID
+            }
+        );
+
+        // and answer with the code we use
+        let open = mock_ibc_channel_open_try("channel-1", APP_ORDER, requested);
+        let res = ibc_channel_open(deps.as_mut(), mock_env(), open).unwrap();
+        let version = res.unwrap().version;
+        assert_eq!(
+            version,
+            r#"{"version":"simple-ica-v3","encoding":"json","account_type":"reflect","reflect_code_id":101,"max_accounts":2}"#
+        );
+
+        let mut channel = mock_ibc_channel("channel-1", APP_ORDER, &version);
+        channel.counterparty_endpoint.port_id = controller_port("acct-1");
+        let connect_msg = IbcChannelConnectMsg::new_confirm(channel);
+        let res = ibc_channel_connect(deps.as_mut(), mock_env(), connect_msg).unwrap();
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Instantiate2 { code_id, msg, .. }) => {
+                assert_eq!(*code_id, REFLECT_ID);
+                assert_eq!(msg.as_slice(), b"{}");
+            }
+            o => panic!("Unexpected message: {:?}", o),
+        }
+        reply(
+            deps.as_mut(),
+            mock_env(),
+            instantiated(res.messages[0].id, "acct-1"),
+        )
+        .unwrap();
+
+        // the negotiated options are kept with the channel
+        let query_msg = QueryMsg::Channel {
+            channel_id: "channel-1".into(),
+        };
+        let raw = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let channel: ChannelResponse = from_slice(&raw).unwrap();
+        assert_eq!(channel.version, IBC_APP_VERSION_V3);
+        assert_eq!(channel.account_type, AccountType::Reflect);
+        assert_eq!(channel.metadata.unwrap().to_version().unwrap(), version);
+
+        // one sub-account fits into max_accounts
+        let packet = PacketMsg::CreateAccount {
+            label: "strategy-a".into(),
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::Internal("Channel allows at most 2 accounts".into())
+        );
+    }
+
+    #[test]
+    fn outflow_limits_cap_dispatches() {
+        let mut deps = setup();
+        connect_with_version(deps.as_mut(), "channel-1", "acct-1", IBC_APP_VERSION_V3);
+        let hourly = OutflowLimit {
+            amount: coin(1000, "uatom"),
+            window: 3600,
+        };
+
+        // only the owner sets the limits of all accounts
+        let update = ExecuteMsg::UpdateOutflowLimits {
+            limits: vec![hourly.clone()],
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("controller", &[]),
+            update.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), update).unwrap();
+
+        let send = |amount: u128| PacketMsg::Dispatch {
+            msgs: vec![BankMsg::Send {
+                to_address: "bob".into(),
+                amount: coins(amount, "uatom"),
+            }
+            .into()],
+            mode: DispatchMode::Atomic,
+            account_id: 0,
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &send(600)).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+
+        // a failed dispatch sent nothing, so it does not count
+        let failed = Reply {
+            id: RECEIVE_DISPATCH_ID,
+            result: SubMsgResult::Err("insufficient funds".into()),
+        };
+        reply(deps.as_mut(), mock_env(), failed).unwrap();
+        let msg = mock_ibc_packet_recv("channel-1", &send(600)).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let msg = mock_ibc_packet_recv("channel-1", &send(600)).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::LimitExceeded("Outflow limit of 1000uatom per 3600s exceeded".into())
+        );
+
+        // stargate messages could move funds past the limits
+        let stargate = PacketMsg::Dispatch {
+            msgs: vec![CosmosMsg::Stargate {
+                type_url: "/cosmos.bank.v1beta1.MsgSend".into(),
+                value: Binary::default(),
+            }],
+            mode: DispatchMode::Atomic,
+            account_id: 0,
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &stargate).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::UnsupportedMsg(
+                "/cosmos.bank.v1beta1.MsgSend messages cannot be counted against the outflow limits"
+                    .into()
+            )
+        );
+
+        // the controller adds a stricter limit, which it cannot drop again
+        let per_minute = OutflowLimit {
+            amount: coin(100, "uatom"),
+            window: 60,
+        };
+        let packet = PacketMsg::SetOutflowLimits {
+            limits: vec![per_minute.clone()],
+            account_id: 0,
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let ack: StdAck = from_slice(&res.acknowledgement).unwrap();
+        ack.into_result().unwrap();
+        let packet = PacketMsg::SetOutflowLimits {
+            limits: vec![hourly],
+            account_id: 0,
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert!(matches!(err, RemoteError::UnsupportedMsg(_)));
+
+        let query_msg = QueryMsg::Account {
+            channel_id: "channel-1".into(),
+            account_id: 0,
+        };
+        let raw = query(deps.as_ref(), mock_env(), query_msg).unwrap();
+        let account: AccountResponse = from_slice(&raw).unwrap();
+        assert_eq!(account.outflow_limits, vec![per_minute]);
+
+        // an hour later the owner's limit has room again, but not the controller's
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let msg = mock_ibc_packet_recv("channel-1", &send(200)).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), env.clone(), msg).unwrap();
+        let ack: StdAck = from_slice(&res.acknowledgement).unwrap();
+        assert_eq!(
+            ack.into_result().unwrap_err(),
+            RemoteError::LimitExceeded("Outflow limit of 100uatom per 60s exceeded".into())
+        );
+        let msg = mock_ibc_packet_recv("channel-1", &send(100)).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), env, msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+    }
+
+    #[test]
+    fn msg_policies_block_dispatches() {
+        let mut deps = setup();
+        connect_with_version(deps.as_mut(), "channel-1", "acct-1", IBC_APP_VERSION_V3);
+
+        let dispatch = |msg: CosmosMsg| PacketMsg::Dispatch {
+            msgs: vec![msg],
+            mode: DispatchMode::Atomic,
+            account_id: 0,
+        };
+        let migrate: CosmosMsg = WasmMsg::Migrate {
+            contract_addr: "pool".into(),
+            new_code_id: 7,
+            msg: to_binary(&Empty {}).unwrap(),
+        }
+        .into();
+        let send: CosmosMsg = BankMsg::Send {
+            to_address: "bob".into(),
+            amount: coins(10, "uatom"),
+        }
+        .into();
+
+        // the owner blocks migrations for every account
+        let update = ExecuteMsg::UpdateMsgPolicy {
+            account: None,
+            blocked_msgs: vec!["wasm/migrate".into()],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), update).unwrap();
+        let msg = mock_ibc_packet_recv("channel-1", &dispatch(migrate)).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::UnsupportedMsg(
+                "wasm/migrate messages are blocked by the host policy wasm/migrate".into()
+            )
+        );
+        let msg = mock_ibc_packet_recv("channel-1", &dispatch(send.clone())).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+
+        // the controller blocks all bank messages of its account
+        let packet = PacketMsg::BlockMsgs {
+            kinds: vec!["bank".into()],
+            account_id: 0,
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let ack: StdAck = from_slice(&res.acknowledgement).unwrap();
+        ack.into_result().unwrap();
+        let msg = mock_ibc_packet_recv("channel-1", &dispatch(send.clone())).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert!(matches!(err, RemoteError::UnsupportedMsg(_)));
+
+        let query_msg = QueryMsg::Account {
+            channel_id: "channel-1".into(),
+            account_id: 0,
+        };
+        let raw = query(deps.as_ref(), mock_env(), query_msg.clone()).unwrap();
+        let account: AccountResponse = from_slice(&raw).unwrap();
+        assert_eq!(account.blocked_msgs, vec!["bank".to_string()]);
+
+        // only the owner lifts the rules of an account
+        let channel = CHANNELS.load(&deps.storage, "channel-1").unwrap();
+        let update = ExecuteMsg::UpdateMsgPolicy {
+            account: Some(AccountKey {
+                connection_id: channel.connection_id,
+                counterparty_port: channel.counterparty_port,
+                account_id: 0,
+            }),
+            blocked_msgs: vec![],
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("controller", &[]),
+            update.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), update).unwrap();
+        let msg = mock_ibc_packet_recv("channel-1", &dispatch(send)).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+    }
+
+    #[test]
+    fn spam_limits_charge_accounts_and_cap_packets() {
+        let mut deps = setup();
+        let account = "acct-1";
+        connect_with_version(deps.as_mut(), "channel-1", account, IBC_APP_VERSION_V3);
+
+        let update = ExecuteMsg::UpdateSpamLimits {
+            max_accounts_per_connection: None,
+            account_fee: Some(coin(100, "uatom")),
+            treasury: Some("treasury".into()),
+            rate_limit: Some(RateLimit {
+                packets: 2,
+                blocks: 10,
+                close_channel: true,
+            }),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info(CREATOR, &[]), update).unwrap();
+
+        // a new account needs a deposit for its fee
+        let packet = PacketMsg::CreateAccount {
+            label: "strategy-a".into(),
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let err = receive_error(deps.as_mut(), msg, Encoding::Json);
+        assert_eq!(
+            err,
+            RemoteError::LimitExceeded("Creating an account needs a deposit of 100uatom".into())
+        );
+        let deposit = ExecuteMsg::Deposit {
+            connection_id: "connection-2".into(),
+            counterparty_port: controller_port(account),
+        };
+        let info = mock_info("anyone", &coins(150, "uatom"));
+        execute(deps.as_mut(), mock_env(), info, deposit).unwrap();
+
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        // the fee is paid once the account exists
+        let response = Reply {
+            id: res.messages[0].id,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: fake_events("sub-acct-1"),
+                data: Some(fake_data("sub-acct-1")),
+            }),
+        };
+        let res = reply(deps.as_mut(), mock_env(), response).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "treasury".into(),
+                amount: coins(100, "uatom"),
+            })
+        );
+        assert_eq!(
+            query_recovery(deps.as_ref(), account).deposit,
+            coins(50, "uatom")
+        );
+        // the depositor can take back what is left
+        let withdraw = ExecuteMsg::Withdraw {
+            connection_id: "connection-2".into(),
+            counterparty_port: controller_port(account),
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("someone-else", &[]),
+            withdraw.clone(),
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::NoDeposit);
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("anyone", &[]),
+            withdraw,
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "anyone".into(),
+                amount: coins(50, "uatom"),
+            })
+        );
+        assert_eq!(query_recovery(deps.as_ref(), account).deposit, vec![]);
+
+        // the third packet within 10 blocks closes the channel
+        let packet = PacketMsg::WhoAmI { account_id: 0 };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let ack: StdAck = from_slice(&res.acknowledgement).unwrap();
+        assert_eq!(
+            ack.into_result().unwrap_err(),
+            RemoteError::LimitExceeded("Rate limit of 2 packets per 10 blocks exceeded".into())
+        );
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Ibc(IbcMsg::CloseChannel {
+                channel_id: "channel-1".into()
+            })
+        );
+        let mut env = mock_env();
+        env.block.height += 10;
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), env, msg).unwrap();
+        let ack: StdAck = from_slice(&res.acknowledgement).unwrap();
+        ack.into_result().unwrap();
+    }
+
+    #[test]
+    fn list_accounts_in_pages_with_stats() {
+        let mut deps = setup();
+        for (channel_id, account) in [("channel-1", "acct-1"), ("channel-2", "acct-2")] {
+            connect_with_version(deps.as_mut(), channel_id, account, IBC_APP_VERSION_V3);
+        }
+        let packet = PacketMsg::CreateAccount {
+            label: "strategy-a".into(),
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+        let response = Reply {
+            id: res.messages[0].id,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: fake_events("sub-acct-1"),
+                data: Some(fake_data("sub-acct-1")),
+            }),
+        };
+        reply(deps.as_mut(), mock_env(), response).unwrap();
+
+        // a dispatch to the sub-account counts for it alone
+        let packet = PacketMsg::Dispatch {
+            msgs: vec![BankMsg::Burn {
+                amount: coins(1, "uatom"),
+            }
+            .into()],
+            mode: DispatchMode::Atomic,
+            account_id: 1,
+        };
+        let msg = mock_ibc_packet_recv("channel-1", &packet).unwrap();
+        ibc_packet_receive(deps.as_mut(), mock_env(), msg).unwrap();
+
+        let list = |start_after: Option<ChannelAccount>| {
+            let msg = QueryMsg::ListAccounts {
+                start_after,
+                limit: Some(2),
+            };
+            let raw = query(deps.as_ref(), mock_env(), msg).unwrap();
+            let res: ListAccountsResponse = from_slice(&raw).unwrap();
+            res.accounts
+        };
+        let page = list(None);
+        let keys: Vec<_> = page
+            .iter()
+            .map(|a| (a.channel_id.as_str(), a.account_id))
+            .collect();
+        assert_eq!(keys, vec![("channel-1", 0), ("channel-1", 1)]);
+        assert_eq!(page[0].controller.as_deref(), Some("controller-of-acct-1"));
+        assert_eq!(page[0].stats.packets, 1);
+        assert_eq!(page[0].stats.dispatches, 0);
+        assert_eq!(page[1].stats.packets, 1);
+        assert_eq!(page[1].stats.dispatches, 1);
+        assert_eq!(page[1].stats.created, Some(mock_env().block.time));
+
+        let page = list(Some(ChannelAccount {
+            channel_id: "channel-1".into(),
+            account_id: 1,
+        }));
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].channel_id, "channel-2");
+
+        // the reflect address leads back to the controller
+        let msg = QueryMsg::AccountByAddress {
+            address: "sub-acct-1".into(),
+        };
+        let raw = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let res: AccountByAddressResponse = from_slice(&raw).unwrap();
+        assert_eq!(
+            res,
+            AccountByAddressResponse {
+                connection_id: "connection-2".into(),
+                counterparty_port: controller_port("acct-1"),
+                controller: Some("controller-of-acct-1".into()),
+                account_id: 1,
+                channel_id: Some("channel-1".into()),
+            }
+        );
+
+        // another channel of the same controller does not list its accounts again
+        connect_with_version(deps.as_mut(), "channel-3", "acct-1", IBC_APP_VERSION_V3);
+        let msg = QueryMsg::ListAccounts {
+            start_after: None,
+            limit: None,
+        };
+        let raw = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let res: ListAccountsResponse = from_slice(&raw).unwrap();
+        let keys: Vec<_> = res
+            .accounts
+            .iter()
+            .map(|a| (a.channel_id.as_str(), a.account_id))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![("channel-1", 0), ("channel-1", 1), ("channel-2", 0)]
+        );
     }
 }
@@ -0,0 +1,137 @@
+//! Chain specific messages sent with `PacketMsg::DispatchCustom`.
+//!
+//! The custom message type of the host chain is picked with a cargo feature.
+//! Without any, `HostCustomMsg` is `Empty` and custom payloads are rejected,
+//! while Stargate messages always go through. The reflect contract must be
+//! built with the same custom message type to be able to execute them.
+
+use cosmwasm_std::{CosmosMsg, Empty};
+use simple_ica::RemoteMsg;
+
+use crate::error::ContractError;
+
+#[cfg(feature = "osmosis")]
+pub use osmosis::OsmosisMsg as HostCustomMsg;
+
+#[cfg(not(feature = "osmosis"))]
+pub type HostCustomMsg = Empty;
+
+#[cfg(feature = "osmosis")]
+mod osmosis {
+    use cosmwasm_std::{CustomMsg, Uint128};
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    /// The token factory messages of the Osmosis wasm bindings
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+    #[serde(rename_all = "snake_case")]
+    pub enum OsmosisMsg {
+        CreateDenom {
+            subdenom: String,
+        },
+        ChangeAdmin {
+            denom: String,
+            new_admin_address: String,
+        },
+        MintTokens {
+            denom: String,
+            amount: Uint128,
+            mint_to_address: String,
+        },
+        BurnTokens {
+            denom: String,
+            amount: Uint128,
+            burn_from_address: String,
+        },
+    }
+
+    impl CustomMsg for OsmosisMsg {}
+}
+
+/// Turns a message from the packet into one the reflect contract can execute here
+pub fn into_host_msg(msg: RemoteMsg) -> Result<CosmosMsg<HostCustomMsg>, ContractError> {
+    match msg {
+        RemoteMsg::Cosmos(msg) => change_custom(msg),
+        RemoteMsg::Stargate { type_url, value } => Ok(CosmosMsg::Stargate { type_url, value }),
+        RemoteMsg::Custom(raw) => decode_custom(&raw).map(CosmosMsg::Custom),
+    }
+}
+
+#[cfg(feature = "osmosis")]
+fn decode_custom(raw: &[u8]) -> Result<HostCustomMsg, ContractError> {
+    cosmwasm_std::from_slice(raw).map_err(|e| ContractError::InvalidPacket(e.to_string()))
+}
+
+#[cfg(not(feature = "osmosis"))]
+fn decode_custom(_raw: &[u8]) -> Result<HostCustomMsg, ContractError> {
+    Err(ContractError::CustomMsgsNotSupported)
+}
+
+fn change_custom(msg: CosmosMsg<Empty>) -> Result<CosmosMsg<HostCustomMsg>, ContractError> {
+    let msg = match msg {
+        CosmosMsg::Bank(msg) => CosmosMsg::Bank(msg),
+        CosmosMsg::Staking(msg) => CosmosMsg::Staking(msg),
+        CosmosMsg::Distribution(msg) => CosmosMsg::Distribution(msg),
+        CosmosMsg::Stargate { type_url, value } => CosmosMsg::Stargate { type_url, value },
+        CosmosMsg::Ibc(msg) => CosmosMsg::Ibc(msg),
+        CosmosMsg::Wasm(msg) => CosmosMsg::Wasm(msg),
+        CosmosMsg::Gov(msg) => CosmosMsg::Gov(msg),
+        // an empty custom message carries nothing we could execute
+        _ => return Err(ContractError::CustomMsgsNotSupported),
+    };
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{coins, BankMsg, Binary};
+
+    #[test]
+    fn cosmos_and_stargate_msgs_pass_through() {
+        let bank: CosmosMsg = BankMsg::Send {
+            to_address: "friend".into(),
+            amount: coins(123, "uatom"),
+        }
+        .into();
+        let msg = into_host_msg(RemoteMsg::Cosmos(bank)).unwrap();
+        assert!(matches!(msg, CosmosMsg::Bank(BankMsg::Send { .. })));
+
+        let stargate = RemoteMsg::Stargate {
+            type_url: "/cosmos.bank.v1beta1.MsgSend".into(),
+            value: Binary::from(b"\x0a\x05hello"),
+        };
+        let msg = into_host_msg(stargate).unwrap();
+        assert_eq!(
+            msg,
+            CosmosMsg::Stargate {
+                type_url: "/cosmos.bank.v1beta1.MsgSend".into(),
+                value: Binary::from(b"\x0a\x05hello"),
+            }
+        );
+    }
+
+    #[cfg(not(feature = "osmosis"))]
+    #[test]
+    fn custom_msgs_need_a_feature() {
+        let custom = RemoteMsg::Custom(br#"{"create_denom":{"subdenom":"ica"}}"#.into());
+        let err = into_host_msg(custom).unwrap_err();
+        assert_eq!(err, ContractError::CustomMsgsNotSupported);
+    }
+
+    #[cfg(feature = "osmosis")]
+    #[test]
+    fn custom_msgs_are_decoded() {
+        let custom = RemoteMsg::Custom(br#"{"create_denom":{"subdenom":"ica"}}"#.into());
+        let msg = into_host_msg(custom).unwrap();
+        assert_eq!(
+            msg,
+            CosmosMsg::Custom(HostCustomMsg::CreateDenom {
+                subdenom: "ica".into()
+            })
+        );
+
+        let unknown = RemoteMsg::Custom(br#"{"unknown":{}}"#.into());
+        into_host_msg(unknown).unwrap_err();
+    }
+}
@@ -1,9 +1,9 @@
 use thiserror::Error;
 
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Coin, StdError};
 use cw_utils::ParseReplyError;
 
-use simple_ica::SimpleIcaError;
+use simple_ica::{ErrorCode, SimpleIcaError};
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ContractError {
@@ -21,4 +21,117 @@ pub enum ContractError {
 
     #[error("Invalid reply id")]
     InvalidReplyId,
+
+    #[error("Channel version does not support {0}")]
+    FeatureNotSupported(String),
+
+    #[error("This host has no custom message type")]
+    CustomMsgsNotSupported,
+
+    #[error("No account for channel {0}")]
+    UnregisteredChannel(String),
+
+    #[error("No account {account_id} for channel {channel}")]
+    UnknownAccount { channel: String, account_id: u64 },
+
+    #[error("Invalid packet: {0}")]
+    InvalidPacket(String),
+
+    #[error("Query failed: {0}")]
+    QueryFailed(String),
+
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    #[error("New channels are paused")]
+    ChannelsPaused,
+
+    #[error("Packet processing is paused")]
+    PacketsPaused,
+
+    #[error("Connection {0} may not open channels")]
+    ConnectionNotAllowed(String),
+
+    #[error("Counterparty port {0} may not open channels")]
+    PortNotAllowed(String),
+
+    #[error("Accounts must use the current reflect code {expected}, not {code_id}")]
+    CodeNotAllowed { code_id: u64, expected: u64 },
+
+    #[error("Migration failed: {0}")]
+    MigrationFailed(String),
+
+    #[error("Unknown account type {0}")]
+    UnknownAccountType(String),
+
+    #[error("Channel allows at most {0} accounts")]
+    TooManyAccounts(u64),
+
+    #[error("Outflow limit of {limit} per {window}s exceeded")]
+    OutflowLimitExceeded { limit: Coin, window: u64 },
+
+    #[error("Outflow limits can only be tightened, {limit} per {window}s is missing")]
+    OutflowLimitLoosened { limit: Coin, window: u64 },
+
+    #[error("Invalid outflow limit: {0}")]
+    InvalidOutflowLimit(String),
+
+    #[error("{kind} messages cannot be counted against the outflow limits")]
+    UncountedOutflow { kind: String },
+
+    #[error("{kind} messages are blocked by the host policy {rule}")]
+    MsgBlocked { kind: String, rule: String },
+
+    #[error("Connection {connection_id} allows at most {max} accounts")]
+    TooManyConnectionAccounts { connection_id: String, max: u64 },
+
+    #[error("Creating an account needs a deposit of {0}")]
+    InsufficientDeposit(Coin),
+
+    #[error("Rate limit of {packets} packets per {blocks} blocks exceeded")]
+    RateLimited { packets: u64, blocks: u64 },
+
+    #[error("Invalid rate limit: {0}")]
+    InvalidRateLimit(String),
+
+    #[error("No funds to deposit")]
+    EmptyDeposit,
+
+    #[error("Nothing deposited for this controller")]
+    NoDeposit,
+
+    #[error("Dispatch failed: {0}")]
+    DispatchFailed(String),
+
+    #[error("Creating the account failed: {0}")]
+    AccountCreationFailed(String),
+}
+
+impl ContractError {
+    /// The code we report in the error ack for this error
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            ContractError::InvalidPacket(_) => ErrorCode::InvalidPacket,
+            ContractError::UnregisteredChannel(_) | ContractError::UnknownAccount { .. } => {
+                ErrorCode::UnknownChannel
+            }
+            ContractError::FeatureNotSupported(_) => ErrorCode::FeatureNotSupported,
+            ContractError::CustomMsgsNotSupported
+            | ContractError::CodeNotAllowed { .. }
+            | ContractError::OutflowLimitLoosened { .. }
+            | ContractError::UncountedOutflow { .. }
+            | ContractError::MsgBlocked { .. } => ErrorCode::UnsupportedMsg,
+            ContractError::InvalidOutflowLimit(_) => ErrorCode::InvalidPacket,
+            ContractError::OutflowLimitExceeded { .. }
+            | ContractError::TooManyConnectionAccounts { .. }
+            | ContractError::InsufficientDeposit(_)
+            | ContractError::RateLimited { .. } => ErrorCode::LimitExceeded,
+            ContractError::QueryFailed(_) => ErrorCode::QueryFailed,
+            ContractError::PacketsPaused => ErrorCode::Paused,
+            ContractError::DispatchFailed(_) | ContractError::AccountCreationFailed(_) => {
+                ErrorCode::ExecutionFailed
+            }
+            _ => ErrorCode::Internal,
+        }
+    }
 }
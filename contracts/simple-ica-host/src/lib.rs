@@ -1,4 +1,9 @@
+pub mod account;
 pub mod contract;
+pub mod custom;
 pub mod error;
 pub mod msg;
+pub mod outflow;
+pub mod policy;
+pub mod spam;
 pub mod state;
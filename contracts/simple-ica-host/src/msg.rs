@@ -1,11 +1,100 @@
-use cosmwasm_std::CosmosMsg;
+use cosmwasm_std::Coin;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-/// Just needs to know the code_id of a reflect contract to spawn sub-accounts
+use crate::account::AccountType;
+use simple_ica::{ChannelMetadata, Encoding, OutflowLimit};
+
+use crate::state::{AccountStats, ClosePolicy, MigrationStatus, RateLimit, SpamLimits};
+
+/// Just needs to know the code_id of a reflect contract to spawn sub-accounts.
+/// Owner of the contract is whoever signed the InstantiateMsg.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     pub reflect_code_id: u64,
+    /// The kind of contract `reflect_code_id` is, cw1-whitelist by default
+    #[serde(default)]
+    pub account_type: AccountType,
+}
+
+/// Only the owner may execute these, except for `Deposit` and `Withdraw`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecuteMsg {
+    /// Changes the given fields, leaving the others as they are.
+    /// A new reflect code id or account type only applies to channels connected afterwards.
+    UpdateConfig {
+        reflect_code_id: Option<u64>,
+        account_type: Option<AccountType>,
+        channels_paused: Option<bool>,
+        packets_paused: Option<bool>,
+        close_policy: Option<ClosePolicy>,
+    },
+    TransferOwnership {
+        new_owner: String,
+    },
+    /// Manages which connections and counterparty ports (e.g. `wasm.<controller address>`)
+    /// may open channels. While a list is empty, it allows everything.
+    UpdateAllowlist {
+        #[serde(default)]
+        add_connections: Vec<String>,
+        #[serde(default)]
+        remove_connections: Vec<String>,
+        #[serde(default)]
+        add_ports: Vec<String>,
+        #[serde(default)]
+        remove_ports: Vec<String>,
+    },
+    /// Migrates the accounts of all controllers to `new_code_id`, at most `limit`
+    /// of them (default 10, max 30) after `start_after`
+    MigrateAccounts {
+        new_code_id: u64,
+        start_after: Option<AccountKey>,
+        limit: Option<u32>,
+    },
+    /// Replaces the caps on what every account may send out. Controllers can add
+    /// stricter ones for their own accounts.
+    UpdateOutflowLimits {
+        limits: Vec<OutflowLimit>,
+    },
+    /// Replaces the kinds of messages (e.g. `wasm/migrate`, `staking` or a stargate
+    /// type url) that all accounts, or the given one, may not dispatch
+    UpdateMsgPolicy {
+        account: Option<AccountKey>,
+        blocked_msgs: Vec<String>,
+    },
+    /// Replaces the limits on new accounts and packets, `None` turns a limit off
+    UpdateSpamLimits {
+        max_accounts_per_connection: Option<u64>,
+        account_fee: Option<Coin>,
+        treasury: Option<String>,
+        rate_limit: Option<RateLimit>,
+    },
+    /// Prepays the account fees of a controller with the sent funds. Anyone may deposit.
+    Deposit {
+        connection_id: String,
+        counterparty_port: String,
+    },
+    /// Sends the sender back what is left of its deposit for the controller
+    Withdraw {
+        connection_id: String,
+        counterparty_port: String,
+    },
+}
+
+/// Identifies an account by the channel its controller is connected over
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ChannelAccount {
+    pub channel_id: String,
+    pub account_id: u64,
+}
+
+/// Identifies an account by its controller, independent of any channel
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccountKey {
+    pub connection_id: String,
+    pub counterparty_port: String,
+    pub account_id: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -13,15 +102,105 @@ pub struct InstantiateMsg {
 pub enum QueryMsg {
     /// Returns (reflect) account that is attached to this channel,
     /// or none.
-    Account { channel_id: String },
-    /// Returns all (channel, reflect_account) pairs.
-    /// No pagination - this is a test contract
-    ListAccounts {},
+    Account {
+        channel_id: String,
+        /// Defaults to the account created on connect
+        #[serde(default)]
+        account_id: u64,
+    },
+    /// Returns the (reflect) account of a controller, whether it has an open channel or not
+    ControllerAccount {
+        connection_id: String,
+        counterparty_port: String,
+        #[serde(default)]
+        account_id: u64,
+    },
+    /// Returns the accounts of open channels by channel and account id, at most
+    /// `limit` of them (default 10, max 30) after `start_after`
+    ListAccounts {
+        start_after: Option<ChannelAccount>,
+        limit: Option<u32>,
+    },
+    /// Returns the controller of a reflect account
+    AccountByAddress { address: String },
+    /// Returns the version, controller and account options of an open channel
+    Channel { channel_id: String },
+    /// Returns the owner and the current runtime configuration
+    Config {},
+    /// Returns the connections and counterparty ports allowed to open channels
+    Allowlist {},
+    /// Returns where a controller's funds go when its channel closes,
+    /// what the host holds in escrow for it and its deposit for account fees
+    Recovery {
+        connection_id: String,
+        counterparty_port: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub owner: String,
+    pub reflect_code_id: u64,
+    pub account_type: AccountType,
+    pub channels_paused: bool,
+    pub packets_paused: bool,
+    pub close_policy: ClosePolicy,
+    pub outflow_limits: Vec<OutflowLimit>,
+    pub blocked_msgs: Vec<String>,
+    pub spam_limits: SpamLimits,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ChannelResponse {
+    pub version: String,
+    pub encoding: Encoding,
+    pub connection_id: String,
+    pub counterparty_port: String,
+    pub account_type: AccountType,
+    /// The metadata negotiated in the handshake, if the channel was opened with any
+    pub metadata: Option<ChannelMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RecoveryResponse {
+    pub return_address: Option<String>,
+    pub escrow: Vec<AccountEscrow>,
+    pub deposit: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccountEscrow {
+    pub account_id: u64,
+    pub funds: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllowlistResponse {
+    pub connections: Vec<String>,
+    pub ports: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
 pub struct AccountResponse {
     pub account: Option<String>,
+    /// The last migration of the account, if it was ever migrated
+    pub migration: Option<MigrationStatus>,
+    /// The limits the controller set, on top of the owner's
+    pub outflow_limits: Vec<OutflowLimit>,
+    /// The kinds of messages blocked for this account, on top of the global ones
+    pub blocked_msgs: Vec<String>,
+    pub stats: AccountStats,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccountByAddressResponse {
+    pub connection_id: String,
+    pub counterparty_port: String,
+    /// The controller contract, if the port is `wasm.<address>`
+    pub controller: Option<String>,
+    pub account_id: u64,
+    /// The open channel of the controller, if it has one
+    pub channel_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -32,11 +211,12 @@ pub struct ListAccountsResponse {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct AccountInfo {
     pub account: String,
+    pub account_id: u64,
     pub channel_id: String,
-}
-
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-#[serde(rename_all = "snake_case")]
-pub enum ReflectExecuteMsg {
-    ReflectMsg { msgs: Vec<CosmosMsg> },
+    pub connection_id: String,
+    pub counterparty_port: String,
+    /// The controller contract, if the port is `wasm.<address>`
+    pub controller: Option<String>,
+    pub migration: Option<MigrationStatus>,
+    pub stats: AccountStats,
 }
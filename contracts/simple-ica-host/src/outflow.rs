@@ -0,0 +1,297 @@
+//! Limits on the funds an account may send out, as a defense against a
+//! compromised controller.
+//!
+//! The owner's limits apply to every account, a controller can add stricter ones
+//! for its own accounts. Every dispatch is counted when it is received and
+//! released again if its messages fail. While any limit applies, messages we
+//! cannot count are refused.
+
+use cosmwasm_std::{BankMsg, Coin, CosmosMsg, IbcMsg, Storage, Timestamp, Uint128, WasmMsg};
+use simple_ica::OutflowLimit;
+
+use crate::error::ContractError;
+use crate::policy::msg_kind;
+use crate::state::{Outflow, OUTFLOWS};
+
+/// The funds the messages take out of the account. Only bank, ICS-20 and wasm
+/// messages are counted, stargate and chain specific messages are opaque to us.
+pub fn outgoing_funds<T>(msgs: &[CosmosMsg<T>]) -> Vec<Coin> {
+    msgs.iter()
+        .flat_map(|msg| match msg {
+            CosmosMsg::Bank(BankMsg::Send { amount, .. })
+            | CosmosMsg::Bank(BankMsg::Burn { amount }) => amount.clone(),
+            CosmosMsg::Ibc(IbcMsg::Transfer { amount, .. }) => vec![amount.clone()],
+            CosmosMsg::Wasm(WasmMsg::Execute { funds, .. })
+            | CosmosMsg::Wasm(WasmMsg::Instantiate { funds, .. })
+            | CosmosMsg::Wasm(WasmMsg::Instantiate2 { funds, .. }) => funds.clone(),
+            _ => vec![],
+        })
+        .collect()
+}
+
+/// Stargate and chain specific messages may move funds without us seeing them,
+/// so they are only allowed for accounts without limits
+pub fn check_countable<T>(
+    msgs: &[CosmosMsg<T>],
+    limits: &[OutflowLimit],
+) -> Result<(), ContractError> {
+    if limits.is_empty() {
+        return Ok(());
+    }
+    match msgs
+        .iter()
+        .find(|msg| matches!(msg, CosmosMsg::Stargate { .. } | CosmosMsg::Custom(_)))
+    {
+        Some(msg) => Err(ContractError::UncountedOutflow {
+            kind: msg_kind(msg),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Adds the funds to the recent outflow of the account and fails if that
+/// breaks any of the limits
+pub fn record_outflow(
+    storage: &mut dyn Storage,
+    account: (&str, &str, u64),
+    now: Timestamp,
+    limits: &[OutflowLimit],
+    funds: Vec<Coin>,
+) -> Result<(), ContractError> {
+    let funds: Vec<Coin> = funds.into_iter().filter(|c| !c.amount.is_zero()).collect();
+    if limits.is_empty() || funds.is_empty() {
+        return Ok(());
+    }
+    let now = now.seconds();
+    let longest = limits.iter().map(|l| l.window).max().unwrap_or_default();
+
+    let mut outflows = OUTFLOWS.may_load(storage, account)?.unwrap_or_default();
+    // forget what no limit looks at anymore
+    outflows.retain(|o| o.time + longest > now);
+    outflows.extend(
+        funds
+            .into_iter()
+            .map(|amount| Outflow { time: now, amount }),
+    );
+
+    for limit in limits {
+        let sent: Uint128 = outflows
+            .iter()
+            .filter(|o| o.amount.denom == limit.amount.denom && o.time + limit.window > now)
+            .map(|o| o.amount.amount)
+            .sum();
+        if sent > limit.amount.amount {
+            return Err(ContractError::OutflowLimitExceeded {
+                limit: limit.amount.clone(),
+                window: limit.window,
+            });
+        }
+    }
+    OUTFLOWS.save(storage, account, &outflows)?;
+    Ok(())
+}
+
+/// Takes funds recorded at `now` out of the outflow again, once the messages
+/// that would have sent them failed
+pub fn release_outflow(
+    storage: &mut dyn Storage,
+    account: (&str, &str, u64),
+    now: Timestamp,
+    funds: &[Coin],
+) -> Result<(), ContractError> {
+    let mut outflows = match OUTFLOWS.may_load(storage, account)? {
+        Some(outflows) => outflows,
+        None => return Ok(()),
+    };
+    let now = now.seconds();
+    for amount in funds {
+        if let Some(i) = outflows
+            .iter()
+            .position(|o| o.time == now && &o.amount == amount)
+        {
+            outflows.remove(i);
+        }
+    }
+    OUTFLOWS.save(storage, account, &outflows)?;
+    Ok(())
+}
+
+/// Limits need a window to count in
+pub fn validate_limits(limits: &[OutflowLimit]) -> Result<(), ContractError> {
+    match limits.iter().find(|l| l.window == 0) {
+        Some(limit) => Err(ContractError::InvalidOutflowLimit(format!(
+            "{} has no window",
+            limit.amount
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// A controller may only replace its limits with ones at least as strict
+pub fn check_tightened(
+    current: &[OutflowLimit],
+    new: &[OutflowLimit],
+) -> Result<(), ContractError> {
+    for limit in current {
+        if !new.iter().any(|l| l.is_within(limit)) {
+            return Err(ContractError::OutflowLimitLoosened {
+                limit: limit.amount.clone(),
+                window: limit.window,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use cosmwasm_std::{coin, coins, to_binary, Binary, Empty, IbcTimeout};
+
+    const ACCOUNT: (&str, &str, u64) = ("connection-1", "wasm.controller", 0);
+
+    fn limit(amount: u128, window: u64) -> OutflowLimit {
+        OutflowLimit {
+            amount: coin(amount, "uatom"),
+            window,
+        }
+    }
+
+    #[test]
+    fn count_outgoing_funds() {
+        let msgs: Vec<CosmosMsg<Empty>> = vec![
+            BankMsg::Send {
+                to_address: "bob".into(),
+                amount: vec![coin(1, "uatom"), coin(2, "uosmo")],
+            }
+            .into(),
+            IbcMsg::Transfer {
+                channel_id: "channel-9".into(),
+                to_address: "alice".into(),
+                amount: coin(3, "uatom"),
+                timeout: IbcTimeout::with_timestamp(Timestamp::from_seconds(100)),
+            }
+            .into(),
+            WasmMsg::Execute {
+                contract_addr: "pool".into(),
+                msg: to_binary(&Empty {}).unwrap(),
+                funds: coins(4, "uatom"),
+            }
+            .into(),
+            BankMsg::Burn {
+                amount: coins(5, "uatom"),
+            }
+            .into(),
+        ];
+        let funds = outgoing_funds(&msgs);
+        assert_eq!(
+            funds,
+            vec![
+                coin(1, "uatom"),
+                coin(2, "uosmo"),
+                coin(3, "uatom"),
+                coin(4, "uatom"),
+                coin(5, "uatom")
+            ]
+        );
+    }
+
+    #[test]
+    fn opaque_msgs_need_no_limits() {
+        let limits = [limit(100, 60)];
+        let stargate: Vec<CosmosMsg<Empty>> = vec![CosmosMsg::Stargate {
+            type_url: "/cosmos.bank.v1beta1.MsgSend".into(),
+            value: Binary::default(),
+        }];
+        let mut msgs: Vec<CosmosMsg<String>> = vec![BankMsg::Burn {
+            amount: coins(1, "uatom"),
+        }
+        .into()];
+        check_countable(&msgs, &limits).unwrap();
+        check_countable(&stargate, &[]).unwrap();
+        assert_eq!(
+            check_countable(&stargate, &limits).unwrap_err(),
+            ContractError::UncountedOutflow {
+                kind: "/cosmos.bank.v1beta1.MsgSend".into()
+            }
+        );
+
+        msgs.push(CosmosMsg::Custom("send".into()));
+        check_countable(&msgs, &[]).unwrap();
+        assert_eq!(
+            check_countable(&msgs, &limits).unwrap_err(),
+            ContractError::UncountedOutflow {
+                kind: "custom".into()
+            }
+        );
+    }
+
+    #[test]
+    fn outflow_within_window() {
+        let mut storage = MockStorage::new();
+        let limits = [limit(100, 60), limit(150, 3600)];
+        let start = Timestamp::from_seconds(1000);
+
+        record_outflow(&mut storage, ACCOUNT, start, &limits, coins(80, "uatom")).unwrap();
+        // other denoms are not limited
+        record_outflow(&mut storage, ACCOUNT, start, &limits, coins(500, "uosmo")).unwrap();
+        let err = record_outflow(&mut storage, ACCOUNT, start, &limits, coins(21, "uatom"));
+        assert_eq!(
+            err.unwrap_err(),
+            ContractError::OutflowLimitExceeded {
+                limit: coin(100, "uatom"),
+                window: 60
+            }
+        );
+
+        // a minute later only the hourly limit is left
+        let later = start.plus_seconds(60);
+        record_outflow(&mut storage, ACCOUNT, later, &limits, coins(70, "uatom")).unwrap();
+        let err = record_outflow(&mut storage, ACCOUNT, later, &limits, coins(1, "uatom"));
+        assert_eq!(
+            err.unwrap_err(),
+            ContractError::OutflowLimitExceeded {
+                limit: coin(150, "uatom"),
+                window: 3600
+            }
+        );
+
+        // without limits nothing is counted
+        record_outflow(&mut storage, ACCOUNT, later, &[], coins(1000, "uatom")).unwrap();
+        let outflows = OUTFLOWS.load(&storage, ACCOUNT).unwrap();
+        assert_eq!(outflows.len(), 3);
+
+        // failed messages give their funds back
+        release_outflow(&mut storage, ACCOUNT, later, &coins(70, "uatom")).unwrap();
+        record_outflow(&mut storage, ACCOUNT, later, &limits, coins(70, "uatom")).unwrap();
+        // but only what was recorded in this block
+        release_outflow(&mut storage, ACCOUNT, later, &coins(80, "uatom")).unwrap();
+        let outflows = OUTFLOWS.load(&storage, ACCOUNT).unwrap();
+        assert_eq!(outflows.len(), 3);
+    }
+
+    #[test]
+    fn limits_only_tighten() {
+        let current = [limit(100, 60)];
+        check_tightened(&current, &[limit(50, 60)]).unwrap();
+        check_tightened(&current, &[limit(100, 120), limit(10, 1)]).unwrap();
+        check_tightened(&[], &[limit(100, 60)]).unwrap();
+
+        let loosened = ContractError::OutflowLimitLoosened {
+            limit: coin(100, "uatom"),
+            window: 60,
+        };
+        assert_eq!(check_tightened(&current, &[]).unwrap_err(), loosened);
+        assert_eq!(
+            check_tightened(&current, &[limit(101, 60)]).unwrap_err(),
+            loosened
+        );
+        assert_eq!(
+            check_tightened(&current, &[limit(100, 59)]).unwrap_err(),
+            loosened
+        );
+
+        validate_limits(&[limit(100, 0)]).unwrap_err();
+    }
+}
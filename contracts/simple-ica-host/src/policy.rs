@@ -0,0 +1,141 @@
+//! Kinds of messages the host refuses to dispatch for an account.
+//!
+//! A rule names a message kind (see `msg_kind`) or a prefix of it, ending before
+//! a `/` or `.`: `staking` blocks all staking messages, `/cosmos.gov` all stargate
+//! messages of the gov module. The owner's rules apply to every account or to a
+//! single one, controllers can only add rules for their own accounts.
+
+use cosmwasm_std::{BankMsg, CosmosMsg, DistributionMsg, GovMsg, IbcMsg, StakingMsg, WasmMsg};
+
+use crate::error::ContractError;
+
+/// Names the kind of a message, e.g. `bank/send`, `wasm/migrate` or `gov/vote`.
+/// Stargate messages go by their type url, chain specific ones are `custom`.
+pub fn msg_kind<T>(msg: &CosmosMsg<T>) -> String {
+    let kind = match msg {
+        CosmosMsg::Bank(BankMsg::Send { .. }) => "bank/send",
+        CosmosMsg::Bank(BankMsg::Burn { .. }) => "bank/burn",
+        CosmosMsg::Bank(_) => "bank",
+        CosmosMsg::Custom(_) => "custom",
+        CosmosMsg::Staking(StakingMsg::Delegate { .. }) => "staking/delegate",
+        CosmosMsg::Staking(StakingMsg::Undelegate { .. }) => "staking/undelegate",
+        CosmosMsg::Staking(StakingMsg::Redelegate { .. }) => "staking/redelegate",
+        CosmosMsg::Staking(_) => "staking",
+        CosmosMsg::Distribution(DistributionMsg::SetWithdrawAddress { .. }) => {
+            "distribution/set_withdraw_address"
+        }
+        CosmosMsg::Distribution(DistributionMsg::WithdrawDelegatorReward { .. }) => {
+            "distribution/withdraw_delegator_reward"
+        }
+        CosmosMsg::Distribution(_) => "distribution",
+        CosmosMsg::Stargate { type_url, .. } => return type_url.clone(),
+        CosmosMsg::Ibc(IbcMsg::Transfer { .. }) => "ibc/transfer",
+        CosmosMsg::Ibc(IbcMsg::SendPacket { .. }) => "ibc/send_packet",
+        CosmosMsg::Ibc(IbcMsg::CloseChannel { .. }) => "ibc/close_channel",
+        CosmosMsg::Ibc(_) => "ibc",
+        CosmosMsg::Wasm(WasmMsg::Execute { .. }) => "wasm/execute",
+        CosmosMsg::Wasm(WasmMsg::Instantiate { .. }) => "wasm/instantiate",
+        CosmosMsg::Wasm(WasmMsg::Instantiate2 { .. }) => "wasm/instantiate2",
+        CosmosMsg::Wasm(WasmMsg::Migrate { .. }) => "wasm/migrate",
+        CosmosMsg::Wasm(WasmMsg::UpdateAdmin { .. }) => "wasm/update_admin",
+        CosmosMsg::Wasm(WasmMsg::ClearAdmin { .. }) => "wasm/clear_admin",
+        CosmosMsg::Wasm(_) => "wasm",
+        CosmosMsg::Gov(GovMsg::Vote { .. }) => "gov/vote",
+        CosmosMsg::Gov(GovMsg::VoteWeighted { .. }) => "gov/vote_weighted",
+        _ => "unknown",
+    };
+    kind.to_string()
+}
+
+/// Whether the rule is the kind itself or a prefix of it up to a `/` or `.`
+fn covers(rule: &str, kind: &str) -> bool {
+    match kind.strip_prefix(rule) {
+        Some(rest) => rest.is_empty() || rest.starts_with(['/', '.']),
+        None => false,
+    }
+}
+
+/// Fails on the first message one of the rules blocks
+pub fn check_msgs<T>(rules: &[String], msgs: &[CosmosMsg<T>]) -> Result<(), ContractError> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+    for msg in msgs {
+        let kind = msg_kind(msg);
+        if let Some(rule) = rules.iter().find(|rule| covers(rule, &kind)) {
+            return Err(ContractError::MsgBlocked {
+                kind,
+                rule: rule.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// An empty rule would block everything
+pub fn validate_rules(rules: &[String]) -> Result<(), ContractError> {
+    if rules.iter().any(|rule| rule.is_empty()) {
+        return Err(ContractError::InvalidPacket("empty message kind".into()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{coins, Binary, Empty, VoteOption};
+
+    #[test]
+    fn rules_cover_kinds_and_prefixes() {
+        let redelegate: CosmosMsg = StakingMsg::Redelegate {
+            src_validator: "val1".into(),
+            dst_validator: "val2".into(),
+            amount: cosmwasm_std::coin(5, "uatom"),
+        }
+        .into();
+        let vote: CosmosMsg = GovMsg::Vote {
+            proposal_id: 4,
+            vote: VoteOption::Yes,
+        }
+        .into();
+        let stargate: CosmosMsg = CosmosMsg::Stargate {
+            type_url: "/cosmos.authz.v1beta1.MsgExec".into(),
+            value: Binary::default(),
+        };
+        let send: CosmosMsg = BankMsg::Send {
+            to_address: "bob".into(),
+            amount: coins(1, "uatom"),
+        }
+        .into();
+        assert_eq!(msg_kind(&redelegate), "staking/redelegate");
+        assert_eq!(msg_kind(&vote), "gov/vote");
+        assert_eq!(msg_kind(&stargate), "/cosmos.authz.v1beta1.MsgExec");
+        assert_eq!(msg_kind(&CosmosMsg::<Empty>::Custom(Empty {})), "custom");
+
+        let msgs = vec![send.clone(), redelegate, vote, stargate];
+        for rule in [
+            "staking",
+            "staking/redelegate",
+            "gov",
+            "/cosmos.authz",
+            "/cosmos.authz.v1beta1.MsgExec",
+        ] {
+            let err = check_msgs(&[rule.to_string()], &msgs).unwrap_err();
+            assert!(matches!(err, ContractError::MsgBlocked { .. }), "{}", rule);
+        }
+        // only whole parts of a kind match
+        for rule in ["stak", "staking/delegate", "/cosmos.auth", "wasm"] {
+            check_msgs(&[rule.to_string()], &msgs).unwrap();
+        }
+
+        let err = check_msgs(&["bank".to_string()], &[send]).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::MsgBlocked {
+                kind: "bank/send".into(),
+                rule: "bank".into()
+            }
+        );
+        validate_rules(&["".to_string()]).unwrap_err();
+    }
+}
@@ -0,0 +1,255 @@
+//! Limits against controllers flooding the host with accounts or packets.
+//!
+//! All of them are off until the owner sets them. Every new account counts
+//! against its connection and pays the account fee from one of the deposits
+//! for its controller. Every packet counts against the rate limit of its channel, whether
+//! it succeeds or not.
+
+use cosmwasm_std::{Addr, BankMsg, Coin, Order, StdResult, Storage};
+
+use crate::error::ContractError;
+use crate::state::{
+    PacketWindow, RateLimit, SpamLimits, ACCOUNTS, CHANNELS, DEPOSITS, PACKET_WINDOWS, PENDING,
+};
+
+/// Fails if the controller may not get another account right now
+pub fn check_new_account(
+    storage: &dyn Storage,
+    limits: &SpamLimits,
+    controller: (&str, &str),
+) -> Result<(), ContractError> {
+    let connection_id = controller.0;
+    if let Some(max) = limits.max_accounts_per_connection {
+        if connection_accounts(storage, connection_id, max)? >= max {
+            return Err(ContractError::TooManyConnectionAccounts {
+                connection_id: connection_id.to_string(),
+                max,
+            });
+        }
+    }
+    if let Some(fee) = account_fee(limits) {
+        if fee_payer(storage, controller, fee)?.is_none() {
+            return Err(ContractError::InsufficientDeposit(fee.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the controller may get another account and takes the fee out of the
+/// first deposit that covers it. Returns the message paying the treasury, if there is one.
+pub fn pay_for_account(
+    storage: &mut dyn Storage,
+    limits: &SpamLimits,
+    controller: (&str, &str),
+) -> Result<Option<BankMsg>, ContractError> {
+    check_new_account(storage, limits, controller)?;
+    let fee = match account_fee(limits) {
+        Some(fee) => fee,
+        None => return Ok(None),
+    };
+    let (depositor, mut deposit) = fee_payer(storage, controller, fee)?
+        .ok_or_else(|| ContractError::InsufficientDeposit(fee.clone()))?;
+    let key = (controller.0, controller.1, &depositor);
+    for coin in deposit.iter_mut().filter(|c| c.denom == fee.denom) {
+        coin.amount -= fee.amount;
+    }
+    deposit.retain(|c| !c.amount.is_zero());
+    if deposit.is_empty() {
+        DEPOSITS.remove(storage, key);
+    } else {
+        DEPOSITS.save(storage, key, &deposit)?;
+    }
+    Ok(limits.treasury.as_ref().map(|treasury| BankMsg::Send {
+        to_address: treasury.to_string(),
+        amount: vec![fee.clone()],
+    }))
+}
+
+/// The first depositor for the controller whose deposit covers the fee
+fn fee_payer(
+    storage: &dyn Storage,
+    controller: (&str, &str),
+    fee: &Coin,
+) -> StdResult<Option<(Addr, Vec<Coin>)>> {
+    for item in DEPOSITS
+        .prefix(controller)
+        .range(storage, None, None, Order::Ascending)
+    {
+        let (depositor, deposit) = item?;
+        if deposit
+            .iter()
+            .any(|c| c.denom == fee.denom && c.amount >= fee.amount)
+        {
+            return Ok(Some((depositor, deposit)));
+        }
+    }
+    Ok(None)
+}
+
+fn account_fee(limits: &SpamLimits) -> Option<&Coin> {
+    limits
+        .account_fee
+        .as_ref()
+        .filter(|fee| !fee.amount.is_zero())
+}
+
+/// Accounts on the connection, including the ones waiting for their instantiate
+/// reply. Stops counting at `max`.
+fn connection_accounts(storage: &dyn Storage, connection_id: &str, max: u64) -> StdResult<u64> {
+    let mut count = 0;
+    for item in PENDING.range(storage, None, None, Order::Ascending) {
+        let (_, pending) = item?;
+        let channel = CHANNELS.load(storage, &pending.channel_id)?;
+        if channel.connection_id == connection_id {
+            count += 1;
+        }
+    }
+    let created = ACCOUNTS
+        .sub_prefix(connection_id)
+        .keys_raw(storage, None, None, Order::Ascending)
+        .take(max as usize)
+        .count();
+    Ok(count + created as u64)
+}
+
+/// Counts a packet of the channel and fails if the current window already had
+/// too many. A new window starts with the first packet after the last one ended.
+pub fn count_packet(
+    storage: &mut dyn Storage,
+    limit: &RateLimit,
+    channel_id: &str,
+    height: u64,
+) -> Result<(), ContractError> {
+    let mut window = PACKET_WINDOWS
+        .may_load(storage, channel_id)?
+        .unwrap_or_default();
+    if window.packets == 0 || window.start + limit.blocks <= height {
+        window = PacketWindow {
+            start: height,
+            packets: 0,
+        };
+    }
+    window.packets += 1;
+    PACKET_WINDOWS.save(storage, channel_id, &window)?;
+    if window.packets > limit.packets {
+        return Err(ContractError::RateLimited {
+            packets: limit.packets,
+            blocks: limit.blocks,
+        });
+    }
+    Ok(())
+}
+
+/// A rate limit needs a window and must let some packets through
+pub fn validate_rate_limit(limit: &RateLimit) -> Result<(), ContractError> {
+    if limit.blocks == 0 {
+        return Err(ContractError::InvalidRateLimit("no window".into()));
+    }
+    if limit.packets == 0 {
+        return Err(ContractError::InvalidRateLimit("no packets allowed".into()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use cosmwasm_std::{coin, coins, Addr};
+
+    const CONTROLLER: (&str, &str) = ("connection-1", "wasm.controller");
+
+    #[test]
+    fn accounts_per_connection_and_fees() {
+        let mut storage = MockStorage::new();
+        let limits = SpamLimits {
+            max_accounts_per_connection: Some(2),
+            account_fee: Some(coin(100, "uatom")),
+            treasury: Some(Addr::unchecked("treasury")),
+            rate_limit: None,
+        };
+
+        let err = check_new_account(&storage, &limits, CONTROLLER).unwrap_err();
+        assert_eq!(err, ContractError::InsufficientDeposit(coin(100, "uatom")));
+        let (alice, bob) = (Addr::unchecked("alice"), Addr::unchecked("bob"));
+        let alice_key = (CONTROLLER.0, CONTROLLER.1, &alice);
+        let bob_key = (CONTROLLER.0, CONTROLLER.1, &bob);
+        DEPOSITS
+            .save(&mut storage, alice_key, &coins(50, "uatom"))
+            .unwrap();
+        let err = check_new_account(&storage, &limits, CONTROLLER).unwrap_err();
+        assert_eq!(err, ContractError::InsufficientDeposit(coin(100, "uatom")));
+        DEPOSITS
+            .save(
+                &mut storage,
+                bob_key,
+                &vec![coin(150, "uatom"), coin(5, "uosmo")],
+            )
+            .unwrap();
+        let msg = pay_for_account(&mut storage, &limits, CONTROLLER).unwrap();
+        assert_eq!(
+            msg,
+            Some(BankMsg::Send {
+                to_address: "treasury".into(),
+                amount: coins(100, "uatom"),
+            })
+        );
+        // only the deposit that covered the fee paid it
+        let deposit = DEPOSITS.load(&storage, bob_key).unwrap();
+        assert_eq!(deposit, vec![coin(50, "uatom"), coin(5, "uosmo")]);
+        assert_eq!(
+            DEPOSITS.load(&storage, alice_key).unwrap(),
+            coins(50, "uatom")
+        );
+        pay_for_account(&mut storage, &limits, CONTROLLER).unwrap_err();
+
+        // other controllers on the connection share its accounts
+        let address = Addr::unchecked("account");
+        ACCOUNTS
+            .save(&mut storage, ("connection-1", "wasm.a", 0), &address)
+            .unwrap();
+        ACCOUNTS
+            .save(&mut storage, ("connection-1", "wasm.b", 0), &address)
+            .unwrap();
+        let free = SpamLimits {
+            max_accounts_per_connection: Some(2),
+            ..SpamLimits::default()
+        };
+        let err = check_new_account(&storage, &free, CONTROLLER).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::TooManyConnectionAccounts {
+                connection_id: "connection-1".into(),
+                max: 2
+            }
+        );
+        check_new_account(&storage, &free, ("connection-2", "wasm.controller")).unwrap();
+    }
+
+    #[test]
+    fn packets_within_window() {
+        let mut storage = MockStorage::new();
+        let limit = RateLimit {
+            packets: 2,
+            blocks: 10,
+            close_channel: false,
+        };
+        count_packet(&mut storage, &limit, "channel-1", 100).unwrap();
+        count_packet(&mut storage, &limit, "channel-1", 105).unwrap();
+        let err = count_packet(&mut storage, &limit, "channel-1", 109).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::RateLimited {
+                packets: 2,
+                blocks: 10
+            }
+        );
+        // other channels have their own window
+        count_packet(&mut storage, &limit, "channel-2", 109).unwrap();
+        // the next window starts with the next packet
+        count_packet(&mut storage, &limit, "channel-1", 110).unwrap();
+        count_packet(&mut storage, &limit, "channel-1", 119).unwrap();
+
+        validate_rate_limit(&RateLimit { blocks: 0, ..limit }).unwrap_err();
+    }
+}
@@ -1,17 +1,269 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Binary};
+use cosmwasm_std::{Addr, Binary, Coin, Empty, Order, StdError, StdResult, Storage, Timestamp};
 use cw_storage_plus::{Item, Map};
+use simple_ica::{
+    lookup_version, ChannelMetadata, Encoding, MsgOutcome, OutflowLimit, ProtocolVersion,
+    IBC_APP_VERSION,
+};
+
+use crate::account::AccountType;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
+    /// May change the config and transfer ownership
+    pub owner: Addr,
     pub reflect_code_id: u64,
+    /// The kind of contract `reflect_code_id` is
+    pub account_type: AccountType,
+    /// Rejects all new channel handshakes
+    pub channels_paused: bool,
+    /// Answers all incoming packets with an error ack
+    pub packets_paused: bool,
+    /// What happens to the funds of an account when its channel closes
+    pub close_policy: ClosePolicy,
+    /// Caps on the funds every account may send out
+    pub outflow_limits: Vec<OutflowLimit>,
+    /// Kinds of messages no account may dispatch, see `policy::msg_kind`
+    pub blocked_msgs: Vec<String>,
+    /// Limits on new accounts and packets, see `spam`
+    pub spam_limits: SpamLimits,
+}
+
+/// Limits against controllers flooding the host, all off by default
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct SpamLimits {
+    /// Most accounts all controllers on one connection may have together
+    pub max_accounts_per_connection: Option<u64>,
+    /// Taken from the controller's deposit for every new account
+    pub account_fee: Option<Coin>,
+    /// Receives the account fees, they stay in the host if not set
+    pub treasury: Option<Addr>,
+    /// Most packets a channel may send within a window of blocks
+    pub rate_limit: Option<RateLimit>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RateLimit {
+    pub packets: u64,
+    pub blocks: u64,
+    /// Closes the channel on a breach, instead of only answering with an error ack
+    pub close_channel: bool,
+}
+
+/// The packets a channel sent since the block `start`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PacketWindow {
+    pub start: u64,
+    pub packets: u64,
+}
+
+/// What the host does with the funds of an account when its channel closes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ClosePolicy {
+    /// Leave them in the account, the controller gets them back with its next channel
+    Keep,
+    /// Send them over this ICS-20 channel to the controller's return address.
+    /// Without a return address the funds stay in the account.
+    Return { transfer_channel: String },
+    /// Move them into the host, they are paid back into the account once the
+    /// controller opens a new channel
+    Escrow,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ChannelInfo {
+    /// The protocol version negotiated during the handshake
+    pub version: String,
+    /// The wire encoding that comes with this version
+    pub encoding: Encoding,
+    /// Together with `counterparty_port` this identifies the controller
+    pub connection_id: String,
+    /// Port of the controller contract, e.g. `wasm.<controller address>`
+    pub counterparty_port: String,
+    /// The type of accounts created over this channel, set on connect
+    pub account_type: AccountType,
+    /// The metadata the channel was opened with, if it used any
+    pub metadata: Option<ChannelMetadata>,
+}
+
+impl ChannelInfo {
+    /// The prefix of the controller's accounts in `ACCOUNTS`
+    pub fn controller(&self) -> (&str, &str) {
+        (&self.connection_id, &self.counterparty_port)
+    }
+
+    /// The key of one of the controller's accounts in `ACCOUNTS`
+    pub fn account(&self, account_id: u64) -> (&str, &str, u64) {
+        (&self.connection_id, &self.counterparty_port, account_id)
+    }
+
+    /// The code of accounts created over this channel, the metadata pins it
+    pub fn reflect_code_id(&self, cfg: &Config) -> u64 {
+        self.metadata
+            .as_ref()
+            .and_then(|m| m.reflect_code_id)
+            .unwrap_or(cfg.reflect_code_id)
+    }
 }
 
-pub const CONFIG: Item<Config> = Item::new("config");
-pub const PENDING: Item<String> = Item::new("pending");
-pub const ACCOUNTS: Map<&str, Addr> = Map::new("accounts");
+/// An account waiting for its instantiate reply
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingAccount {
+    pub channel_id: String,
+    pub account_id: u64,
+    pub account_type: AccountType,
+}
+
+/// A migration waiting for its reply
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingMigration {
+    pub connection_id: String,
+    pub counterparty_port: String,
+    pub account_id: u64,
+    pub code_id: u64,
+    /// The channel the controller asked on, the reply writes the ack for it.
+    /// `None` for migrations started by the owner.
+    pub channel_id: Option<String>,
+}
+
+impl PendingMigration {
+    /// The key of the migrated account in `ACCOUNTS`
+    pub fn account(&self) -> (&str, &str, u64) {
+        (
+            &self.connection_id,
+            &self.counterparty_port,
+            self.account_id,
+        )
+    }
+}
+
+/// Outcome of the last migration of an account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrationStatus {
+    pub code_id: u64,
+    /// Why it failed, the account then still runs its previous code
+    pub error: Option<String>,
+}
+
+/// Funds an account sent out at `time` (in seconds)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Outflow {
+    pub time: u64,
+    pub amount: Coin,
+}
+
+/// When an account was created and what it did since
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct AccountStats {
+    /// Unknown for accounts created before the host recorded it
+    pub created: Option<Timestamp>,
+    /// Packets the host processed for the account, failed ones are not counted
+    pub packets: u64,
+    /// The dispatches among those packets
+    pub dispatches: u64,
+}
+
+/// Collects the results of the dispatch currently being executed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DispatchResults {
+    /// Encoding of the channel the dispatch came in on, used for the ack
+    pub encoding: Encoding,
+    /// The channel the dispatch came in on, its version decides how errors are acked
+    pub channel_id: String,
+    pub results: Vec<Binary>,
+    /// Only filled by best effort dispatches, one entry per message
+    pub outcomes: Vec<MsgOutcome>,
+    /// The dispatching account
+    pub account_id: u64,
+    /// The outflow of each submessage, released again if it fails
+    pub funds: Vec<Vec<Coin>>,
+}
+
+pub const CONFIG: Item<Config> = Item::new("host_config");
+pub const CHANNELS: Map<&str, ChannelInfo> = Map::new("channels");
+/// Accounts waiting for their instantiate reply, by reply id
+pub const PENDING: Map<u64, PendingAccount> = Map::new("pending_accounts");
+/// Account migrations waiting for their reply, by reply id
+pub const PENDING_MIGRATIONS: Map<u64, PendingMigration> = Map::new("pending_migrations");
+/// Reply id for the next account instantiation or migration
+pub const NEXT_REPLY_ID: Item<u64> = Item::new("next_reply_id");
+/// Reflect accounts by controller (connection id, counterparty port) and account id.
+/// They outlive channels, a new channel from the same controller gets the same accounts.
+pub const ACCOUNTS: Map<(&str, &str, u64), Addr> = Map::new("controller_accounts");
+
+/// Addresses on the controller chain set with `PacketMsg::SetReturnAddress`, by controller
+pub const RETURN_ADDRESSES: Map<(&str, &str), String> = Map::new("return_addresses");
+/// The type of every account, accounts without one are `AccountType::Cw1Whitelist`
+pub const ACCOUNT_TYPES: Map<(&str, &str, u64), AccountType> = Map::new("account_types");
+/// The last migration of every account that was ever migrated
+pub const MIGRATIONS: Map<(&str, &str, u64), MigrationStatus> = Map::new("migrations");
+/// Outflow limits the controllers set for their accounts, on top of `Config::outflow_limits`
+pub const ACCOUNT_LIMITS: Map<(&str, &str, u64), Vec<OutflowLimit>> = Map::new("account_limits");
+/// Kinds of messages an account may not dispatch, on top of `Config::blocked_msgs`
+pub const BLOCKED_MSGS: Map<(&str, &str, u64), Vec<String>> = Map::new("blocked_msgs");
+/// What the accounts sent out within the longest window of their limits
+pub const OUTFLOWS: Map<(&str, &str, u64), Vec<Outflow>> = Map::new("outflows");
+/// Creation time and activity of every account
+pub const ACCOUNT_STATS: Map<(&str, &str, u64), AccountStats> = Map::new("account_stats");
+/// The controller and account id of every account address, for reverse lookups
+pub const ACCOUNT_OWNERS: Map<&Addr, (String, String, u64)> = Map::new("account_owners");
+/// Funds every depositor prepaid for the account fees of a controller
+pub const DEPOSITS: Map<(&str, &str, &Addr), Vec<Coin>> = Map::new("deposits");
+/// The packets of every channel in its current rate limit window
+pub const PACKET_WINDOWS: Map<&str, PacketWindow> = Map::new("packet_windows");
+/// Funds held for accounts whose channel closed under `ClosePolicy::Escrow`
+pub const ESCROW: Map<(&str, &str, u64), Vec<Coin>> = Map::new("escrow");
+
+// connection ids and counterparty ports allowed to open channels.
+// An empty list does not restrict anything.
+pub const ALLOWED_CONNECTIONS: Map<&str, Empty> = Map::new("allowed_connections");
+pub const ALLOWED_PORTS: Map<&str, Empty> = Map::new("allowed_ports");
 
 // this stores all results from current dispatch
-pub const RESULTS: Item<Vec<Binary>> = Item::new("results");
+pub const RESULTS: Item<DispatchResults> = Item::new("results");
+
+/// Returns the account of the controller on the other side of the channel, if any
+pub fn channel_account(
+    storage: &dyn Storage,
+    channel_id: &str,
+    account_id: u64,
+) -> StdResult<Option<Addr>> {
+    match CHANNELS.may_load(storage, channel_id)? {
+        Some(info) => ACCOUNTS.may_load(storage, info.account(account_id)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the open channel of a controller, if it has one
+pub fn controller_channel(
+    storage: &dyn Storage,
+    controller: (&str, &str),
+) -> StdResult<Option<String>> {
+    for item in CHANNELS.range(storage, None, None, Order::Ascending) {
+        let (channel_id, info) = item?;
+        if info.controller() == controller {
+            return Ok(Some(channel_id));
+        }
+    }
+    Ok(None)
+}
+
+/// The address of the controller contract behind a `wasm.<address>` port
+pub fn controller_address(counterparty_port: &str) -> Option<String> {
+    counterparty_port.strip_prefix("wasm.").map(Into::into)
+}
+
+/// Channels without a recorded version speak the original json protocol
+pub fn channel_version(
+    storage: &dyn Storage,
+    channel_id: &str,
+) -> StdResult<&'static ProtocolVersion> {
+    let version = match CHANNELS.may_load(storage, channel_id)? {
+        Some(info) => info.version,
+        None => IBC_APP_VERSION.to_string(),
+    };
+    lookup_version(&version).map_err(|e| StdError::generic_err(e.to_string()))
+}
@@ -3,7 +3,9 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use simple_ica::{BalancesResponse, DispatchResponse, PacketMsg, StdAck, WhoAmIResponse};
+use simple_ica::{
+    BalancesResponse, ChannelMetadata, DispatchResponse, PacketMsg, StdAck, WhoAmIResponse,
+};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -16,4 +18,5 @@ fn main() {
     export_schema(&schema_for!(DispatchResponse), &out_dir);
     export_schema(&schema_for!(BalancesResponse), &out_dir);
     export_schema(&schema_for!(WhoAmIResponse), &out_dir);
+    export_schema(&schema_for!(ChannelMetadata), &out_dir);
 }
@@ -0,0 +1,29 @@
+use cosmwasm_std::Binary;
+use sha2::{Digest, Sha256};
+
+/// Salt the host passes to `Instantiate2` for a controller's account, so the
+/// controller can compute the address before the host reports it.
+///
+/// `connection_id` is the connection on the host chain and `controller_port` the
+/// port of the controller contract, together they identify the controller.
+pub fn account_salt(connection_id: &str, controller_port: &str, account_id: u64) -> Binary {
+    let identity = format!("{}/{}/{}", connection_id, controller_port, account_id);
+    Binary::from(Sha256::digest(identity.as_bytes()).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn salt_depends_on_full_identity() {
+        let salt = account_salt("connection-0", "wasm.controller", 0);
+        // instantiate2 accepts up to 64 bytes
+        assert_eq!(salt.len(), 32);
+        assert_eq!(salt, account_salt("connection-0", "wasm.controller", 0));
+
+        assert_ne!(salt, account_salt("connection-1", "wasm.controller", 0));
+        assert_ne!(salt, account_salt("connection-0", "wasm.other", 0));
+        assert_ne!(salt, account_salt("connection-0", "wasm.controller", 1));
+    }
+}
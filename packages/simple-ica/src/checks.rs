@@ -1,15 +1,23 @@
-pub use crate::{APP_ORDER, IBC_APP_VERSION};
+pub use crate::APP_ORDER;
 use cosmwasm_std::IbcOrder;
 
 use thiserror::Error;
 
+use crate::versions::lookup_version;
+
 #[derive(Error, Debug, PartialEq)]
 pub enum SimpleIcaError {
     #[error("Only supports unordered channels")]
     InvalidChannelOrder,
 
-    #[error("Counterparty version must be '{0}'")]
-    InvalidChannelVersion(&'static str),
+    #[error("Unsupported channel version '{0}'")]
+    UnsupportedVersion(String),
+
+    #[error("No supported version in proposal '{0}'")]
+    NoCommonVersion(String),
+
+    #[error("Invalid channel metadata: {0}")]
+    InvalidMetadata(String),
 }
 
 pub fn check_order(order: &IbcOrder) -> Result<(), SimpleIcaError> {
@@ -20,10 +28,7 @@ pub fn check_order(order: &IbcOrder) -> Result<(), SimpleIcaError> {
     }
 }
 
+/// Ensures the version is one of the `SUPPORTED_VERSIONS`
 pub fn check_version(version: &str) -> Result<(), SimpleIcaError> {
-    if version != IBC_APP_VERSION {
-        Err(SimpleIcaError::InvalidChannelVersion(IBC_APP_VERSION))
-    } else {
-        Ok(())
-    }
+    lookup_version(version).map(|_| ())
 }
@@ -0,0 +1,298 @@
+use cosmwasm_std::{from_slice, to_binary, Binary, StdResult};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::proto::ProtoCodec;
+
+/// How packets, acks and responses are serialized on a channel.
+/// This is fixed per channel during version negotiation.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Json,
+    Proto3,
+}
+
+impl Encoding {
+    pub fn encode<T: Serialize + ProtoCodec>(&self, msg: &T) -> StdResult<Binary> {
+        match self {
+            Encoding::Json => to_binary(msg),
+            Encoding::Proto3 => msg.to_proto_bytes().map(Binary),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned + ProtoCodec>(&self, data: &[u8]) -> StdResult<T> {
+        match self {
+            Encoding::Json => from_slice(data),
+            Encoding::Proto3 => T::from_proto_bytes(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        BalancesResponse, CreateAccountResponse, DispatchMode, DispatchResponse,
+        MigrateAccountResponse, MsgOutcome, OutflowLimit, PacketMsg, RemoteMsg, StdAck,
+        WhoAmIResponse,
+    };
+    use cosmwasm_std::{
+        coin, coins, to_binary, BankMsg, CosmosMsg, Empty, GovMsg, IbcMsg, IbcTimeout,
+        IbcTimeoutBlock, StakingMsg, Timestamp, VoteOption, WasmMsg,
+    };
+    use std::fmt::Debug;
+
+    /// Both encodings must give back exactly what was put in
+    fn assert_round_trip<T>(msg: T)
+    where
+        T: Serialize + DeserializeOwned + ProtoCodec + PartialEq + Debug,
+    {
+        for encoding in [Encoding::Json, Encoding::Proto3] {
+            let bin = encoding.encode(&msg).unwrap();
+            let back: T = encoding.decode(&bin).unwrap();
+            assert_eq!(msg, back, "{:?} round trip", encoding);
+        }
+    }
+
+    fn sample_msgs() -> Vec<CosmosMsg> {
+        vec![
+            BankMsg::Send {
+                to_address: "friend".into(),
+                amount: vec![coin(123456789, "uatom"), coin(1, "ustake")],
+            }
+            .into(),
+            BankMsg::Burn {
+                amount: coins(7, "uosmo"),
+            }
+            .into(),
+            WasmMsg::Execute {
+                contract_addr: "contract".into(),
+                msg: to_binary(&Empty {}).unwrap(),
+                funds: coins(12, "ujuno"),
+            }
+            .into(),
+            WasmMsg::Instantiate {
+                admin: Some("admin".into()),
+                code_id: 17,
+                msg: b"{}".into(),
+                funds: vec![],
+                label: "label".into(),
+            }
+            .into(),
+            WasmMsg::Instantiate {
+                admin: None,
+                code_id: 18,
+                msg: b"{}".into(),
+                funds: vec![],
+                label: String::new(),
+            }
+            .into(),
+            WasmMsg::Migrate {
+                contract_addr: "contract".into(),
+                new_code_id: 3,
+                msg: b"{}".into(),
+            }
+            .into(),
+            StakingMsg::Delegate {
+                validator: "val1".into(),
+                amount: coin(100, "ustake"),
+            }
+            .into(),
+            StakingMsg::Undelegate {
+                validator: "val1".into(),
+                amount: coin(50, "ustake"),
+            }
+            .into(),
+            StakingMsg::Redelegate {
+                src_validator: "val1".into(),
+                dst_validator: "val2".into(),
+                amount: coin(25, "ustake"),
+            }
+            .into(),
+            IbcMsg::Transfer {
+                channel_id: "channel-7".into(),
+                to_address: "remote".into(),
+                amount: coin(99, "uatom"),
+                timeout: IbcTimeout::with_timestamp(Timestamp::from_seconds(1234)),
+            }
+            .into(),
+            IbcMsg::Transfer {
+                channel_id: "channel-7".into(),
+                to_address: "remote".into(),
+                amount: coin(99, "uatom"),
+                timeout: IbcTimeout::with_both(
+                    IbcTimeoutBlock {
+                        revision: 2,
+                        height: 12345,
+                    },
+                    Timestamp::from_nanos(987654321),
+                ),
+            }
+            .into(),
+            // no native proto representation, this one goes as embedded json
+            GovMsg::Vote {
+                proposal_id: 4,
+                vote: VoteOption::Yes,
+            }
+            .into(),
+        ]
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        assert_round_trip(PacketMsg::Dispatch {
+            msgs: sample_msgs(),
+            mode: DispatchMode::Atomic,
+            account_id: 0,
+        });
+        assert_round_trip(PacketMsg::Dispatch {
+            msgs: vec![],
+            mode: DispatchMode::Atomic,
+            account_id: 3,
+        });
+        assert_round_trip(PacketMsg::Dispatch {
+            msgs: sample_msgs(),
+            mode: DispatchMode::BestEffort { gas_limit: None },
+            account_id: 0,
+        });
+        assert_round_trip(PacketMsg::WhoAmI { account_id: 0 });
+        assert_round_trip(PacketMsg::WhoAmI { account_id: 7 });
+        assert_round_trip(PacketMsg::Balances {
+            callback: true,
+            account_id: 0,
+        });
+        assert_round_trip(PacketMsg::Balances {
+            callback: false,
+            account_id: 2,
+        });
+        assert_round_trip(PacketMsg::CreateAccount {
+            label: "strategy-a".into(),
+        });
+        assert_round_trip(PacketMsg::SetReturnAddress {
+            address: Some("osmo1recover".into()),
+        });
+        assert_round_trip(PacketMsg::SetReturnAddress { address: None });
+        assert_round_trip(PacketMsg::MigrateAccount {
+            new_code_id: 12,
+            account_id: 1,
+        });
+        assert_round_trip(PacketMsg::SetOutflowLimits {
+            limits: vec![
+                OutflowLimit {
+                    amount: coin(1000, "uatom"),
+                    window: 3600,
+                },
+                OutflowLimit {
+                    amount: coin(5000, "uatom"),
+                    window: 86400,
+                },
+            ],
+            account_id: 2,
+        });
+        assert_round_trip(PacketMsg::SetOutflowLimits {
+            limits: vec![],
+            account_id: 0,
+        });
+        assert_round_trip(PacketMsg::BlockMsgs {
+            kinds: vec!["wasm/migrate".into(), "/cosmos.gov".into()],
+            account_id: 3,
+        });
+        let mut remote: Vec<RemoteMsg> = sample_msgs().into_iter().map(RemoteMsg::Cosmos).collect();
+        remote.push(RemoteMsg::Stargate {
+            type_url: "/osmosis.gamm.v1beta1.MsgSwapExactAmountIn".into(),
+            value: b"\x0a\x05hello".into(),
+        });
+        remote.push(RemoteMsg::Custom(
+            br#"{"create_denom":{"subdenom":"ica"}}"#.into(),
+        ));
+        assert_round_trip(PacketMsg::DispatchCustom {
+            msgs: remote,
+            mode: DispatchMode::BestEffort {
+                gas_limit: Some(200_000),
+            },
+            account_id: 1,
+        });
+    }
+
+    #[test]
+    fn acks_and_responses_round_trip() {
+        assert_round_trip(StdAck::Result(b"some data".into()));
+        assert_round_trip(StdAck::Result(Binary::default()));
+        assert_round_trip(StdAck::Error("oops".to_string()));
+        assert_round_trip(StdAck::CodedError {
+            code: 3,
+            message: "no account".to_string(),
+        });
+        assert_round_trip(DispatchResponse {
+            results: vec![b"one".into(), Binary::default(), b"three".into()],
+            outcomes: vec![],
+        });
+        assert_round_trip(DispatchResponse {
+            results: vec![],
+            outcomes: vec![
+                MsgOutcome::Success(b"one".into()),
+                MsgOutcome::Error("out of gas".into()),
+                MsgOutcome::Success(Binary::default()),
+            ],
+        });
+        assert_round_trip(WhoAmIResponse {
+            account: "account-789".into(),
+        });
+        assert_round_trip(CreateAccountResponse {
+            account_id: 4,
+            account: "account-790".into(),
+        });
+        assert_round_trip(MigrateAccountResponse {
+            account_id: 1,
+            code_id: 12,
+        });
+        assert_round_trip(BalancesResponse {
+            account: "account-789".into(),
+            balances: vec![coin(123456, "uatom"), coin(7654321, "tgrd")],
+            execute_callback: true,
+        });
+    }
+
+    #[test]
+    fn proto_is_more_compact() {
+        let packet = PacketMsg::Dispatch {
+            msgs: sample_msgs(),
+            mode: DispatchMode::Atomic,
+            account_id: 0,
+        };
+        let json = Encoding::Json.encode(&packet).unwrap();
+        let proto = Encoding::Proto3.encode(&packet).unwrap();
+        assert!(proto.len() < json.len());
+    }
+
+    #[test]
+    fn defaults_keep_v1_json() {
+        let packet = PacketMsg::Dispatch {
+            msgs: vec![],
+            mode: DispatchMode::Atomic,
+            account_id: 0,
+        };
+        let json = Encoding::Json.encode(&packet).unwrap();
+        assert_eq!(json.as_slice(), br#"{"dispatch":{"msgs":[]}}"#);
+        let back: PacketMsg = Encoding::Json.decode(&json).unwrap();
+        assert_eq!(back, packet);
+
+        // so does the default account
+        let packet = PacketMsg::WhoAmI { account_id: 0 };
+        let json = Encoding::Json.encode(&packet).unwrap();
+        assert_eq!(json.as_slice(), br#"{"who_am_i":{}}"#);
+    }
+
+    #[test]
+    fn proto_rejects_invalid_data() {
+        Encoding::Proto3
+            .decode::<PacketMsg>(b"{\"who_am_i\":{}}")
+            .unwrap_err();
+        // an empty message has no variant set
+        Encoding::Proto3.decode::<PacketMsg>(b"").unwrap_err();
+        Encoding::Proto3.decode::<StdAck>(b"").unwrap_err();
+    }
+}
@@ -0,0 +1,161 @@
+use thiserror::Error;
+
+/// Stable numeric codes the host puts in `StdAck::CodedError`.
+/// Never renumber these, controllers match on the raw values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Anything without a more specific code
+    Internal = 1,
+    /// The packet could not be decoded
+    InvalidPacket = 2,
+    /// The host has no account for the channel
+    UnknownChannel = 3,
+    /// The channel version lacks the feature the packet needs
+    FeatureNotSupported = 4,
+    /// A message in the packet cannot be executed on the host chain
+    UnsupportedMsg = 5,
+    /// A query on the host chain failed
+    QueryFailed = 6,
+    /// The host owner paused packet processing
+    Paused = 7,
+    /// The packet breaks a limit of the host, e.g. on outflows, accounts or packet rate
+    LimitExceeded = 8,
+    /// A message the host executed for the account failed
+    ExecutionFailed = 9,
+}
+
+impl ErrorCode {
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        let code = match code {
+            1 => ErrorCode::Internal,
+            2 => ErrorCode::InvalidPacket,
+            3 => ErrorCode::UnknownChannel,
+            4 => ErrorCode::FeatureNotSupported,
+            5 => ErrorCode::UnsupportedMsg,
+            6 => ErrorCode::QueryFailed,
+            7 => ErrorCode::Paused,
+            8 => ErrorCode::LimitExceeded,
+            9 => ErrorCode::ExecutionFailed,
+            _ => return None,
+        };
+        Some(code)
+    }
+}
+
+/// An error ack from the host, decoded by its code
+#[derive(Error, Debug, PartialEq)]
+pub enum RemoteError {
+    #[error("Remote internal error: {0}")]
+    Internal(String),
+
+    #[error("Remote could not decode packet: {0}")]
+    InvalidPacket(String),
+
+    #[error("Remote has no account for channel: {0}")]
+    UnknownChannel(String),
+
+    #[error("Remote does not support feature: {0}")]
+    FeatureNotSupported(String),
+
+    #[error("Remote cannot execute message: {0}")]
+    UnsupportedMsg(String),
+
+    #[error("Remote query failed: {0}")]
+    QueryFailed(String),
+
+    #[error("Remote is paused: {0}")]
+    Paused(String),
+
+    #[error("Remote limit exceeded: {0}")]
+    LimitExceeded(String),
+
+    #[error("Remote execution failed: {0}")]
+    ExecutionFailed(String),
+
+    /// A code added in a newer version of the protocol
+    #[error("Remote error {code}: {message}")]
+    Unknown { code: u32, message: String },
+
+    /// Plain `StdAck::Error` from hosts without structured errors
+    #[error("{0}")]
+    Uncoded(String),
+}
+
+impl RemoteError {
+    pub fn from_code(code: u32, message: String) -> Self {
+        match ErrorCode::from_code(code) {
+            Some(ErrorCode::Internal) => RemoteError::Internal(message),
+            Some(ErrorCode::InvalidPacket) => RemoteError::InvalidPacket(message),
+            Some(ErrorCode::UnknownChannel) => RemoteError::UnknownChannel(message),
+            Some(ErrorCode::FeatureNotSupported) => RemoteError::FeatureNotSupported(message),
+            Some(ErrorCode::UnsupportedMsg) => RemoteError::UnsupportedMsg(message),
+            Some(ErrorCode::QueryFailed) => RemoteError::QueryFailed(message),
+            Some(ErrorCode::Paused) => RemoteError::Paused(message),
+            Some(ErrorCode::LimitExceeded) => RemoteError::LimitExceeded(message),
+            Some(ErrorCode::ExecutionFailed) => RemoteError::ExecutionFailed(message),
+            None => RemoteError::Unknown { code, message },
+        }
+    }
+
+    /// The raw code sent by the host, if any
+    pub fn code(&self) -> Option<u32> {
+        let code = match self {
+            RemoteError::Internal(_) => ErrorCode::Internal,
+            RemoteError::InvalidPacket(_) => ErrorCode::InvalidPacket,
+            RemoteError::UnknownChannel(_) => ErrorCode::UnknownChannel,
+            RemoteError::FeatureNotSupported(_) => ErrorCode::FeatureNotSupported,
+            RemoteError::UnsupportedMsg(_) => ErrorCode::UnsupportedMsg,
+            RemoteError::QueryFailed(_) => ErrorCode::QueryFailed,
+            RemoteError::Paused(_) => ErrorCode::Paused,
+            RemoteError::LimitExceeded(_) => ErrorCode::LimitExceeded,
+            RemoteError::ExecutionFailed(_) => ErrorCode::ExecutionFailed,
+            RemoteError::Unknown { code, .. } => return Some(*code),
+            RemoteError::Uncoded(_) => return None,
+        };
+        Some(code.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lookup_version, StdAck, IBC_APP_VERSION, IBC_APP_VERSION_V3};
+
+    #[test]
+    fn codes_are_stable() {
+        for code in 1..=9 {
+            assert_eq!(ErrorCode::from_code(code).unwrap().code(), code);
+        }
+        assert_eq!(ErrorCode::from_code(0), None);
+        assert_eq!(ErrorCode::UnknownChannel.code(), 3);
+    }
+
+    #[test]
+    fn error_acks_follow_channel_version() {
+        let v1 = lookup_version(IBC_APP_VERSION).unwrap();
+        let ack = StdAck::fail_for(v1, ErrorCode::UnknownChannel, "channel-7".into());
+        let err = v1.encoding.decode::<StdAck>(&ack).unwrap().into_result();
+        assert_eq!(err, Err(RemoteError::Uncoded("channel-7".into())));
+
+        let v3 = lookup_version(IBC_APP_VERSION_V3).unwrap();
+        let ack = StdAck::fail_for(v3, ErrorCode::UnknownChannel, "channel-7".into());
+        let err = v3.encoding.decode::<StdAck>(&ack).unwrap().into_result();
+        assert_eq!(err, Err(RemoteError::UnknownChannel("channel-7".into())));
+        assert_eq!(err.unwrap_err().code(), Some(3));
+
+        // codes from newer hosts are kept as they are
+        let err = RemoteError::from_code(99, "new".into());
+        assert_eq!(
+            err,
+            RemoteError::Unknown {
+                code: 99,
+                message: "new".into()
+            }
+        );
+        assert_eq!(err.code(), Some(99));
+    }
+}
@@ -3,23 +3,166 @@ use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use crate::encoding::Encoding;
+use crate::errors::{ErrorCode, RemoteError};
+use crate::proto::ProtoCodec;
+use crate::versions::{ProtocolVersion, FEATURE_STRUCTURED_ERRORS};
+
+/// The account every channel gets on connect. Any other id requires `FEATURE_SUB_ACCOUNTS`.
+pub const DEFAULT_ACCOUNT_ID: u64 = 0;
+
+/// Keeps the default account off the wire, so v1 hosts see the packets they know
+pub fn is_default_account(account_id: &u64) -> bool {
+    *account_id == DEFAULT_ACCOUNT_ID
+}
+
 /// This is the message we send over the IBC channel
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum PacketMsg {
-    Dispatch { msgs: Vec<CosmosMsg> },
-    WhoAmI {},
-    Balances { callback: bool },
+    Dispatch {
+        msgs: Vec<CosmosMsg>,
+        /// Left out on the wire for atomic dispatches, so v1 hosts see the packets they know
+        #[serde(default, skip_serializing_if = "DispatchMode::is_atomic")]
+        mode: DispatchMode,
+        #[serde(default, skip_serializing_if = "is_default_account")]
+        account_id: u64,
+    },
+    WhoAmI {
+        #[serde(default, skip_serializing_if = "is_default_account")]
+        account_id: u64,
+    },
+    Balances {
+        callback: bool,
+        #[serde(default, skip_serializing_if = "is_default_account")]
+        account_id: u64,
+    },
+    /// Like `Dispatch`, but may also contain chain specific messages.
+    /// Requires `FEATURE_CUSTOM_MSGS`.
+    DispatchCustom {
+        msgs: Vec<RemoteMsg>,
+        #[serde(default, skip_serializing_if = "DispatchMode::is_atomic")]
+        mode: DispatchMode,
+        #[serde(default, skip_serializing_if = "is_default_account")]
+        account_id: u64,
+    },
+    /// Sets (or clears) the address on the controller chain that gets the account's funds
+    /// back when a channel closes and the host returns funds over ICS-20.
+    /// Requires `FEATURE_RETURN_ADDRESS`, the ack carries no data.
+    SetReturnAddress { address: Option<String> },
+    /// Creates another account for the controller on the host, answered with
+    /// `CreateAccountResponse`. Requires `FEATURE_SUB_ACCOUNTS`.
+    CreateAccount { label: String },
+    /// Migrates the account to `new_code_id`, which must be the host's current reflect code.
+    /// Answered with `MigrateAccountResponse`, requires `FEATURE_MIGRATE_ACCOUNT`.
+    MigrateAccount {
+        new_code_id: u64,
+        #[serde(default, skip_serializing_if = "is_default_account")]
+        account_id: u64,
+    },
+    /// Limits what the account may send out, on top of the limits of the host owner.
+    /// Limits set earlier can only be tightened, never loosened or removed.
+    /// Requires `FEATURE_OUTFLOW_LIMITS`, the ack carries no data.
+    SetOutflowLimits {
+        limits: Vec<OutflowLimit>,
+        #[serde(default, skip_serializing_if = "is_default_account")]
+        account_id: u64,
+    },
+    /// Stops the host from dispatching these kinds of messages for the account, e.g.
+    /// `wasm/migrate`, `staking` or a stargate type url. Blocks cannot be lifted
+    /// by the controller. Requires `FEATURE_MSG_POLICIES`, the ack carries no data.
+    BlockMsgs {
+        kinds: Vec<String>,
+        #[serde(default, skip_serializing_if = "is_default_account")]
+        account_id: u64,
+    },
+}
+
+impl PacketMsg {
+    /// The account the packet acts on, `DEFAULT_ACCOUNT_ID` for packets without one
+    pub fn account_id(&self) -> u64 {
+        match self {
+            PacketMsg::Dispatch { account_id, .. }
+            | PacketMsg::WhoAmI { account_id }
+            | PacketMsg::Balances { account_id, .. }
+            | PacketMsg::DispatchCustom { account_id, .. }
+            | PacketMsg::MigrateAccount { account_id, .. }
+            | PacketMsg::SetOutflowLimits { account_id, .. }
+            | PacketMsg::BlockMsgs { account_id, .. } => *account_id,
+            PacketMsg::SetReturnAddress { .. } | PacketMsg::CreateAccount { .. } => {
+                DEFAULT_ACCOUNT_ID
+            }
+        }
+    }
+}
+
+/// At most `amount` may leave an account within any `window` seconds
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct OutflowLimit {
+    pub amount: Coin,
+    pub window: u64,
+}
+
+impl OutflowLimit {
+    /// Whether this limit is at least as strict as `other`
+    pub fn is_within(&self, other: &OutflowLimit) -> bool {
+        self.amount.denom == other.amount.denom
+            && self.amount.amount <= other.amount.amount
+            && self.window >= other.window
+    }
+}
+
+/// How the host executes the messages of one dispatch
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DispatchMode {
+    /// All messages succeed or the whole dispatch is reverted
+    #[default]
+    Atomic,
+    /// Every message runs on its own, failures are reported per message in
+    /// `DispatchResponse::outcomes` and do not revert the others.
+    /// Requires `FEATURE_BEST_EFFORT_DISPATCH`.
+    BestEffort {
+        /// Gas limit applied to each single message
+        gas_limit: Option<u64>,
+    },
+}
+
+impl DispatchMode {
+    pub fn is_atomic(&self) -> bool {
+        matches!(self, DispatchMode::Atomic)
+    }
+}
+
+/// A message to execute on the remote chain, including the ones that
+/// only make sense on that particular chain
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteMsg {
+    /// Any chain independent message
+    Cosmos(CosmosMsg),
+    /// A protobuf encoded sdk message, routed by its type url on the host chain
+    Stargate { type_url: String, value: Binary },
+    /// The raw JSON of a `CosmosMsg::Custom` payload in the format of the host
+    /// chain bindings (e.g. Osmosis token factory). The host decodes it into its
+    /// own custom message type and rejects it if it has none.
+    Custom(Binary),
 }
 
 /// This is a generic ICS acknowledgement format.
 /// Proto defined here: https://github.com/cosmos/cosmos-sdk/blob/v0.42.0/proto/ibc/core/channel/v1/channel.proto#L141-L147
-/// If ibc_receive_packet returns Err(), then x/wasm runtime will rollback the state and return an error message in this format
+/// If ibc_receive_packet returns Err(), then x/wasm runtime will rollback the state and return an error message in this format.
+/// `CodedError` is our own addition for channels that negotiated `FEATURE_STRUCTURED_ERRORS`.
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum StdAck {
     Result(Binary),
     Error(String),
+    /// An error with one of the `ErrorCode`s, requires `FEATURE_STRUCTURED_ERRORS`
+    CodedError {
+        code: u32,
+        message: String,
+    },
 }
 
 impl StdAck {
@@ -34,14 +177,51 @@ impl StdAck {
         StdAck::Error(err).ack()
     }
 
+    // create a serialized success message in the encoding of the channel
+    pub fn success_with<T: Serialize + ProtoCodec>(encoding: Encoding, data: &T) -> Binary {
+        let res = encoding.encode(data).unwrap();
+        StdAck::Result(res).ack_with(encoding)
+    }
+
+    // create a serialized error message in the encoding of the channel
+    pub fn fail_with(encoding: Encoding, err: String) -> Binary {
+        StdAck::Error(err).ack_with(encoding)
+    }
+
+    // create a serialized error message for the channel, with a code if its version allows it
+    pub fn fail_for(version: &ProtocolVersion, code: ErrorCode, err: String) -> Binary {
+        let ack = if version.supports(FEATURE_STRUCTURED_ERRORS) {
+            StdAck::CodedError {
+                code: code.code(),
+                message: err,
+            }
+        } else {
+            StdAck::Error(err)
+        };
+        ack.ack_with(version.encoding)
+    }
+
     pub fn ack(&self) -> Binary {
         to_binary(self).unwrap()
     }
 
-    pub fn unwrap(self) -> Binary {
+    pub fn ack_with(&self, encoding: Encoding) -> Binary {
+        encoding.encode(self).unwrap()
+    }
+
+    /// Splits the ack into the success data or the typed error of the host
+    pub fn into_result(self) -> Result<Binary, RemoteError> {
         match self {
-            StdAck::Result(data) => data,
-            StdAck::Error(err) => panic!("{}", err),
+            StdAck::Result(data) => Ok(data),
+            StdAck::Error(err) => Err(RemoteError::Uncoded(err)),
+            StdAck::CodedError { code, message } => Err(RemoteError::from_code(code, message)),
+        }
+    }
+
+    pub fn unwrap(self) -> Binary {
+        match self.into_result() {
+            Ok(data) => data,
+            Err(err) => panic!("{}", err),
         }
     }
 
@@ -53,6 +233,7 @@ impl StdAck {
         match self {
             StdAck::Result(_) => panic!("not an error"),
             StdAck::Error(err) => err,
+            StdAck::CodedError { message, .. } => message,
         }
     }
 }
@@ -61,6 +242,24 @@ impl StdAck {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct DispatchResponse {
     pub results: Vec<Binary>,
+    /// The outcome of every message by index, only set for `DispatchMode::BestEffort`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outcomes: Vec<MsgOutcome>,
+}
+
+/// Result of a single message of a best effort dispatch
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MsgOutcome {
+    /// The data returned by the message, if any
+    Success(Binary),
+    Error(String),
+}
+
+impl MsgOutcome {
+    pub fn is_success(&self) -> bool {
+        matches!(self, MsgOutcome::Success(_))
+    }
 }
 
 /// This is the success response we send on ack for PacketMsg::WhoAmI.
@@ -70,6 +269,22 @@ pub struct WhoAmIResponse {
     pub account: String,
 }
 
+/// This is the success response we send on ack for PacketMsg::CreateAccount.
+/// Return the id and address of the new account
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CreateAccountResponse {
+    pub account_id: u64,
+    pub account: String,
+}
+
+/// This is the success response we send on ack for PacketMsg::MigrateAccount.
+/// Return the code the account runs now
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateAccountResponse {
+    pub account_id: u64,
+    pub code_id: u64,
+}
+
 /// This is the success response we send on ack for PacketMsg::Balance.
 /// Just acknowledge success or error
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -1,12 +1,42 @@
+mod account;
 mod checks;
+mod encoding;
+mod errors;
 mod ibc_msg;
+mod metadata;
+mod proto;
+mod versions;
 
 use cosmwasm_std::IbcOrder;
 
+pub use crate::account::account_salt;
 pub use crate::checks::{check_order, check_version, SimpleIcaError};
-pub use crate::ibc_msg::{BalancesResponse, DispatchResponse, PacketMsg, StdAck, WhoAmIResponse};
+pub use crate::encoding::Encoding;
+pub use crate::errors::{ErrorCode, RemoteError};
+pub use crate::ibc_msg::{
+    is_default_account, BalancesResponse, CreateAccountResponse, DispatchMode, DispatchResponse,
+    MigrateAccountResponse, MsgOutcome, OutflowLimit, PacketMsg, RemoteMsg, StdAck, WhoAmIResponse,
+    DEFAULT_ACCOUNT_ID,
+};
+pub use crate::metadata::ChannelMetadata;
+pub use crate::proto::ProtoCodec;
+pub use crate::versions::{
+    channel_open_version, connected_metadata, connected_version, lookup_version, negotiate_version,
+    propose_versions, ProtocolVersion, FEATURE_BEST_EFFORT_DISPATCH, FEATURE_CUSTOM_MSGS,
+    FEATURE_MIGRATE_ACCOUNT, FEATURE_MSG_POLICIES, FEATURE_OUTFLOW_LIMITS, FEATURE_RETURN_ADDRESS,
+    FEATURE_STRUCTURED_ERRORS, FEATURE_SUB_ACCOUNTS, SUPPORTED_VERSIONS, V2_FEATURES, V3_FEATURES,
+    VERSION_SEPARATOR,
+};
 
 pub const IBC_APP_VERSION: &str = "simple-ica-v1";
+/// Same protocol as `IBC_APP_VERSION`, but with protobuf instead of json on the wire
+pub const IBC_APP_VERSION_PROTO: &str = "simple-ica-v1-proto";
+/// Adds custom messages (`V2_FEATURES`) on top of v1
+pub const IBC_APP_VERSION_V2: &str = "simple-ica-v2";
+pub const IBC_APP_VERSION_V2_PROTO: &str = "simple-ica-v2-proto";
+/// Adds the optional features listed in `V3_FEATURES` on top of v1
+pub const IBC_APP_VERSION_V3: &str = "simple-ica-v3";
+pub const IBC_APP_VERSION_V3_PROTO: &str = "simple-ica-v3-proto";
 pub const APP_ORDER: IbcOrder = IbcOrder::Unordered;
 // we use this for tests to ensure it is rejected
 pub const BAD_APP_ORDER: IbcOrder = IbcOrder::Ordered;
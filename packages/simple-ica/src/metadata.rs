@@ -0,0 +1,124 @@
+use cosmwasm_std::{from_slice, to_vec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::checks::SimpleIcaError;
+use crate::encoding::Encoding;
+use crate::versions::{lookup_version, ProtocolVersion};
+
+/// A channel version given as json instead of a bare version name, like the ICS-27
+/// metadata. It pins one protocol version and carries the account options the
+/// controller asks for. The host answers with the options it actually applies.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ChannelMetadata {
+    /// One of the `SUPPORTED_VERSIONS`, e.g. `simple-ica-v2`
+    pub version: String,
+    /// Must be the encoding of `version`
+    pub encoding: Encoding,
+    /// Type of the accounts the host creates, e.g. `cw1_whitelist` or `reflect`.
+    /// The host's default if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_type: Option<String>,
+    /// Code id of the accounts. The host rejects any other than its current one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reflect_code_id: Option<u64>,
+    /// Most accounts the controller may have on this channel, the default one included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_accounts: Option<u64>,
+}
+
+impl ChannelMetadata {
+    pub fn new(version: &ProtocolVersion) -> Self {
+        ChannelMetadata {
+            version: version.version.to_string(),
+            encoding: version.encoding,
+            account_type: None,
+            reflect_code_id: None,
+            max_accounts: None,
+        }
+    }
+
+    /// Reads and validates a channel version.
+    /// Returns `None` for a plain version name or a proposal list.
+    pub fn parse(version: &str) -> Result<Option<Self>, SimpleIcaError> {
+        if !version.trim_start().starts_with('{') {
+            return Ok(None);
+        }
+        let metadata: ChannelMetadata = from_slice(version.as_bytes())
+            .map_err(|e| SimpleIcaError::InvalidMetadata(e.to_string()))?;
+        metadata.protocol()?;
+        Ok(Some(metadata))
+    }
+
+    /// The protocol version it pins
+    pub fn protocol(&self) -> Result<&'static ProtocolVersion, SimpleIcaError> {
+        let version = lookup_version(&self.version)?;
+        if version.encoding != self.encoding {
+            return Err(SimpleIcaError::InvalidMetadata(format!(
+                "{} does not use {:?} encoding",
+                self.version, self.encoding
+            )));
+        }
+        if self.max_accounts == Some(0) {
+            return Err(SimpleIcaError::InvalidMetadata(
+                "max_accounts must allow the default account".into(),
+            ));
+        }
+        Ok(version)
+    }
+
+    /// The channel version string for this metadata
+    pub fn to_version(&self) -> Result<String, SimpleIcaError> {
+        to_vec(self)
+            .ok()
+            .and_then(|json| String::from_utf8(json).ok())
+            .ok_or_else(|| SimpleIcaError::InvalidMetadata(format!("{:?}", self)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IBC_APP_VERSION, IBC_APP_VERSION_V2, IBC_APP_VERSION_V2_PROTO};
+
+    #[test]
+    fn parse_metadata() {
+        // plain versions are left to the negotiation
+        assert_eq!(ChannelMetadata::parse(IBC_APP_VERSION).unwrap(), None);
+        assert_eq!(ChannelMetadata::parse("").unwrap(), None);
+
+        let version = r#"{"version":"simple-ica-v2-proto","encoding":"proto3","account_type":"reflect","max_accounts":3}"#;
+        let metadata = ChannelMetadata::parse(version).unwrap().unwrap();
+        assert_eq!(
+            metadata.protocol().unwrap().version,
+            IBC_APP_VERSION_V2_PROTO
+        );
+        assert_eq!(metadata.account_type.as_deref(), Some("reflect"));
+        assert_eq!(metadata.reflect_code_id, None);
+        assert_eq!(metadata.max_accounts, Some(3));
+        // unset options are left out
+        assert_eq!(metadata.to_version().unwrap(), version);
+
+        let v2 = ChannelMetadata::new(lookup_version(IBC_APP_VERSION_V2).unwrap());
+        assert_eq!(
+            v2.to_version().unwrap(),
+            r#"{"version":"simple-ica-v2","encoding":"json"}"#
+        );
+    }
+
+    #[test]
+    fn reject_invalid_metadata() {
+        let invalid = [
+            // not json
+            "{simple-ica-v2}",
+            // unknown version
+            r#"{"version":"simple-ica-v9","encoding":"json"}"#,
+            // not the encoding of the version
+            r#"{"version":"simple-ica-v2","encoding":"proto3"}"#,
+            r#"{"version":"simple-ica-v2","encoding":"json","max_accounts":0}"#,
+        ];
+        for version in invalid {
+            ChannelMetadata::parse(version).unwrap_err();
+        }
+    }
+}
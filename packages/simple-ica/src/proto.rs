@@ -0,0 +1,913 @@
+//! Protobuf wire types for the compact packet encoding.
+//!
+//! These mirror the JSON types in `ibc_msg` one-to-one. The most common `CosmosMsg`
+//! variants get a native representation, everything else is carried as embedded JSON
+//! so the proto encoding never loses information compared to the JSON one.
+//!
+//! The schema for other implementations is `proto/simple_ica.proto`, any change
+//! to the field tags here must go there too.
+
+use cosmwasm_std::{
+    from_slice, to_vec, BankMsg, Binary, Coin, CosmosMsg, IbcMsg, IbcTimeout, IbcTimeoutBlock,
+    StakingMsg, StdError, StdResult, Timestamp, Uint128, WasmMsg,
+};
+use prost::Message;
+
+use crate::ibc_msg::{
+    BalancesResponse, CreateAccountResponse, DispatchMode, DispatchResponse,
+    MigrateAccountResponse, MsgOutcome, OutflowLimit, PacketMsg, RemoteMsg, StdAck, WhoAmIResponse,
+};
+
+/// Conversion between one of our packet types and its protobuf encoding
+pub trait ProtoCodec: Sized {
+    fn to_proto_bytes(&self) -> StdResult<Vec<u8>>;
+    fn from_proto_bytes(data: &[u8]) -> StdResult<Self>;
+}
+
+fn decode<M: Message + Default>(data: &[u8]) -> StdResult<M> {
+    M::decode(data).map_err(|e| StdError::parse_err(std::any::type_name::<M>(), e.to_string()))
+}
+
+fn missing(field: &str) -> StdError {
+    StdError::parse_err("proto", format!("missing field {}", field))
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoPacket {
+    #[prost(oneof = "proto_packet::Msg", tags = "1, 2, 3, 4, 5, 6, 7, 8, 9")]
+    msg: Option<proto_packet::Msg>,
+}
+
+mod proto_packet {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Msg {
+        #[prost(message, tag = "1")]
+        Dispatch(super::ProtoDispatch),
+        #[prost(message, tag = "2")]
+        WhoAmI(super::ProtoWhoAmI),
+        #[prost(message, tag = "3")]
+        Balances(super::ProtoBalances),
+        #[prost(message, tag = "4")]
+        DispatchCustom(super::ProtoDispatchCustom),
+        #[prost(message, tag = "5")]
+        SetReturnAddress(super::ProtoSetReturnAddress),
+        #[prost(message, tag = "6")]
+        CreateAccount(super::ProtoCreateAccount),
+        #[prost(message, tag = "7")]
+        MigrateAccount(super::ProtoMigrateAccount),
+        #[prost(message, tag = "8")]
+        SetOutflowLimits(super::ProtoSetOutflowLimits),
+        #[prost(message, tag = "9")]
+        BlockMsgs(super::ProtoBlockMsgs),
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoDispatch {
+    #[prost(message, repeated, tag = "1")]
+    msgs: Vec<ProtoCosmosMsg>,
+    #[prost(message, optional, tag = "2")]
+    mode: Option<ProtoDispatchMode>,
+    #[prost(uint64, tag = "3")]
+    account_id: u64,
+}
+
+/// Left out for atomic dispatches
+#[derive(Clone, PartialEq, Message)]
+struct ProtoDispatchMode {
+    #[prost(bool, tag = "1")]
+    best_effort: bool,
+    #[prost(uint64, optional, tag = "2")]
+    gas_limit: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoDispatchCustom {
+    #[prost(message, repeated, tag = "1")]
+    msgs: Vec<ProtoRemoteMsg>,
+    #[prost(message, optional, tag = "2")]
+    mode: Option<ProtoDispatchMode>,
+    #[prost(uint64, tag = "3")]
+    account_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoSetReturnAddress {
+    #[prost(string, optional, tag = "1")]
+    address: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoCreateAccount {
+    #[prost(string, tag = "1")]
+    label: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoMigrateAccount {
+    #[prost(uint64, tag = "1")]
+    new_code_id: u64,
+    #[prost(uint64, tag = "2")]
+    account_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoSetOutflowLimits {
+    #[prost(message, repeated, tag = "1")]
+    limits: Vec<ProtoOutflowLimit>,
+    #[prost(uint64, tag = "2")]
+    account_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoOutflowLimit {
+    #[prost(message, optional, tag = "1")]
+    amount: Option<ProtoCoin>,
+    #[prost(uint64, tag = "2")]
+    window: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoBlockMsgs {
+    #[prost(string, repeated, tag = "1")]
+    kinds: Vec<String>,
+    #[prost(uint64, tag = "2")]
+    account_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoRemoteMsg {
+    #[prost(oneof = "proto_remote_msg::Msg", tags = "1, 2, 3")]
+    msg: Option<proto_remote_msg::Msg>,
+}
+
+mod proto_remote_msg {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Msg {
+        #[prost(message, tag = "1")]
+        Cosmos(super::ProtoCosmosMsg),
+        #[prost(message, tag = "2")]
+        Stargate(super::ProtoStargate),
+        #[prost(bytes, tag = "3")]
+        Custom(Vec<u8>),
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoStargate {
+    #[prost(string, tag = "1")]
+    type_url: String,
+    #[prost(bytes, tag = "2")]
+    value: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoWhoAmI {
+    #[prost(uint64, tag = "1")]
+    account_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoBalances {
+    #[prost(bool, tag = "1")]
+    callback: bool,
+    #[prost(uint64, tag = "2")]
+    account_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoCoin {
+    #[prost(string, tag = "1")]
+    denom: String,
+    #[prost(string, tag = "2")]
+    amount: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoCosmosMsg {
+    #[prost(
+        oneof = "proto_cosmos_msg::Msg",
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 15"
+    )]
+    msg: Option<proto_cosmos_msg::Msg>,
+}
+
+mod proto_cosmos_msg {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Msg {
+        #[prost(message, tag = "1")]
+        BankSend(super::ProtoBankSend),
+        #[prost(message, tag = "2")]
+        BankBurn(super::ProtoBankBurn),
+        #[prost(message, tag = "3")]
+        WasmExecute(super::ProtoWasmExecute),
+        #[prost(message, tag = "4")]
+        WasmInstantiate(super::ProtoWasmInstantiate),
+        #[prost(message, tag = "5")]
+        WasmMigrate(super::ProtoWasmMigrate),
+        #[prost(message, tag = "6")]
+        StakingDelegate(super::ProtoDelegate),
+        #[prost(message, tag = "7")]
+        StakingUndelegate(super::ProtoDelegate),
+        #[prost(message, tag = "8")]
+        StakingRedelegate(super::ProtoRedelegate),
+        #[prost(message, tag = "9")]
+        IbcTransfer(super::ProtoIbcTransfer),
+        /// Any message without a native representation, JSON encoded
+        #[prost(bytes, tag = "15")]
+        Json(Vec<u8>),
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoBankSend {
+    #[prost(string, tag = "1")]
+    to_address: String,
+    #[prost(message, repeated, tag = "2")]
+    amount: Vec<ProtoCoin>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoBankBurn {
+    #[prost(message, repeated, tag = "1")]
+    amount: Vec<ProtoCoin>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoWasmExecute {
+    #[prost(string, tag = "1")]
+    contract_addr: String,
+    #[prost(bytes, tag = "2")]
+    msg: Vec<u8>,
+    #[prost(message, repeated, tag = "3")]
+    funds: Vec<ProtoCoin>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoWasmInstantiate {
+    #[prost(string, optional, tag = "1")]
+    admin: Option<String>,
+    #[prost(uint64, tag = "2")]
+    code_id: u64,
+    #[prost(bytes, tag = "3")]
+    msg: Vec<u8>,
+    #[prost(message, repeated, tag = "4")]
+    funds: Vec<ProtoCoin>,
+    #[prost(string, tag = "5")]
+    label: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoWasmMigrate {
+    #[prost(string, tag = "1")]
+    contract_addr: String,
+    #[prost(uint64, tag = "2")]
+    new_code_id: u64,
+    #[prost(bytes, tag = "3")]
+    msg: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoDelegate {
+    #[prost(string, tag = "1")]
+    validator: String,
+    #[prost(message, optional, tag = "2")]
+    amount: Option<ProtoCoin>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoRedelegate {
+    #[prost(string, tag = "1")]
+    src_validator: String,
+    #[prost(string, tag = "2")]
+    dst_validator: String,
+    #[prost(message, optional, tag = "3")]
+    amount: Option<ProtoCoin>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoIbcTransfer {
+    #[prost(string, tag = "1")]
+    channel_id: String,
+    #[prost(string, tag = "2")]
+    to_address: String,
+    #[prost(message, optional, tag = "3")]
+    amount: Option<ProtoCoin>,
+    #[prost(message, optional, tag = "4")]
+    timeout_block: Option<ProtoTimeoutBlock>,
+    #[prost(uint64, optional, tag = "5")]
+    timeout_timestamp: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoTimeoutBlock {
+    #[prost(uint64, tag = "1")]
+    revision: u64,
+    #[prost(uint64, tag = "2")]
+    height: u64,
+}
+
+/// Same layout as `ibc.core.channel.v1.Acknowledgement`
+#[derive(Clone, PartialEq, Message)]
+struct ProtoAck {
+    #[prost(oneof = "proto_ack::Response", tags = "21, 22, 23")]
+    response: Option<proto_ack::Response>,
+}
+
+mod proto_ack {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Response {
+        #[prost(bytes, tag = "21")]
+        Result(Vec<u8>),
+        #[prost(string, tag = "22")]
+        Error(String),
+        /// Not part of the ibc-go ack, only sent on channels with structured errors
+        #[prost(message, tag = "23")]
+        CodedError(super::ProtoCodedError),
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoCodedError {
+    #[prost(uint32, tag = "1")]
+    code: u32,
+    #[prost(string, tag = "2")]
+    message: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoDispatchResponse {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    results: Vec<Vec<u8>>,
+    #[prost(message, repeated, tag = "2")]
+    outcomes: Vec<ProtoMsgOutcome>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoMsgOutcome {
+    #[prost(oneof = "proto_msg_outcome::Outcome", tags = "1, 2")]
+    outcome: Option<proto_msg_outcome::Outcome>,
+}
+
+mod proto_msg_outcome {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Outcome {
+        #[prost(bytes, tag = "1")]
+        Success(Vec<u8>),
+        #[prost(string, tag = "2")]
+        Error(String),
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoWhoAmIResponse {
+    #[prost(string, tag = "1")]
+    account: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoCreateAccountResponse {
+    #[prost(uint64, tag = "1")]
+    account_id: u64,
+    #[prost(string, tag = "2")]
+    account: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoMigrateAccountResponse {
+    #[prost(uint64, tag = "1")]
+    account_id: u64,
+    #[prost(uint64, tag = "2")]
+    code_id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ProtoBalancesResponse {
+    #[prost(string, tag = "1")]
+    account: String,
+    #[prost(message, repeated, tag = "2")]
+    balances: Vec<ProtoCoin>,
+    #[prost(bool, tag = "3")]
+    execute_callback: bool,
+}
+
+fn coin_to_proto(coin: &Coin) -> ProtoCoin {
+    ProtoCoin {
+        denom: coin.denom.clone(),
+        amount: coin.amount.to_string(),
+    }
+}
+
+fn coin_from_proto(coin: ProtoCoin) -> StdResult<Coin> {
+    let amount = coin
+        .amount
+        .parse::<u128>()
+        .map_err(|e| StdError::parse_err("Coin", e.to_string()))?;
+    Ok(Coin {
+        denom: coin.denom,
+        amount: Uint128::new(amount),
+    })
+}
+
+fn coins_to_proto(coins: &[Coin]) -> Vec<ProtoCoin> {
+    coins.iter().map(coin_to_proto).collect()
+}
+
+fn coins_from_proto(coins: Vec<ProtoCoin>) -> StdResult<Vec<Coin>> {
+    coins.into_iter().map(coin_from_proto).collect()
+}
+
+fn required_coin(coin: Option<ProtoCoin>) -> StdResult<Coin> {
+    coin_from_proto(coin.ok_or_else(|| missing("amount"))?)
+}
+
+fn timeout_from_proto(
+    block: Option<ProtoTimeoutBlock>,
+    timestamp: Option<u64>,
+) -> StdResult<IbcTimeout> {
+    let block = block.map(|b| IbcTimeoutBlock {
+        revision: b.revision,
+        height: b.height,
+    });
+    let timestamp = timestamp.map(Timestamp::from_nanos);
+    match (block, timestamp) {
+        (Some(block), Some(timestamp)) => Ok(IbcTimeout::with_both(block, timestamp)),
+        (Some(block), None) => Ok(IbcTimeout::with_block(block)),
+        (None, Some(timestamp)) => Ok(IbcTimeout::with_timestamp(timestamp)),
+        (None, None) => Err(missing("timeout")),
+    }
+}
+
+fn cosmos_msg_to_proto(msg: &CosmosMsg) -> StdResult<ProtoCosmosMsg> {
+    use proto_cosmos_msg::Msg;
+
+    let msg = match msg {
+        CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => Msg::BankSend(ProtoBankSend {
+            to_address: to_address.clone(),
+            amount: coins_to_proto(amount),
+        }),
+        CosmosMsg::Bank(BankMsg::Burn { amount }) => Msg::BankBurn(ProtoBankBurn {
+            amount: coins_to_proto(amount),
+        }),
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr,
+            msg,
+            funds,
+        }) => Msg::WasmExecute(ProtoWasmExecute {
+            contract_addr: contract_addr.clone(),
+            msg: msg.to_vec(),
+            funds: coins_to_proto(funds),
+        }),
+        CosmosMsg::Wasm(WasmMsg::Instantiate {
+            admin,
+            code_id,
+            msg,
+            funds,
+            label,
+        }) => Msg::WasmInstantiate(ProtoWasmInstantiate {
+            admin: admin.clone(),
+            code_id: *code_id,
+            msg: msg.to_vec(),
+            funds: coins_to_proto(funds),
+            label: label.clone(),
+        }),
+        CosmosMsg::Wasm(WasmMsg::Migrate {
+            contract_addr,
+            new_code_id,
+            msg,
+        }) => Msg::WasmMigrate(ProtoWasmMigrate {
+            contract_addr: contract_addr.clone(),
+            new_code_id: *new_code_id,
+            msg: msg.to_vec(),
+        }),
+        CosmosMsg::Staking(StakingMsg::Delegate { validator, amount }) => {
+            Msg::StakingDelegate(ProtoDelegate {
+                validator: validator.clone(),
+                amount: Some(coin_to_proto(amount)),
+            })
+        }
+        CosmosMsg::Staking(StakingMsg::Undelegate { validator, amount }) => {
+            Msg::StakingUndelegate(ProtoDelegate {
+                validator: validator.clone(),
+                amount: Some(coin_to_proto(amount)),
+            })
+        }
+        CosmosMsg::Staking(StakingMsg::Redelegate {
+            src_validator,
+            dst_validator,
+            amount,
+        }) => Msg::StakingRedelegate(ProtoRedelegate {
+            src_validator: src_validator.clone(),
+            dst_validator: dst_validator.clone(),
+            amount: Some(coin_to_proto(amount)),
+        }),
+        CosmosMsg::Ibc(IbcMsg::Transfer {
+            channel_id,
+            to_address,
+            amount,
+            timeout,
+        }) => Msg::IbcTransfer(ProtoIbcTransfer {
+            channel_id: channel_id.clone(),
+            to_address: to_address.clone(),
+            amount: Some(coin_to_proto(amount)),
+            timeout_block: timeout.block().map(|b| ProtoTimeoutBlock {
+                revision: b.revision,
+                height: b.height,
+            }),
+            timeout_timestamp: timeout.timestamp().map(|t| t.nanos()),
+        }),
+        other => Msg::Json(to_vec(other)?),
+    };
+    Ok(ProtoCosmosMsg { msg: Some(msg) })
+}
+
+fn cosmos_msg_from_proto(msg: ProtoCosmosMsg) -> StdResult<CosmosMsg> {
+    use proto_cosmos_msg::Msg;
+
+    let msg = match msg.msg.ok_or_else(|| missing("msg"))? {
+        Msg::BankSend(m) => BankMsg::Send {
+            to_address: m.to_address,
+            amount: coins_from_proto(m.amount)?,
+        }
+        .into(),
+        Msg::BankBurn(m) => BankMsg::Burn {
+            amount: coins_from_proto(m.amount)?,
+        }
+        .into(),
+        Msg::WasmExecute(m) => WasmMsg::Execute {
+            contract_addr: m.contract_addr,
+            msg: Binary(m.msg),
+            funds: coins_from_proto(m.funds)?,
+        }
+        .into(),
+        Msg::WasmInstantiate(m) => WasmMsg::Instantiate {
+            admin: m.admin,
+            code_id: m.code_id,
+            msg: Binary(m.msg),
+            funds: coins_from_proto(m.funds)?,
+            label: m.label,
+        }
+        .into(),
+        Msg::WasmMigrate(m) => WasmMsg::Migrate {
+            contract_addr: m.contract_addr,
+            new_code_id: m.new_code_id,
+            msg: Binary(m.msg),
+        }
+        .into(),
+        Msg::StakingDelegate(m) => StakingMsg::Delegate {
+            validator: m.validator,
+            amount: required_coin(m.amount)?,
+        }
+        .into(),
+        Msg::StakingUndelegate(m) => StakingMsg::Undelegate {
+            validator: m.validator,
+            amount: required_coin(m.amount)?,
+        }
+        .into(),
+        Msg::StakingRedelegate(m) => StakingMsg::Redelegate {
+            src_validator: m.src_validator,
+            dst_validator: m.dst_validator,
+            amount: required_coin(m.amount)?,
+        }
+        .into(),
+        Msg::IbcTransfer(m) => IbcMsg::Transfer {
+            channel_id: m.channel_id,
+            to_address: m.to_address,
+            amount: required_coin(m.amount)?,
+            timeout: timeout_from_proto(m.timeout_block, m.timeout_timestamp)?,
+        }
+        .into(),
+        Msg::Json(raw) => from_slice(&raw)?,
+    };
+    Ok(msg)
+}
+
+fn remote_msg_to_proto(msg: &RemoteMsg) -> StdResult<ProtoRemoteMsg> {
+    use proto_remote_msg::Msg;
+
+    let msg = match msg {
+        RemoteMsg::Cosmos(msg) => Msg::Cosmos(cosmos_msg_to_proto(msg)?),
+        RemoteMsg::Stargate { type_url, value } => Msg::Stargate(ProtoStargate {
+            type_url: type_url.clone(),
+            value: value.to_vec(),
+        }),
+        RemoteMsg::Custom(raw) => Msg::Custom(raw.to_vec()),
+    };
+    Ok(ProtoRemoteMsg { msg: Some(msg) })
+}
+
+fn remote_msg_from_proto(msg: ProtoRemoteMsg) -> StdResult<RemoteMsg> {
+    use proto_remote_msg::Msg;
+
+    let msg = match msg.msg.ok_or_else(|| missing("msg"))? {
+        Msg::Cosmos(msg) => RemoteMsg::Cosmos(cosmos_msg_from_proto(msg)?),
+        Msg::Stargate(m) => RemoteMsg::Stargate {
+            type_url: m.type_url,
+            value: Binary(m.value),
+        },
+        Msg::Custom(raw) => RemoteMsg::Custom(Binary(raw)),
+    };
+    Ok(msg)
+}
+
+fn mode_to_proto(mode: &DispatchMode) -> Option<ProtoDispatchMode> {
+    match mode {
+        DispatchMode::Atomic => None,
+        DispatchMode::BestEffort { gas_limit } => Some(ProtoDispatchMode {
+            best_effort: true,
+            gas_limit: *gas_limit,
+        }),
+    }
+}
+
+fn mode_from_proto(mode: Option<ProtoDispatchMode>) -> DispatchMode {
+    match mode {
+        Some(ProtoDispatchMode {
+            best_effort: true,
+            gas_limit,
+        }) => DispatchMode::BestEffort { gas_limit },
+        _ => DispatchMode::Atomic,
+    }
+}
+
+fn outcome_to_proto(outcome: &MsgOutcome) -> ProtoMsgOutcome {
+    use proto_msg_outcome::Outcome;
+
+    let outcome = match outcome {
+        MsgOutcome::Success(data) => Outcome::Success(data.to_vec()),
+        MsgOutcome::Error(err) => Outcome::Error(err.clone()),
+    };
+    ProtoMsgOutcome {
+        outcome: Some(outcome),
+    }
+}
+
+fn outcome_from_proto(outcome: ProtoMsgOutcome) -> StdResult<MsgOutcome> {
+    use proto_msg_outcome::Outcome;
+
+    let outcome = match outcome.outcome.ok_or_else(|| missing("outcome"))? {
+        Outcome::Success(data) => MsgOutcome::Success(Binary(data)),
+        Outcome::Error(err) => MsgOutcome::Error(err),
+    };
+    Ok(outcome)
+}
+
+impl ProtoCodec for PacketMsg {
+    fn to_proto_bytes(&self) -> StdResult<Vec<u8>> {
+        use proto_packet::Msg;
+
+        let msg = match self {
+            PacketMsg::Dispatch {
+                msgs,
+                mode,
+                account_id,
+            } => Msg::Dispatch(ProtoDispatch {
+                msgs: msgs
+                    .iter()
+                    .map(cosmos_msg_to_proto)
+                    .collect::<StdResult<_>>()?,
+                mode: mode_to_proto(mode),
+                account_id: *account_id,
+            }),
+            PacketMsg::WhoAmI { account_id } => Msg::WhoAmI(ProtoWhoAmI {
+                account_id: *account_id,
+            }),
+            PacketMsg::Balances {
+                callback,
+                account_id,
+            } => Msg::Balances(ProtoBalances {
+                callback: *callback,
+                account_id: *account_id,
+            }),
+            PacketMsg::DispatchCustom {
+                msgs,
+                mode,
+                account_id,
+            } => Msg::DispatchCustom(ProtoDispatchCustom {
+                msgs: msgs
+                    .iter()
+                    .map(remote_msg_to_proto)
+                    .collect::<StdResult<_>>()?,
+                mode: mode_to_proto(mode),
+                account_id: *account_id,
+            }),
+            PacketMsg::SetReturnAddress { address } => {
+                Msg::SetReturnAddress(ProtoSetReturnAddress {
+                    address: address.clone(),
+                })
+            }
+            PacketMsg::CreateAccount { label } => Msg::CreateAccount(ProtoCreateAccount {
+                label: label.clone(),
+            }),
+            PacketMsg::MigrateAccount {
+                new_code_id,
+                account_id,
+            } => Msg::MigrateAccount(ProtoMigrateAccount {
+                new_code_id: *new_code_id,
+                account_id: *account_id,
+            }),
+            PacketMsg::SetOutflowLimits { limits, account_id } => {
+                Msg::SetOutflowLimits(ProtoSetOutflowLimits {
+                    limits: limits
+                        .iter()
+                        .map(|l| ProtoOutflowLimit {
+                            amount: Some(coin_to_proto(&l.amount)),
+                            window: l.window,
+                        })
+                        .collect(),
+                    account_id: *account_id,
+                })
+            }
+            PacketMsg::BlockMsgs { kinds, account_id } => Msg::BlockMsgs(ProtoBlockMsgs {
+                kinds: kinds.clone(),
+                account_id: *account_id,
+            }),
+        };
+        Ok(ProtoPacket { msg: Some(msg) }.encode_to_vec())
+    }
+
+    fn from_proto_bytes(data: &[u8]) -> StdResult<Self> {
+        use proto_packet::Msg;
+
+        let packet: ProtoPacket = decode(data)?;
+        let msg = match packet.msg.ok_or_else(|| missing("msg"))? {
+            Msg::Dispatch(d) => PacketMsg::Dispatch {
+                msgs: d
+                    .msgs
+                    .into_iter()
+                    .map(cosmos_msg_from_proto)
+                    .collect::<StdResult<_>>()?,
+                mode: mode_from_proto(d.mode),
+                account_id: d.account_id,
+            },
+            Msg::WhoAmI(w) => PacketMsg::WhoAmI {
+                account_id: w.account_id,
+            },
+            Msg::Balances(b) => PacketMsg::Balances {
+                callback: b.callback,
+                account_id: b.account_id,
+            },
+            Msg::DispatchCustom(d) => PacketMsg::DispatchCustom {
+                msgs: d
+                    .msgs
+                    .into_iter()
+                    .map(remote_msg_from_proto)
+                    .collect::<StdResult<_>>()?,
+                mode: mode_from_proto(d.mode),
+                account_id: d.account_id,
+            },
+            Msg::SetReturnAddress(r) => PacketMsg::SetReturnAddress { address: r.address },
+            Msg::CreateAccount(c) => PacketMsg::CreateAccount { label: c.label },
+            Msg::MigrateAccount(m) => PacketMsg::MigrateAccount {
+                new_code_id: m.new_code_id,
+                account_id: m.account_id,
+            },
+            Msg::SetOutflowLimits(s) => PacketMsg::SetOutflowLimits {
+                limits: s
+                    .limits
+                    .into_iter()
+                    .map(|l| {
+                        Ok(OutflowLimit {
+                            amount: required_coin(l.amount)?,
+                            window: l.window,
+                        })
+                    })
+                    .collect::<StdResult<_>>()?,
+                account_id: s.account_id,
+            },
+            Msg::BlockMsgs(b) => PacketMsg::BlockMsgs {
+                kinds: b.kinds,
+                account_id: b.account_id,
+            },
+        };
+        Ok(msg)
+    }
+}
+
+impl ProtoCodec for StdAck {
+    fn to_proto_bytes(&self) -> StdResult<Vec<u8>> {
+        use proto_ack::Response;
+
+        let response = match self {
+            StdAck::Result(data) => Response::Result(data.to_vec()),
+            StdAck::Error(err) => Response::Error(err.clone()),
+            StdAck::CodedError { code, message } => Response::CodedError(ProtoCodedError {
+                code: *code,
+                message: message.clone(),
+            }),
+        };
+        Ok(ProtoAck {
+            response: Some(response),
+        }
+        .encode_to_vec())
+    }
+
+    fn from_proto_bytes(data: &[u8]) -> StdResult<Self> {
+        use proto_ack::Response;
+
+        let ack: ProtoAck = decode(data)?;
+        let ack = match ack.response.ok_or_else(|| missing("response"))? {
+            Response::Result(data) => StdAck::Result(Binary(data)),
+            Response::Error(err) => StdAck::Error(err),
+            Response::CodedError(err) => StdAck::CodedError {
+                code: err.code,
+                message: err.message,
+            },
+        };
+        Ok(ack)
+    }
+}
+
+impl ProtoCodec for DispatchResponse {
+    fn to_proto_bytes(&self) -> StdResult<Vec<u8>> {
+        Ok(ProtoDispatchResponse {
+            results: self.results.iter().map(|r| r.to_vec()).collect(),
+            outcomes: self.outcomes.iter().map(outcome_to_proto).collect(),
+        }
+        .encode_to_vec())
+    }
+
+    fn from_proto_bytes(data: &[u8]) -> StdResult<Self> {
+        let res: ProtoDispatchResponse = decode(data)?;
+        Ok(DispatchResponse {
+            results: res.results.into_iter().map(Binary).collect(),
+            outcomes: res
+                .outcomes
+                .into_iter()
+                .map(outcome_from_proto)
+                .collect::<StdResult<_>>()?,
+        })
+    }
+}
+
+impl ProtoCodec for WhoAmIResponse {
+    fn to_proto_bytes(&self) -> StdResult<Vec<u8>> {
+        Ok(ProtoWhoAmIResponse {
+            account: self.account.clone(),
+        }
+        .encode_to_vec())
+    }
+
+    fn from_proto_bytes(data: &[u8]) -> StdResult<Self> {
+        let res: ProtoWhoAmIResponse = decode(data)?;
+        Ok(WhoAmIResponse {
+            account: res.account,
+        })
+    }
+}
+
+impl ProtoCodec for CreateAccountResponse {
+    fn to_proto_bytes(&self) -> StdResult<Vec<u8>> {
+        Ok(ProtoCreateAccountResponse {
+            account_id: self.account_id,
+            account: self.account.clone(),
+        }
+        .encode_to_vec())
+    }
+
+    fn from_proto_bytes(data: &[u8]) -> StdResult<Self> {
+        let res: ProtoCreateAccountResponse = decode(data)?;
+        Ok(CreateAccountResponse {
+            account_id: res.account_id,
+            account: res.account,
+        })
+    }
+}
+
+impl ProtoCodec for MigrateAccountResponse {
+    fn to_proto_bytes(&self) -> StdResult<Vec<u8>> {
+        Ok(ProtoMigrateAccountResponse {
+            account_id: self.account_id,
+            code_id: self.code_id,
+        }
+        .encode_to_vec())
+    }
+
+    fn from_proto_bytes(data: &[u8]) -> StdResult<Self> {
+        let res: ProtoMigrateAccountResponse = decode(data)?;
+        Ok(MigrateAccountResponse {
+            account_id: res.account_id,
+            code_id: res.code_id,
+        })
+    }
+}
+
+impl ProtoCodec for BalancesResponse {
+    fn to_proto_bytes(&self) -> StdResult<Vec<u8>> {
+        Ok(ProtoBalancesResponse {
+            account: self.account.clone(),
+            balances: coins_to_proto(&self.balances),
+            execute_callback: self.execute_callback,
+        }
+        .encode_to_vec())
+    }
+
+    fn from_proto_bytes(data: &[u8]) -> StdResult<Self> {
+        let res: ProtoBalancesResponse = decode(data)?;
+        Ok(BalancesResponse {
+            account: res.account,
+            balances: coins_from_proto(res.balances)?,
+            execute_callback: res.execute_callback,
+        })
+    }
+}
@@ -0,0 +1,323 @@
+use cosmwasm_std::{IbcChannelConnectMsg, IbcChannelOpenMsg};
+
+use crate::checks::SimpleIcaError;
+use crate::encoding::Encoding;
+use crate::metadata::ChannelMetadata;
+
+/// Separates the entries of a version proposal sent in `ChanOpenInit`,
+/// e.g. `simple-ica-v2,simple-ica-v1`
+pub const VERSION_SEPARATOR: char = ',';
+
+/// One version of the packet protocol, along with its wire encoding and the optional
+/// features both sides may rely on once it has been negotiated for a channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProtocolVersion {
+    pub version: &'static str,
+    pub encoding: Encoding,
+    pub features: &'static [&'static str],
+}
+
+impl ProtocolVersion {
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+/// `PacketMsg::DispatchCustom` with Stargate and chain specific messages
+pub const FEATURE_CUSTOM_MSGS: &str = "custom-msgs";
+
+/// `DispatchMode::BestEffort` with per message outcomes in the ack
+pub const FEATURE_BEST_EFFORT_DISPATCH: &str = "best-effort-dispatch";
+
+/// Receive errors are acked with `StdAck::CodedError` instead of a plain string
+pub const FEATURE_STRUCTURED_ERRORS: &str = "structured-errors";
+
+/// `PacketMsg::SetReturnAddress` for funds left behind when a channel closes
+pub const FEATURE_RETURN_ADDRESS: &str = "return-address";
+
+/// `PacketMsg::CreateAccount` and packets for accounts other than `DEFAULT_ACCOUNT_ID`
+pub const FEATURE_SUB_ACCOUNTS: &str = "sub-accounts";
+
+/// `PacketMsg::MigrateAccount` to move an account to the host's current reflect code
+pub const FEATURE_MIGRATE_ACCOUNT: &str = "migrate-account";
+
+/// `PacketMsg::SetOutflowLimits` and `ErrorCode::LimitExceeded`
+pub const FEATURE_OUTFLOW_LIMITS: &str = "outflow-limits";
+
+/// `PacketMsg::BlockMsgs` to stop the host from dispatching some kinds of messages
+pub const FEATURE_MSG_POLICIES: &str = "msg-policies";
+
+/// Everything `simple-ica-v2` supports, as it was introduced. Frozen, peers on v2
+/// rely on exactly this.
+pub const V2_FEATURES: &[&str] = &[FEATURE_CUSTOM_MSGS];
+
+/// Everything `simple-ica-v3` supports. Frozen as well, a new feature needs a new
+/// version.
+pub const V3_FEATURES: &[&str] = &[
+    FEATURE_CUSTOM_MSGS,
+    FEATURE_BEST_EFFORT_DISPATCH,
+    FEATURE_STRUCTURED_ERRORS,
+    FEATURE_RETURN_ADDRESS,
+    FEATURE_SUB_ACCOUNTS,
+    FEATURE_MIGRATE_ACCOUNT,
+    FEATURE_OUTFLOW_LIMITS,
+    FEATURE_MSG_POLICIES,
+];
+
+/// All versions we speak, ordered from most to least preferred.
+/// New `PacketMsg` variants must be gated behind a feature of a newer version,
+/// so channels that negotiated an older one keep working unchanged.
+/// We prefer the compact protobuf encoding whenever the counterparty offers it.
+pub const SUPPORTED_VERSIONS: &[ProtocolVersion] = &[
+    ProtocolVersion {
+        version: crate::IBC_APP_VERSION_V3_PROTO,
+        encoding: Encoding::Proto3,
+        features: V3_FEATURES,
+    },
+    ProtocolVersion {
+        version: crate::IBC_APP_VERSION_V3,
+        encoding: Encoding::Json,
+        features: V3_FEATURES,
+    },
+    ProtocolVersion {
+        version: crate::IBC_APP_VERSION_V2_PROTO,
+        encoding: Encoding::Proto3,
+        features: V2_FEATURES,
+    },
+    ProtocolVersion {
+        version: crate::IBC_APP_VERSION_V2,
+        encoding: Encoding::Json,
+        features: V2_FEATURES,
+    },
+    ProtocolVersion {
+        version: crate::IBC_APP_VERSION_PROTO,
+        encoding: Encoding::Proto3,
+        features: &[],
+    },
+    ProtocolVersion {
+        version: crate::IBC_APP_VERSION,
+        encoding: Encoding::Json,
+        features: &[],
+    },
+];
+
+/// Returns the supported version with the given name
+pub fn lookup_version(version: &str) -> Result<&'static ProtocolVersion, SimpleIcaError> {
+    SUPPORTED_VERSIONS
+        .iter()
+        .find(|v| v.version == version)
+        .ok_or_else(|| SimpleIcaError::UnsupportedVersion(version.to_string()))
+}
+
+/// Builds the proposal we send in `ChanOpenInit`. An empty version means the relayer
+/// left the choice to us, so we offer everything we support. Otherwise we only keep
+/// the entries we understand, in our order of preference.
+pub fn propose_versions(requested: &str) -> Result<String, SimpleIcaError> {
+    let requested: Vec<&str> = split_versions(requested).collect();
+    let proposal: Vec<&str> = SUPPORTED_VERSIONS
+        .iter()
+        .map(|v| v.version)
+        .filter(|v| requested.is_empty() || requested.contains(v))
+        .collect();
+    if proposal.is_empty() {
+        return Err(SimpleIcaError::NoCommonVersion(requested.join(",")));
+    }
+    Ok(proposal.join(&VERSION_SEPARATOR.to_string()))
+}
+
+/// Picks the highest version that we support out of the counterparty's proposal.
+pub fn negotiate_version(proposal: &str) -> Result<&'static ProtocolVersion, SimpleIcaError> {
+    negotiate_from(SUPPORTED_VERSIONS, proposal)
+}
+
+fn negotiate_from<'a>(
+    supported: &'a [ProtocolVersion],
+    proposal: &str,
+) -> Result<&'a ProtocolVersion, SimpleIcaError> {
+    let offered: Vec<&str> = split_versions(proposal).collect();
+    supported
+        .iter()
+        .find(|v| offered.contains(&v.version))
+        .ok_or_else(|| SimpleIcaError::NoCommonVersion(proposal.to_string()))
+}
+
+fn split_versions(versions: &str) -> impl Iterator<Item = &str> {
+    versions
+        .split(VERSION_SEPARATOR)
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+}
+
+/// Returns the version string we answer in `ibc_channel_open`.
+/// On `OpenInit` this is our proposal, on `OpenTry` the single version we picked.
+/// Channel metadata already pins a version, so it is only validated and echoed.
+pub fn channel_open_version(msg: &IbcChannelOpenMsg) -> Result<String, SimpleIcaError> {
+    let requested = msg.counterparty_version().unwrap_or(&msg.channel().version);
+    if let Some(metadata) = ChannelMetadata::parse(requested)? {
+        return metadata.to_version();
+    }
+    match msg.counterparty_version() {
+        Some(proposal) => negotiate_version(proposal).map(|v| v.version.to_string()),
+        None => propose_versions(&msg.channel().version),
+    }
+}
+
+/// Returns the version both sides agreed on once the channel is connected.
+/// This must be exactly one version that we support.
+pub fn connected_version(
+    msg: &IbcChannelConnectMsg,
+) -> Result<&'static ProtocolVersion, SimpleIcaError> {
+    match ChannelMetadata::parse(final_version(msg))? {
+        Some(metadata) => metadata.protocol(),
+        None => lookup_version(final_version(msg)),
+    }
+}
+
+/// Returns the metadata of a connected channel, if it was opened with any
+pub fn connected_metadata(
+    msg: &IbcChannelConnectMsg,
+) -> Result<Option<ChannelMetadata>, SimpleIcaError> {
+    ChannelMetadata::parse(final_version(msg))
+}
+
+fn final_version(msg: &IbcChannelConnectMsg) -> &str {
+    // on OpenAck the counterparty tells us what it picked,
+    // on OpenConfirm the channel already holds the final version
+    msg.counterparty_version().unwrap_or(&msg.channel().version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        APP_ORDER, IBC_APP_VERSION, IBC_APP_VERSION_PROTO, IBC_APP_VERSION_V2,
+        IBC_APP_VERSION_V2_PROTO, IBC_APP_VERSION_V3, IBC_APP_VERSION_V3_PROTO,
+    };
+    use cosmwasm_std::testing::{
+        mock_ibc_channel_connect_ack, mock_ibc_channel_open_init, mock_ibc_channel_open_try,
+    };
+
+    const TEST_VERSIONS: &[ProtocolVersion] = &[
+        ProtocolVersion {
+            version: "simple-ica-v3",
+            encoding: Encoding::Json,
+            features: &["foo", "bar"],
+        },
+        ProtocolVersion {
+            version: "simple-ica-v2",
+            encoding: Encoding::Json,
+            features: &["foo"],
+        },
+        ProtocolVersion {
+            version: IBC_APP_VERSION,
+            encoding: Encoding::Json,
+            features: &[],
+        },
+    ];
+
+    #[test]
+    fn features_are_frozen() {
+        // do not change these lists, add a new version instead
+        let frozen = ["custom-msgs"];
+        assert_eq!(V2_FEATURES, frozen);
+        for version in [IBC_APP_VERSION_V2, IBC_APP_VERSION_V2_PROTO] {
+            assert_eq!(lookup_version(version).unwrap().features, frozen);
+        }
+
+        let frozen = [
+            "custom-msgs",
+            "best-effort-dispatch",
+            "structured-errors",
+            "return-address",
+            "sub-accounts",
+            "migrate-account",
+            "outflow-limits",
+            "msg-policies",
+        ];
+        assert_eq!(V3_FEATURES, frozen);
+        for version in [IBC_APP_VERSION_V3, IBC_APP_VERSION_V3_PROTO] {
+            assert_eq!(lookup_version(version).unwrap().features, frozen);
+        }
+    }
+
+    #[test]
+    fn negotiate_picks_highest_common_version() {
+        let picked = negotiate_from(TEST_VERSIONS, "simple-ica-v1,simple-ica-v2").unwrap();
+        assert_eq!(picked.version, "simple-ica-v2");
+        assert!(picked.supports("foo"));
+        assert!(!picked.supports("bar"));
+
+        // unknown entries and whitespace are ignored
+        let picked = negotiate_from(TEST_VERSIONS, "simple-ica-v9, simple-ica-v1").unwrap();
+        assert_eq!(picked.version, IBC_APP_VERSION);
+
+        let err = negotiate_from(TEST_VERSIONS, "reflect,ics20-1").unwrap_err();
+        assert_eq!(
+            err,
+            SimpleIcaError::NoCommonVersion("reflect,ics20-1".to_string())
+        );
+    }
+
+    #[test]
+    fn proposal_only_contains_supported_versions() {
+        let all = [
+            IBC_APP_VERSION_V3_PROTO,
+            IBC_APP_VERSION_V3,
+            IBC_APP_VERSION_V2_PROTO,
+            IBC_APP_VERSION_V2,
+            IBC_APP_VERSION_PROTO,
+            IBC_APP_VERSION,
+        ]
+        .join(",");
+        assert_eq!(propose_versions("").unwrap(), all);
+        assert_eq!(
+            propose_versions("simple-ica-v9,simple-ica-v1").unwrap(),
+            IBC_APP_VERSION
+        );
+        propose_versions("reflect").unwrap_err();
+    }
+
+    #[test]
+    fn negotiate_prefers_proto_encoding() {
+        let picked = negotiate_version(&propose_versions("").unwrap()).unwrap();
+        assert_eq!(picked.version, IBC_APP_VERSION_V3_PROTO);
+        assert_eq!(picked.encoding, Encoding::Proto3);
+        assert!(picked.supports(FEATURE_CUSTOM_MSGS));
+        let picked = negotiate_version(IBC_APP_VERSION).unwrap();
+        assert_eq!(picked.encoding, Encoding::Json);
+        assert!(!picked.supports(FEATURE_CUSTOM_MSGS));
+    }
+
+    #[test]
+    fn metadata_is_echoed_and_connected() {
+        let metadata = r#"{"version":"simple-ica-v2","encoding":"json","max_accounts":2}"#;
+        let open = mock_ibc_channel_open_try("channel-1", APP_ORDER, metadata);
+        assert_eq!(channel_open_version(&open).unwrap(), metadata);
+        let open = mock_ibc_channel_open_init("channel-1", APP_ORDER, metadata);
+        assert_eq!(channel_open_version(&open).unwrap(), metadata);
+
+        let connect = mock_ibc_channel_connect_ack("channel-1", APP_ORDER, metadata);
+        assert_eq!(
+            connected_version(&connect).unwrap().version,
+            IBC_APP_VERSION_V2
+        );
+        let metadata = connected_metadata(&connect).unwrap().unwrap();
+        assert_eq!(metadata.max_accounts, Some(2));
+
+        // plain versions have no metadata
+        let connect = mock_ibc_channel_connect_ack("channel-1", APP_ORDER, IBC_APP_VERSION);
+        assert_eq!(connected_metadata(&connect).unwrap(), None);
+
+        let bad = r#"{"version":"simple-ica-v2","encoding":"proto3"}"#;
+        let open = mock_ibc_channel_open_try("channel-1", APP_ORDER, bad);
+        channel_open_version(&open).unwrap_err();
+    }
+
+    #[test]
+    fn lookup_requires_exact_version() {
+        lookup_version(IBC_APP_VERSION).unwrap();
+        // a proposal list is not a negotiated version
+        let list = format!("{},{}", IBC_APP_VERSION, IBC_APP_VERSION);
+        lookup_version(&list).unwrap_err();
+    }
+}